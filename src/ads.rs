@@ -0,0 +1,37 @@
+use crate::player_match;
+use crate::utils::MediaInfo;
+
+// Titles or artists of ads in Spotify free and web radio streams
+const AD_NAMES: [&str; 5] = ["advertisement", "advert", "ad", "commercial", "sponsored"];
+
+// Ads are usually 15 or 30 seconds long
+const MAX_AD_DURATION: u64 = 30;
+
+// Ad detection heuristics, plus user patterns matched against title and artist like allowlist
+// entries ("Sponsor*", "re:^Reklama")
+pub fn is_advertisement(media_info: &MediaInfo, patterns: &[String]) -> bool {
+    // Spotify ads have "https://open.spotify.com/ad/<id>" url instead of track link
+    if media_info.url.contains("open.spotify.com/ad/") {
+        return true;
+    }
+
+    if AD_NAMES.iter().any(|name| {
+        media_info.title.eq_ignore_ascii_case(name) || media_info.artist.eq_ignore_ascii_case(name)
+    }) {
+        return true;
+    }
+
+    // Short tracks with only the advertiser name as title, missing values are "Unknown ..." for MPRIS
+    let is_unknown = |value: &str, placeholder: &str| {
+        value.is_empty() || value.eq_ignore_ascii_case(placeholder)
+    };
+    if is_unknown(&media_info.artist, "unknown artist")
+        && is_unknown(&media_info.album, "unknown album")
+        && media_info.duration > 0
+        && media_info.duration <= MAX_AD_DURATION
+    {
+        return true;
+    }
+
+    player_match::is_listed(patterns, &[&media_info.title, &media_info.artist])
+}
@@ -0,0 +1,271 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::SystemTime;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::cache_db;
+use crate::cover_cache;
+use crate::debug_log;
+use crate::utils::get_app_cache_dir;
+
+fn now() -> u64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+// Cache key of album, players tag the same album with different case, whitespace or unicode form
+pub fn normalize_key(album_id: &str) -> String {
+    album_id
+        .nfc()
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// Rename entries saved with keys from older versions to normalized ones, returns number of renamed
+// entries. If both keys exist the normalized one is kept.
+fn migrate_db_keys(db: &mut PickleDb) -> usize {
+    let mut renamed = 0;
+    for key in db.get_all() {
+        let new_key = normalize_key(&key);
+        if new_key == key {
+            continue;
+        }
+        if !db.exists(&new_key) {
+            if let Some(value) = db.get::<serde_json::Value>(&key) {
+                let _ = db.set(&new_key, &value);
+            }
+        }
+        let _ = db.rem(&key);
+        renamed += 1;
+    }
+    renamed
+}
+
+// One time migration of album cache, access times, album info and cover images to normalized keys.
+// Files are rewritten only if something changed.
+pub fn migrate_keys(cache_dir: &Path, debug_log: bool) {
+    let load = |name: &str| {
+        PickleDb::load(
+            cache_dir.join(name),
+            PickleDbDumpPolicy::DumpUponRequest,
+            SerializationMethod::Json,
+        )
+        .ok()
+    };
+
+    if let Some(mut album_cache) = load("album_cache.db") {
+        for key in album_cache.get_all() {
+            cover_cache::migrate_cover(cache_dir, &key);
+        }
+        let renamed = migrate_db_keys(&mut album_cache);
+        if renamed > 0 {
+            debug_log!(debug_log, "[cache] normalized {} album keys", renamed);
+            if album_cache.dump().is_err() {
+                println!("[cache] error, unable to write cache file.");
+            }
+        }
+    }
+
+    // Pinned albums keep their access time, otherwise the latest one is used
+    if let Some(mut access_db) = load("album_cache_access.db") {
+        let mut renamed = 0;
+        for key in access_db.get_all() {
+            let new_key = normalize_key(&key);
+            if new_key == key {
+                continue;
+            }
+            let last_used = access_db.get::<u64>(&key).unwrap_or(0);
+            let new_last_used = access_db.get::<u64>(&new_key).unwrap_or(0);
+            let _ = access_db.set(&new_key, &last_used.max(new_last_used));
+            let _ = access_db.rem(&key);
+            renamed += 1;
+        }
+        if renamed > 0 && access_db.dump().is_err() {
+            println!("[cache] error, unable to write cache access file.");
+        }
+    }
+
+    if let Some(mut album_info_db) = load("album_info.db") {
+        if migrate_db_keys(&mut album_info_db) > 0 && album_info_db.dump().is_err() {
+            println!("[cache] error, unable to write album info file.");
+        }
+    }
+}
+
+// Last use time of every cached album is stored in separate file, so the old
+// album_cache.db format stays the same
+pub fn load_access_db(path: &Path, auto_dump: bool) -> PickleDb {
+    let policy = || match auto_dump {
+        true => PickleDbDumpPolicy::AutoDump,
+        false => PickleDbDumpPolicy::DumpUponRequest,
+    };
+    cache_db::load(path, policy)
+}
+
+// Cache kept only in memory, used when it can't be stored on disk
+pub fn memory_db() -> PickleDb {
+    PickleDb::new("", PickleDbDumpPolicy::NeverDump, SerializationMethod::Json)
+}
+
+// Access time of covers set manually with "cover set", they are never removed
+const PINNED: u64 = u64::MAX;
+
+fn is_pinned(access_db: &PickleDb, album_id: &str) -> bool {
+    access_db.get::<u64>(album_id) == Some(PINNED)
+}
+
+// Mark album as recently used
+pub fn touch(access_db: &mut PickleDb, album_id: &str) {
+    if is_pinned(access_db, album_id) {
+        return;
+    }
+    if access_db.set(album_id, &now()).is_err() {
+        println!("[cache] error, unable to write to cache access file.");
+    }
+}
+
+// Remove least recently used albums until there are at most max_entries left.
+// Albums without saved access time are treated as the oldest ones.
+pub fn evict(
+    album_cache: &mut PickleDb,
+    access_db: &mut PickleDb,
+    max_entries: usize,
+    debug_log: bool,
+) -> usize {
+    let total = album_cache.total_keys();
+    if total <= max_entries {
+        return 0;
+    }
+
+    let mut entries: Vec<(String, u64)> = album_cache
+        .get_all()
+        .into_iter()
+        .map(|key| {
+            let last_used = access_db.get::<u64>(&key).unwrap_or(0);
+            (key, last_used)
+        })
+        .filter(|(_, last_used)| *last_used != PINNED)
+        .collect();
+
+    // Oldest first
+    entries.sort_by_key(|(_, last_used)| *last_used);
+
+    let mut removed = 0;
+    let pinned = total - entries.len();
+    let to_remove = total.saturating_sub(max_entries.max(pinned));
+    for (key, _) in entries.into_iter().take(to_remove) {
+        if let Ok(true) = album_cache.rem(&key) {
+            debug_log!(debug_log, "[cache] removed least recently used: {}", key);
+            removed += 1;
+        }
+        let _ = access_db.rem(&key);
+    }
+
+    removed
+}
+
+// Startup compaction: drop broken entries, access times of albums no longer in
+// cache and apply the entries limit. Both files are rewritten only once.
+pub fn compact(db_path: &Path, access_path: &Path, max_entries: usize, debug_log: bool) {
+    let mut album_cache = match PickleDb::load(
+        db_path,
+        PickleDbDumpPolicy::DumpUponRequest,
+        SerializationMethod::Json,
+    ) {
+        Ok(db) => db,
+        Err(_) => return,
+    };
+    let mut access_db = load_access_db(access_path, false);
+
+    let mut invalid = 0;
+    for key in album_cache.get_all() {
+        let valid = match album_cache.get::<String>(&key) {
+            Some(url) => url.len() > 5,
+            None => false,
+        };
+        if !valid {
+            let _ = album_cache.rem(&key);
+            invalid += 1;
+        }
+    }
+
+    for key in access_db.get_all() {
+        if !album_cache.exists(&key) {
+            let _ = access_db.rem(&key);
+        }
+    }
+
+    let evicted = evict(&mut album_cache, &mut access_db, max_entries, debug_log);
+
+    debug_log!(
+        debug_log,
+        "[cache] compaction: {} entries left, removed {} invalid and {} least recently used",
+        album_cache.total_keys(),
+        invalid,
+        evicted
+    );
+
+    if album_cache.dump().is_err() || access_db.dump().is_err() {
+        println!("[cache] error, unable to write compacted cache file.");
+    }
+}
+
+fn open_cache_files() -> (PickleDb, PickleDb) {
+    let cache_dir = match get_app_cache_dir() {
+        Some(path) => path,
+        None => {
+            println!("\x1b[31mWARNING: Failed to determine user cache directory.\x1b[0m");
+            process::exit(1);
+        }
+    };
+    if let Err(err) = fs::create_dir_all(&cache_dir) {
+        println!("Could not create cache directory: {}", err);
+        process::exit(1);
+    }
+
+    let db_path = cache_dir.join("album_cache.db");
+    let album_cache = cache_db::load(&db_path, || PickleDbDumpPolicy::AutoDump);
+    let access_db = load_access_db(&cache_dir.join("album_cache_access.db"), true);
+    (album_cache, access_db)
+}
+
+// Used by "cover set" subcommand
+pub fn set_command(album_id: &str, url: &str) {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        println!("\x1b[31mCover must be a link to image on the internet.\x1b[0m");
+        process::exit(1);
+    }
+
+    let album_id = &normalize_key(album_id);
+    let (mut album_cache, mut access_db) = open_cache_files();
+    if album_cache.set(album_id, &url).is_err() || access_db.set(album_id, &PINNED).is_err() {
+        println!("[cache] error, unable to write to cache file.");
+        process::exit(1);
+    }
+
+    println!("Cover for \"{}\" set to: {}", album_id, url);
+    println!("Restart the app to see the change if the album is playing now.");
+    process::exit(0);
+}
+
+// Used by "cover unset" subcommand
+pub fn unset_command(album_id: &str) {
+    let album_id = &normalize_key(album_id);
+    let (mut album_cache, mut access_db) = open_cache_files();
+    let removed = matches!(album_cache.rem(album_id), Ok(true));
+    let _ = access_db.rem(album_id);
+
+    if removed {
+        println!("Removed cover of \"{}\" from cache.", album_id);
+    } else {
+        println!("\"{}\" is not in cache.", album_id);
+    }
+    process::exit(0);
+}
@@ -0,0 +1,104 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cache_db;
+use crate::debug_log;
+use crate::utils;
+
+// Min MusicBrainz search score to trust that the release is the same album
+const MIN_SCORE: u64 = 90;
+
+// Album details from MusicBrainz used in text templates, empty when unknown
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AlbumInfo {
+    pub year: String,
+    pub tracks: String,
+    // Artist bio from TheAudioDB, not saved in cache
+    #[serde(skip)]
+    pub bio: String,
+}
+
+pub fn load_db(path: &Path, cache_enabled: bool) -> PickleDb {
+    let policy = || match cache_enabled {
+        true => PickleDbDumpPolicy::AutoDump,
+        false => PickleDbDumpPolicy::NeverDump,
+    };
+    cache_db::load(path, policy)
+}
+
+fn fetch(artist: &str, album: &str) -> Result<AlbumInfo, Box<dyn std::error::Error>> {
+    let request_url = format!(
+        "https://musicbrainz.org/ws/2/release/?query=artist:\"{}\"%20AND%20release:\"{}\"&fmt=json&limit=1",
+        url_escape::encode_component(artist),
+        url_escape::encode_component(album),
+    );
+    let data = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(request_url)
+        .header(
+            USER_AGENT,
+            format!(
+                "music-discord-rpc/{} (patryk.kurdziel@protonmail.com)",
+                utils::VERSION
+            ),
+        )
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?;
+
+    let release = &data["releases"][0];
+    if release["score"].as_u64().unwrap_or(0) < MIN_SCORE {
+        return Ok(AlbumInfo::default());
+    }
+
+    Ok(AlbumInfo {
+        year: release["date"]
+            .as_str()
+            .unwrap_or_default()
+            .chars()
+            .take(4)
+            .collect(),
+        tracks: release["track-count"]
+            .as_u64()
+            .map(|count| count.to_string())
+            .unwrap_or_default(),
+        bio: String::new(),
+    })
+}
+
+// Year and number of tracks of album, saved in cache also when not found
+pub fn get_album_info(
+    db: &mut PickleDb,
+    album_id: &str,
+    artist: &str,
+    album: &str,
+    debug_log: bool,
+) -> AlbumInfo {
+    if album.is_empty() || album.eq("Unknown Album") {
+        return AlbumInfo::default();
+    }
+
+    if let Some(info) = db.get::<AlbumInfo>(album_id) {
+        return info;
+    }
+
+    match fetch(artist, album) {
+        Ok(info) => {
+            debug_log!(debug_log, "[musicbrainz] album info: {:?}", info);
+            if db.set(album_id, &info).is_err() {
+                println!("[cache] error, unable to write to album info file.");
+            }
+            info
+        }
+        // Network errors are not cached, so it will be checked again next time
+        Err(err) => {
+            debug_log!(debug_log, "[musicbrainz] could not get album info: {}", err);
+            AlbumInfo::default()
+        }
+    }
+}
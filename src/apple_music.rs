@@ -0,0 +1,110 @@
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::time::Duration;
+
+use crate::player_match;
+use crate::utils::{self, MediaInfo};
+
+// Players of the Apple Music catalog, matched like allowlist entries
+const PLAYERS: [&str; 2] = ["com.apple.Music", "Cider"];
+
+// Found tracks are kept in memory, the list is cleared when full
+pub const MAX_CACHED_TRACKS: usize = 1000;
+
+// iTunes Search API returns 100x100 artwork, bigger sizes are available under the same link
+pub const ARTWORK_SIZE: &str = "1000x1000bb";
+
+// Track in Apple Music catalog
+#[derive(Debug, Clone, Default)]
+pub struct AppleMusicTrack {
+    pub url: String,
+    pub artwork_url: String,
+}
+
+pub fn is_apple_music<S: AsRef<str>>(names: &[S]) -> bool {
+    PLAYERS
+        .iter()
+        .any(|player| player_match::matches(player, names))
+}
+
+// Persistent ID of the current Music.app track, the same for every play of the track
+#[cfg(target_os = "macos")]
+fn get_persistent_id() -> Option<String> {
+    let output = std::process::Command::new("osascript")
+        .args([
+            "-e",
+            r#"tell application "Music" to get persistent ID of current track"#,
+        ])
+        .output()
+        .ok()?;
+    let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match output.status.success() && !id.is_empty() {
+        true => Some(id),
+        false => None,
+    }
+}
+
+// Key under which the catalog track is remembered
+pub fn track_key(media_info: &MediaInfo) -> String {
+    #[cfg(target_os = "macos")]
+    if media_info.player_id == "com.apple.Music" {
+        if let Some(id) = get_persistent_id() {
+            return id;
+        }
+    }
+    format!(
+        "{} - {} - {}",
+        media_info.artist, media_info.title, media_info.album
+    )
+}
+
+// Search the catalog with iTunes Search API. The result from the same album is preferred,
+// empty if the track was not found.
+pub fn search(
+    artist: &str,
+    title: &str,
+    album: &str,
+) -> Result<AppleMusicTrack, Box<dyn std::error::Error>> {
+    let request_url = format!(
+        "https://itunes.apple.com/search?media=music&entity=song&limit=10&term={}",
+        url_escape::encode_component(&format!("{} {}", artist, title))
+    );
+    let data = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(request_url)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?;
+
+    let results = match data["results"].as_array() {
+        Some(results) if !results.is_empty() => results,
+        _ => return Ok(AppleMusicTrack::default()),
+    };
+    let same = |value: &serde_json::Value, text: &str| {
+        value.as_str().unwrap_or_default().to_lowercase() == text.to_lowercase()
+    };
+    let result = results
+        .iter()
+        .find(|result| {
+            same(&result["artistName"], artist) && same(&result["collectionName"], album)
+        })
+        .or_else(|| {
+            results
+                .iter()
+                .find(|result| same(&result["artistName"], artist))
+        })
+        .unwrap_or(&results[0]);
+
+    Ok(AppleMusicTrack {
+        url: result["trackViewUrl"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        artwork_url: result["artworkUrl100"]
+            .as_str()
+            .unwrap_or_default()
+            .replace("100x100bb", ARTWORK_SIZE),
+    })
+}
@@ -0,0 +1,271 @@
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::debug_log;
+use crate::utils::{self, ArtData, MediaInfo};
+
+// Track is forgotten when the extension stops sending updates (e.g. browser was closed)
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+// Artwork sent as data URL makes the body big, but there is no reason to accept more
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+// Track sent by the extension
+#[derive(Debug, Clone)]
+struct BrowserTrack {
+    site: String,
+    media_info: MediaInfo,
+    received: Instant,
+}
+
+// Localhost endpoint for a browser extension that sends metadata of web players with poor or
+// missing MPRIS metadata (Bandcamp, SoundCloud, Yandex Music...)
+#[derive(Debug, Clone)]
+pub struct BrowserExtension {
+    pub address: String,
+    track: Arc<Mutex<Option<BrowserTrack>>>,
+}
+
+// Message schema, everything except title is optional:
+// {"site": "bandcamp.com", "title": "...", "artist": "...", "album": "...", "album_artist": "...",
+//  "url": "https://...", "artwork": "https://... or data:image/jpeg;base64,...",
+//  "duration": 215.3, "position": 42.0, "playing": true}
+fn parse_track(body: &[u8]) -> Result<BrowserTrack, String> {
+    let message: Value = serde_json::from_slice(body).map_err(|err| err.to_string())?;
+    let text = |key: &str| message[key].as_str().unwrap_or_default().trim().to_string();
+
+    let title = text("title");
+    if title.is_empty() {
+        return Err(String::from("missing title"));
+    }
+    let mut artist = text("artist");
+    if artist.is_empty() {
+        artist = "Unknown Artist".to_string();
+    }
+    let mut album_artist = text("album_artist");
+    if album_artist.is_empty() {
+        album_artist = artist.clone();
+    }
+    let mut album = text("album");
+    if album.is_empty() {
+        album = "Unknown Album".to_string();
+    }
+
+    let artwork = text("artwork");
+    let (art_url, art_data) = match artwork.starts_with("data:") {
+        true => (
+            String::new(),
+            ArtData(utils::decode_data_url(&artwork).ok_or("invalid artwork data URL")?),
+        ),
+        false => (artwork, ArtData::default()),
+    };
+    let duration = message["duration"].as_f64().unwrap_or(0.0).max(0.0) as u64;
+
+    Ok(BrowserTrack {
+        site: text("site"),
+        media_info: MediaInfo {
+            title,
+            artist,
+            album_artist,
+            album,
+            is_playing: message["playing"].as_bool().unwrap_or(true),
+            is_stopped: false,
+            duration,
+            position: message["position"].as_f64().unwrap_or(0.0).max(0.0) as u64,
+            is_track_position: duration > 0,
+            art_url,
+            art_data,
+            url: text("url"),
+            track_id: String::new(),
+            rating: None,
+            #[cfg(target_os = "macos")]
+            player_id: "Browser extension".to_string(),
+        },
+        received: Instant::now(),
+    })
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn handle_client(
+    stream: TcpStream,
+    track: &Mutex<Option<BrowserTrack>>,
+    debug_log: bool,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut content_length = 0;
+    let mut is_json = false;
+    let mut from_website = false;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_lowercase().as_str() {
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            "content-type" => is_json = value.starts_with("application/json"),
+            // Requests made by websites have their origin, extensions use their own scheme
+            "origin" => from_website = value.starts_with("http"),
+            _ => {}
+        }
+    }
+
+    if path != "/now-playing" {
+        return respond(&stream, "404 Not Found", "Not Found\n");
+    }
+    // JSON content type can't be sent by websites without CORS preflight, which is never allowed
+    if from_website {
+        return respond(&stream, "403 Forbidden", "Forbidden\n");
+    }
+
+    match method.as_str() {
+        "POST" => {
+            if !is_json {
+                return respond(
+                    &stream,
+                    "415 Unsupported Media Type",
+                    "Expected application/json\n",
+                );
+            }
+            if content_length > MAX_BODY_SIZE {
+                return respond(&stream, "413 Payload Too Large", "Payload Too Large\n");
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            match parse_track(&body) {
+                Ok(new_track) => {
+                    debug_log!(
+                        debug_log,
+                        "[browser extension] {}: {} - {} (playing: {})",
+                        new_track.site,
+                        new_track.media_info.artist,
+                        new_track.media_info.title,
+                        new_track.media_info.is_playing
+                    );
+                    *track.lock().unwrap() = Some(new_track);
+                    respond(&stream, "204 No Content", "")
+                }
+                Err(err) => respond(&stream, "400 Bad Request", &format!("{}\n", err)),
+            }
+        }
+        // Sent when the tab was closed or playback has ended
+        "DELETE" => {
+            debug_log!(debug_log, "[browser extension] track cleared");
+            *track.lock().unwrap() = None;
+            respond(&stream, "204 No Content", "")
+        }
+        _ => respond(&stream, "405 Method Not Allowed", "Method Not Allowed\n"),
+    }
+}
+
+impl BrowserExtension {
+    // Start the endpoint in background thread
+    pub fn new(address: &str, debug_log: bool) -> BrowserExtension {
+        let track: Arc<Mutex<Option<BrowserTrack>>> = Arc::new(Mutex::new(None));
+
+        match TcpListener::bind(address) {
+            Ok(listener) => {
+                println!(
+                    "Browser extension endpoint available at: http://{}/now-playing",
+                    address
+                );
+                let track = Arc::clone(&track);
+                thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        match stream {
+                            Ok(stream) => {
+                                if let Err(err) = handle_client(stream, &track, debug_log) {
+                                    debug_log!(
+                                        debug_log,
+                                        "[browser extension] request error: {}",
+                                        err
+                                    );
+                                }
+                            }
+                            Err(err) => {
+                                debug_log!(
+                                    debug_log,
+                                    "[browser extension] connection error: {}",
+                                    err
+                                );
+                            }
+                        }
+                    }
+                });
+            }
+            Err(err) => println!(
+                "\x1b[31mWARNING: Could not start browser extension endpoint on {}: {}\x1b[0m",
+                address, err
+            ),
+        }
+
+        BrowserExtension {
+            address: address.to_string(),
+            track,
+        }
+    }
+
+    // Last track sent by the extension, if it's still up to date
+    fn get_track(&self) -> Option<BrowserTrack> {
+        let mut track = self.track.lock().unwrap();
+        if track
+            .as_ref()
+            .is_some_and(|track| track.received.elapsed() > STALE_AFTER)
+        {
+            *track = None;
+        }
+        track.clone()
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.get_track().is_some()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.get_track()
+            .is_some_and(|track| track.media_info.is_playing)
+    }
+
+    pub fn get_currently_playing(
+        &self,
+        debug_log: bool,
+    ) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+        let track = match self.get_track() {
+            Some(track) => track,
+            None => return Err("No track sent by the browser extension.".into()),
+        };
+        debug_log!(debug_log, "Browser extension track from: {}", track.site);
+
+        // Position is sent only on changes, so it's counted from the time of the last message
+        let mut media_info = track.media_info;
+        if media_info.is_playing {
+            media_info.position = (media_info.position + track.received.elapsed().as_secs())
+                .min(media_info.duration.max(media_info.position));
+        }
+        Ok(media_info)
+    }
+}
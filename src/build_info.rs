@@ -0,0 +1,109 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::discord_socket;
+use crate::utils;
+
+// Optional parts chosen at build time: (cargo feature, included, what it provides)
+const FEATURES: [(&str, bool, &str); 2] = [
+    (
+        "scripting",
+        cfg!(feature = "scripting"),
+        "user script customizing the activity (script_file)",
+    ),
+    (
+        "local-tags",
+        cfg!(feature = "local-tags"),
+        "year, {label} and {catalog} from tags of local files",
+    ),
+];
+
+// Executable found in PATH, e.g. pw-dump used for PipeWire streams
+fn find_program(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn program_status(name: &str) -> String {
+    match find_program(name) {
+        Some(path) => format!("found ({})", path.display()),
+        None => String::from("not found"),
+    }
+}
+
+// What the app can use on this system, checked when printed
+fn capabilities() -> Vec<(&'static str, String)> {
+    let mut capabilities = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    capabilities.push((
+        "D-Bus session bus (MPRIS players)",
+        match dbus::blocking::Connection::new_session() {
+            Ok(_) => String::from("available"),
+            Err(err) => format!("not available: {}", err),
+        },
+    ));
+    #[cfg(target_os = "linux")]
+    capabilities.push(("pw-dump (PipeWire streams)", program_status("pw-dump")));
+    #[cfg(target_os = "macos")]
+    capabilities.push(("media-control (players)", program_status("media-control")));
+
+    let sockets = discord_socket::find_sockets();
+    capabilities.push((
+        "Discord IPC sockets",
+        match sockets.is_empty() {
+            true => String::from("none found (Discord is not running?)"),
+            false => sockets
+                .iter()
+                .map(|socket| socket.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+        },
+    ));
+
+    capabilities.push((
+        "Built-in Last.fm API key",
+        match option_env!("LASTFM_API_KEY").is_some_and(|key| !key.is_empty()) {
+            true => String::from("yes"),
+            false => String::from(
+                "no, set lastfm_api_key for album covers from Last.fm (MusicBrainz, iTunes and Deezer are used without it)",
+            ),
+        },
+    ));
+
+    let dir_status = |dir: Option<PathBuf>| match dir {
+        Some(dir) => dir.display().to_string(),
+        None => String::from("not available ($HOME is not set)"),
+    };
+    capabilities.push(("Config directory", dir_status(utils::get_app_config_dir())));
+    capabilities.push(("Cache directory", dir_status(utils::get_app_cache_dir())));
+    capabilities
+}
+
+// --version, with --verbose also compiled features and what is available at runtime, so it's
+// clear why e.g. a configured script is not used in a packaged build
+pub fn print_version(verbose: bool) {
+    println!("music-discord-rpc {}", utils::VERSION);
+    if !verbose {
+        return;
+    }
+
+    println!("\nFeatures:");
+    for (name, included, description) in FEATURES {
+        println!(
+            "  {} {}: {}",
+            match included {
+                true => "+",
+                false => "-",
+            },
+            name,
+            description
+        );
+    }
+
+    println!("\nRuntime:");
+    for (name, status) in capabilities() {
+        println!("  {}: {}", name, status);
+    }
+}
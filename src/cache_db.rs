@@ -0,0 +1,98 @@
+use pickledb::error::ErrorType;
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Corrupted file is renamed to e.g. "album_cache.db.corrupted-1700000000"
+fn backup_path(path: &Path) -> PathBuf {
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupted-{}", time));
+    path.with_file_name(name)
+}
+
+// Load cache file or create a new one if it doesn't exist. Corrupted file (e.g. left empty after
+// a crash or power loss) is moved aside with a warning instead of being silently overwritten.
+// If the file can't be read or moved, the cache is kept only in memory.
+pub fn load(path: &Path, policy: impl Fn() -> PickleDbDumpPolicy) -> PickleDb {
+    let err = match PickleDb::load(path, policy(), SerializationMethod::Json) {
+        Ok(db) => return db,
+        Err(err) => err,
+    };
+    if !path.exists() || matches!(policy(), PickleDbDumpPolicy::NeverDump) {
+        return PickleDb::new(path, policy(), SerializationMethod::Json);
+    }
+
+    if let ErrorType::Io = err.get_type() {
+        println!(
+            "\x1b[33mWARNING: Could not read cache file {}: {}. It's kept only in memory until exit.\x1b[0m",
+            path.display(),
+            err
+        );
+        return PickleDb::new(
+            path,
+            PickleDbDumpPolicy::NeverDump,
+            SerializationMethod::Json,
+        );
+    }
+
+    let backup = backup_path(path);
+    match fs::rename(path, &backup) {
+        Ok(_) => {
+            println!(
+                "\x1b[33mWARNING: Cache file {} is corrupted ({}). It was moved to {} and a new one was created.\x1b[0m",
+                path.display(),
+                err,
+                backup.display()
+            );
+            PickleDb::new(path, policy(), SerializationMethod::Json)
+        }
+        Err(rename_err) => {
+            println!(
+                "\x1b[33mWARNING: Cache file {} is corrupted ({}) and could not be moved: {}. It's kept only in memory until exit.\x1b[0m",
+                path.display(),
+                err,
+                rename_err
+            );
+            PickleDb::new(
+                path,
+                PickleDbDumpPolicy::NeverDump,
+                SerializationMethod::Json,
+            )
+        }
+    }
+}
+
+// Flush cache files changed since the last call to disk ("fsync" cache_dump_policy). Files are
+// replaced by rename on every write, so the directory is flushed too.
+pub fn sync_changed(cache_dir: &Path, since: &mut SystemTime) {
+    let started = SystemTime::now();
+    let Ok(entries) = fs::read_dir(cache_dir) else {
+        return;
+    };
+    let mut changed = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("db") {
+            continue;
+        }
+        let is_changed = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified >= *since);
+        if is_changed {
+            if let Err(err) = File::open(&path).and_then(|file| file.sync_all()) {
+                println!("[cache] error, unable to flush {}: {}", path.display(), err);
+            }
+            changed = true;
+        }
+    }
+    if changed {
+        let _ = File::open(cache_dir).and_then(|dir| dir.sync_all());
+    }
+    *since = started;
+}
@@ -0,0 +1,282 @@
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont};
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::cover_cache;
+use crate::debug_log;
+use crate::utils::{self, MediaInfo};
+
+const WIDTH: u32 = 600;
+const HEIGHT: u32 = 200;
+const PADDING: u32 = 16;
+const COVER_SIZE: u32 = HEIGHT - 2 * PADDING;
+
+const BACKGROUND: Rgba<u8> = Rgba([30, 31, 34, 255]);
+const TEXT: Rgba<u8> = Rgba([242, 243, 245, 255]);
+const TEXT_DIM: Rgba<u8> = Rgba([181, 186, 193, 255]);
+const BAR: Rgba<u8> = Rgba([78, 80, 88, 255]);
+const BAR_FILL: Rgba<u8> = Rgba([88, 101, 242, 255]);
+
+// Progress bar is redrawn while playing at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+// Used when now_playing_card_font is not set
+const DEFAULT_FONTS: [&str; 6] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/google-noto/NotoSans-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+];
+
+// Track displayed on the card, position is the one at the time of the last metadata change
+struct Track {
+    title: String,
+    artist: String,
+    album: String,
+    duration: u64,
+    position: u64,
+    is_playing: bool,
+    is_track_position: bool,
+    since: Instant,
+}
+
+// "Now playing" card image (cover, title, artist and progress) written to the cache directory,
+// e.g. for an OBS image source or screenshots
+pub struct NowPlayingCard {
+    path: PathBuf,
+    cache_dir: PathBuf,
+    use_cover_cache: bool,
+    font: FontVec,
+    track: Option<Track>,
+    album_id: String,
+    cover: Option<RgbaImage>,
+    last_render: Instant,
+}
+
+fn fill_rect(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for py in y..(y + height).min(image.height()) {
+        for px in x..(x + width).min(image.width()) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+fn load_cover(data: &[u8]) -> Option<RgbaImage> {
+    let cover = image::load_from_memory(data).ok()?;
+    Some(
+        cover
+            .resize_to_fill(COVER_SIZE, COVER_SIZE, FilterType::Triangle)
+            .to_rgba8(),
+    )
+}
+
+impl NowPlayingCard {
+    pub fn new(
+        cache_dir: &Path,
+        use_cover_cache: bool,
+        font_path: Option<&str>,
+    ) -> Result<NowPlayingCard, Box<dyn std::error::Error>> {
+        let font_path = match font_path {
+            Some(path) => PathBuf::from(path),
+            None => DEFAULT_FONTS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .ok_or("no font found, set now_playing_card_font")?,
+        };
+        let font = FontVec::try_from_vec(fs::read(&font_path)?)
+            .map_err(|_| format!("invalid font file: {}", font_path.display()))?;
+        fs::create_dir_all(cache_dir)?;
+        Ok(NowPlayingCard {
+            path: cache_dir.join("now_playing.png"),
+            cache_dir: cache_dir.to_path_buf(),
+            use_cover_cache,
+            font,
+            track: None,
+            album_id: String::new(),
+            cover: None,
+            last_render: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Called on metadata change. Cover is taken from image provided by the player (also local
+    // file), or from covers stored in cache.
+    pub fn set_track(&mut self, media_info: &MediaInfo, album_id: &str, debug_log: bool) {
+        self.track = Some(Track {
+            title: media_info.title.clone(),
+            artist: media_info.artist.clone(),
+            album: media_info.album.clone(),
+            duration: media_info.duration,
+            position: media_info.position,
+            is_playing: media_info.is_playing,
+            is_track_position: media_info.is_track_position,
+            since: Instant::now(),
+        });
+        self.cover = if !media_info.art_data.0.is_empty() {
+            load_cover(&media_info.art_data.0)
+        } else if let Some(path) = media_info.art_url.strip_prefix("file://") {
+            let path = url_escape::decode(path).to_string();
+            fs::read(path).ok().and_then(|data| load_cover(&data))
+        } else {
+            None
+        };
+        self.album_id = album_id.to_string();
+        self.render(debug_log);
+    }
+
+    // Redraws the progress bar while the same track plays
+    pub fn update_progress(&mut self, debug_log: bool) {
+        if self.last_render.elapsed() < PROGRESS_INTERVAL
+            || !self.track.as_ref().is_some_and(|track| track.is_playing)
+        {
+            return;
+        }
+        self.render(debug_log);
+    }
+
+    // Removes the card when nothing is playing
+    pub fn clear(&mut self) {
+        if self.track.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    // Text starting at position (left, baseline)
+    fn draw_text(
+        &self,
+        image: &mut RgbaImage,
+        text: &str,
+        (x, baseline): (f32, f32),
+        size: f32,
+        max_width: f32,
+        color: Rgba<u8>,
+    ) {
+        let font = self.font.as_scaled(PxScale::from(size));
+
+        // Shorten with ellipsis if it doesn't fit
+        let width =
+            |text: &str| -> f32 { text.chars().map(|c| font.h_advance(font.glyph_id(c))).sum() };
+        let mut text = text.to_string();
+        if width(&text) > max_width {
+            while !text.is_empty() && width(&format!("{}…", text)) > max_width {
+                text.pop();
+            }
+            text = format!("{}…", text.trim_end());
+        }
+
+        let mut caret = x;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            previous = Some(id);
+            let glyph = id.with_scale_and_position(size, ab_glyph::point(caret, baseline));
+            caret += font.h_advance(id);
+
+            let Some(outline) = self.font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= image.width() as i32 || py >= image.height() as i32 {
+                    return;
+                }
+                let pixel = image.get_pixel_mut(px as u32, py as u32);
+                for (channel, value) in pixel.0.iter_mut().zip(color.0).take(3) {
+                    *channel = (*channel as f32 * (1.0 - coverage) + value as f32 * coverage) as u8;
+                }
+            });
+        }
+    }
+
+    fn render(&mut self, debug_log: bool) {
+        let Some(track) = &self.track else {
+            return;
+        };
+        self.last_render = Instant::now();
+
+        // Covers are stored in cache by network worker, possibly after the track has changed
+        if self.cover.is_none() && self.use_cover_cache {
+            if let Some(path) = cover_cache::get_cover_path(&self.cache_dir, &self.album_id) {
+                self.cover = fs::read(path).ok().and_then(|data| load_cover(&data));
+            }
+        }
+
+        let mut card = RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND);
+        match &self.cover {
+            Some(cover) => imageops::overlay(&mut card, cover, PADDING as i64, PADDING as i64),
+            None => fill_rect(&mut card, PADDING, PADDING, COVER_SIZE, COVER_SIZE, BAR),
+        }
+
+        let x = (COVER_SIZE + 2 * PADDING) as f32;
+        let max_width = WIDTH as f32 - x - PADDING as f32;
+        self.draw_text(&mut card, &track.title, (x, 48.0), 28.0, max_width, TEXT);
+        self.draw_text(
+            &mut card,
+            &track.artist,
+            (x, 82.0),
+            20.0,
+            max_width,
+            TEXT_DIM,
+        );
+        self.draw_text(
+            &mut card,
+            &track.album,
+            (x, 110.0),
+            16.0,
+            max_width,
+            TEXT_DIM,
+        );
+
+        if track.is_track_position && track.duration > 0 {
+            let position = match track.is_playing {
+                true => track.position + track.since.elapsed().as_secs(),
+                false => track.position,
+            }
+            .min(track.duration);
+            let bar_width = max_width as u32;
+            let bar_y = HEIGHT - PADDING - 34;
+            fill_rect(&mut card, x as u32, bar_y, bar_width, 6, BAR);
+            let filled = (bar_width as u64 * position / track.duration) as u32;
+            fill_rect(&mut card, x as u32, bar_y, filled, 6, BAR_FILL);
+
+            let time = format!(
+                "{} / {}",
+                utils::format_time(position),
+                utils::format_time(track.duration)
+            );
+            self.draw_text(
+                &mut card,
+                &time,
+                (x, (HEIGHT - PADDING) as f32 - 6.0),
+                16.0,
+                max_width,
+                TEXT_DIM,
+            );
+        }
+
+        // Written to temporary file first, so readers never get a half written image
+        let temp_path = self.path.with_extension("png.tmp");
+        let result = card
+            .save_with_format(&temp_path, ImageFormat::Png)
+            .map_err(|err| err.to_string())
+            .and_then(|_| fs::rename(&temp_path, &self.path).map_err(|err| err.to_string()));
+        match result {
+            Ok(_) => debug_log!(debug_log, "Now playing card saved: {}", self.path.display()),
+            Err(err) => println!("Could not save now playing card: {}", err),
+        }
+    }
+}
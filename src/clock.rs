@@ -0,0 +1,41 @@
+use std::time::{Duration, Instant, SystemTime};
+
+// Smaller differences are delays of the loop itself (e.g. slow D-Bus or network)
+const JUMP_THRESHOLD: Duration = Duration::from_secs(10);
+
+// Detects jumps of the system clock. Monotonic time stops during suspend and is not changed by
+// NTP or the user, so when the wall clock moves differently the published start time (unix
+// time) is no longer right. Time zone changes don't matter, unix time is the same in all of them.
+pub struct ClockWatch {
+    instant: Instant,
+    wall: SystemTime,
+}
+
+impl ClockWatch {
+    pub fn new() -> ClockWatch {
+        ClockWatch {
+            instant: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    // Seconds the wall clock moved more than monotonic time since the last check, e.g. time
+    // spent in suspend. Negative when the clock was set back.
+    pub fn jumped(&mut self) -> Option<i64> {
+        let monotonic = self.instant.elapsed();
+        let (wall, is_forward) = match SystemTime::now().duration_since(self.wall) {
+            Ok(elapsed) => (elapsed, true),
+            Err(err) => (err.duration(), false),
+        };
+        *self = ClockWatch::new();
+
+        let jump = match is_forward {
+            true => wall.as_secs() as i64 - monotonic.as_secs() as i64,
+            false => -(wall.as_secs() as i64) - monotonic.as_secs() as i64,
+        };
+        match jump.unsigned_abs() >= JUMP_THRESHOLD.as_secs() {
+            true => Some(jump),
+            false => None,
+        }
+    }
+}
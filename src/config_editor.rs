@@ -2,11 +2,14 @@ use inquire::{list_option::ListOption, validator::Validation, Confirm, MultiSele
 use serde::{Deserialize, Serialize};
 use std::{fs, path::PathBuf, process};
 
-use crate::settings::create_config_file;
+use crate::config_migration::CONFIG_VERSION;
+use crate::settings::{create_config_file, CONFIG_TEMPLATE};
 use crate::utils;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Config {
+    #[serde(default)]
+    version: u64,
     interval: u64,
     button: Vec<String>,
     lastfm_name: String,
@@ -21,14 +24,18 @@ struct Config {
     disable_musicbrainz_cover: bool,
 }
 
-pub fn setup() {
-    let (config_exists, config_file) = create_config_file(false);
+pub fn setup(custom_config_file: Option<PathBuf>) {
+    let (config_exists, config_file) = match custom_config_file {
+        Some(path) => (true, path),
+        None => create_config_file(false),
+    };
     if !config_exists {
         process::exit(1);
     }
 
     // Load existing config or set to default values
     let mut config = load_config(&config_file).unwrap_or(Config {
+        version: CONFIG_VERSION,
         interval: 10,
         button: vec![],
         lastfm_name: String::new(),
@@ -56,6 +63,7 @@ pub fn setup() {
     }
 
     // Save config
+    config.version = CONFIG_VERSION;
     let yaml = serde_yaml::to_string(&config).unwrap_or_else(|err| {
         eprintln!("Error serializing config: {}", err);
         process::exit(1);
@@ -145,8 +153,14 @@ fn config_form(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
     let options = vec![
         "yt".to_string(),
         "lastfm".to_string(),
+        "lastfmTrack".to_string(),
         "listenbrainz".to_string(),
+        "musicbrainz".to_string(),
         "mprisUrl".to_string(),
+        "appleMusic".to_string(),
+        "tidal".to_string(),
+        "deezer".to_string(),
+        "qobuz".to_string(),
         "shamelessAd".to_string(),
     ];
 
@@ -260,3 +274,353 @@ fn prompt_strings(previous: &Vec<String>) -> Result<Vec<String>, Box<dyn std::er
 
     Ok(items)
 }
+
+// Replace first commented out example of given option (with its list items) in config template
+fn set_template_option(template: &str, key: &str, value: &str) -> String {
+    let prefix = format!("# {}:", key);
+    let mut lines: Vec<&str> = template.lines().collect();
+    if let Some(index) = lines.iter().position(|line| line.starts_with(&prefix)) {
+        let mut end = index + 1;
+        while end < lines.len() && lines[end].starts_with("#   - ") {
+            end += 1;
+        }
+        lines.splice(index..end, [value]);
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn yaml_string(value: &str) -> String {
+    // JSON string is also valid YAML string
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn yaml_list(key: &str, values: &[String]) -> String {
+    let mut text = format!("{}:", key);
+    for value in values {
+        text.push_str(&format!("\n  - {}", yaml_string(value)));
+    }
+    text
+}
+
+// Add allowlist entry to the text of config file, other options and comments are kept. Existing
+// allowlist is written again with the new entry, otherwise the commented out example is replaced.
+fn add_allowlist_entry(
+    config_text: &str,
+    name: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let yaml: serde_yaml::Value = serde_yaml::from_str(config_text)?;
+    let mut entries = match yaml.get("allowlist") {
+        Some(serde_yaml::Value::Sequence(entries)) => entries.clone(),
+        Some(serde_yaml::Value::Null) | None => Vec::new(),
+        Some(_) => return Err("allowlist in config file is not a list".into()),
+    };
+
+    let lines: Vec<&str> = config_text.lines().collect();
+    let Some(index) = lines.iter().position(|line| line.starts_with("allowlist:")) else {
+        if config_text
+            .lines()
+            .any(|line| line.starts_with("# allowlist:"))
+        {
+            return Ok(set_template_option(
+                config_text,
+                "allowlist",
+                &yaml_list("allowlist", &[name.to_string()]),
+            ));
+        }
+        return Ok(format!(
+            "{}\n{}\n",
+            config_text.trim_end(),
+            yaml_list("allowlist", &[name.to_string()])
+        ));
+    };
+
+    // Items of the list are indented or start with "-"
+    let mut end = index + 1;
+    while end < lines.len() && (lines[end].starts_with(' ') || lines[end].starts_with('-')) {
+        end += 1;
+    }
+    entries.push(serde_yaml::Value::String(name.to_string()));
+    // Entries with priority are written by serde_yaml, plain names in the style of the template
+    let names: Option<Vec<String>> = entries
+        .iter()
+        .map(|entry| entry.as_str().map(String::from))
+        .collect();
+    let allowlist_text = match names {
+        Some(names) => yaml_list("allowlist", &names),
+        None => {
+            let mut allowlist = serde_yaml::Mapping::new();
+            allowlist.insert("allowlist".into(), serde_yaml::Value::Sequence(entries));
+            serde_yaml::to_string(&allowlist)?
+        }
+    };
+
+    let mut lines: Vec<&str> = lines;
+    lines.splice(index..end, allowlist_text.lines());
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+// Player chosen with --list-players --save, added to the allowlist of user config file (or the
+// one from --config), which is created if it doesn't exist
+pub fn save_allowlist_player(custom_config_file: Option<PathBuf>, name: &str) {
+    let (config_exists, config_file) = match custom_config_file {
+        Some(path) => (true, path),
+        None => create_config_file(false),
+    };
+    if !config_exists {
+        process::exit(1);
+    }
+
+    let config_text = fs::read_to_string(&config_file).unwrap_or_else(|err| {
+        eprintln!("Error reading config file: {}", err);
+        process::exit(1);
+    });
+    let yaml: serde_yaml::Value = serde_yaml::from_str(&config_text).unwrap_or_default();
+    let is_saved = yaml
+        .get("allowlist")
+        .and_then(|allowlist| allowlist.as_sequence())
+        .is_some_and(|entries| {
+            entries.iter().any(|entry| {
+                entry
+                    .as_str()
+                    .or(entry.get("name").and_then(|name| name.as_str()))
+                    == Some(name)
+            })
+        });
+    if is_saved {
+        println!(
+            "\n\"{}\" is already on the allowlist in {}",
+            name,
+            config_file.display()
+        );
+        return;
+    }
+
+    let config_text = add_allowlist_entry(&config_text, name).unwrap_or_else(|err| {
+        eprintln!("Error updating config file: {}", err);
+        process::exit(1);
+    });
+    if let Err(err) = fs::write(&config_file, config_text) {
+        eprintln!("Error writing config file: {}", err);
+        process::exit(1);
+    }
+    println!(
+        "\nAdded \"{}\" to the allowlist in {}",
+        name,
+        config_file.display()
+    );
+    #[cfg(target_os = "linux")]
+    println!("Restart the app to apply it: music-discord-rpc restart");
+    #[cfg(target_os = "macos")]
+    println!("Restart the app to apply it: brew services restart music-discord-rpc");
+}
+
+#[cfg(target_os = "linux")]
+fn detect_players() -> Vec<String> {
+    let finder = match mpris::PlayerFinder::new() {
+        Ok(finder) => finder,
+        Err(_) => return Vec::new(),
+    };
+    match finder.find_all() {
+        Ok(players) => players
+            .iter()
+            .filter(|player| player.bus_name() != "org.mpris.MediaPlayer2.playerctld")
+            .map(|player| player.identity().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn detect_players() -> Vec<String> {
+    match utils::get_currently_playing() {
+        Ok(player) => vec![player.player_id],
+        Err(_) => Vec::new(),
+    }
+}
+
+// First run wizard, writes commented config file and optionally enables autostart
+pub fn wizard(custom_config_file: Option<PathBuf>) {
+    let config_file = match custom_config_file {
+        Some(path) => path,
+        None => {
+            let (config_exists, config_file) = create_config_file(false);
+            if !config_exists {
+                process::exit(1);
+            }
+            config_file
+        }
+    };
+
+    println!("\nmusic-discord-rpc setup");
+    println!("───────────────────────");
+    println!("This wizard will create a new config file with your answers.");
+    println!("Nothing is saved until you confirm changes.");
+    println!("Use arrows to navigate, Ctrl+C to exit.");
+
+    let config_text = match wizard_form() {
+        Ok(config_text) => config_text,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    #[cfg(target_os = "linux")]
+    let autostart = Select::new(
+        "Start in the background and enable autostart?",
+        vec!["systemd", "XDG Autostart", "no"],
+    )
+    .with_help_message("Use XDG Autostart on distributions without systemd")
+    .prompt()
+    .unwrap_or("no");
+
+    println!("\nWarning: Your previous config will be saved as config.yaml.bak");
+    let save = Confirm::new("Save this configuration?")
+        .with_default(true)
+        .prompt();
+
+    match save {
+        Ok(true) => {
+            if config_file.exists() {
+                let _ = fs::copy(&config_file, config_file.with_extension("yaml.bak"));
+            }
+            if let Err(err) = fs::write(&config_file, config_text) {
+                eprintln!("Error writing config file: {}", err);
+                process::exit(1);
+            }
+            println!("Config saved: {}", config_file.display());
+        }
+        _ => {
+            println!("Discarded.");
+            process::exit(0);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    match autostart {
+        "systemd" => utils::enable_service(),
+        "XDG Autostart" => utils::add_xdg_autostart(),
+        _ => println!("You can enable autostart later with: music-discord-rpc enable"),
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        println!("To start the app in the background and enable autostart run:");
+        println!("brew services start music-discord-rpc");
+    }
+
+    process::exit(0);
+}
+
+fn wizard_form() -> Result<String, Box<dyn std::error::Error>> {
+    let mut config_text = CONFIG_TEMPLATE.to_string();
+
+    // Last.fm
+    println!("\n───────── last.fm ─────────");
+    let lastfm_name = Text::new("Last.fm username:")
+        .with_help_message("Used for the profile button and avatar, leave empty to skip")
+        .prompt()?;
+    if !lastfm_name.trim().is_empty() {
+        config_text = set_template_option(
+            &config_text,
+            "lastfm_name",
+            &format!("lastfm_name: {}", yaml_string(lastfm_name.trim())),
+        );
+    }
+
+    let own_api_key = crate::LASTFM_API_KEY.is_empty()
+        || Confirm::new("Use your own Last.fm API key?")
+            .with_help_message("Not needed, the precompiled binary already contains one")
+            .with_default(false)
+            .prompt()?;
+    if own_api_key {
+        let api_key = Text::new("Last.fm API key:")
+            .with_help_message("Get it from: https://www.last.fm/api, leave empty to skip")
+            .prompt()?;
+        if !api_key.trim().is_empty() {
+            config_text = set_template_option(
+                &config_text,
+                "lastfm_api_key",
+                &format!("lastfm_api_key: {}", yaml_string(api_key.trim())),
+            );
+        }
+    }
+
+    // Allowlist
+    println!("\n──────── allowlist ────────");
+    println!("Only use the status from the selected music players.");
+    println!("Select nothing to use any player.");
+    let mut allowlist = Vec::new();
+    let players = detect_players();
+    if players.is_empty() {
+        println!("No running players detected, you can enter their names manually.");
+    } else {
+        allowlist = MultiSelect::new("Detected players:", players).prompt()?;
+    }
+    if Confirm::new("Add other players manually?")
+        .with_help_message("Use -l or --list-players to get player exact name")
+        .with_default(false)
+        .prompt()?
+    {
+        allowlist.extend(prompt_strings(&allowlist)?);
+    }
+    if !allowlist.is_empty() {
+        config_text = set_template_option(
+            &config_text,
+            "allowlist",
+            &yaml_list("allowlist", &allowlist),
+        );
+    }
+
+    // Buttons
+    println!("\n───────── buttons ─────────");
+    let options = vec![
+        "yt".to_string(),
+        "lastfm".to_string(),
+        "lastfmTrack".to_string(),
+        "listenbrainz".to_string(),
+        "musicbrainz".to_string(),
+        "mprisUrl".to_string(),
+        "appleMusic".to_string(),
+        "tidal".to_string(),
+        "deezer".to_string(),
+        "qobuz".to_string(),
+        "shamelessAd".to_string(),
+    ];
+    let buttons = MultiSelect::new("Activity buttons (max 2):", options)
+        .with_validator(|choices: &[ListOption<&String>]| {
+            Ok(if choices.len() <= 2 {
+                Validation::Valid
+            } else {
+                Validation::Invalid("Max 2 options".into())
+            })
+        })
+        .prompt()?;
+    if !buttons.is_empty() {
+        config_text = set_template_option(&config_text, "button", &yaml_list("button", &buttons));
+    }
+
+    if buttons.iter().any(|v| v == "lastfm") && lastfm_name.trim().is_empty() {
+        println!("\x1b[33mLast.fm button requires Last.fm username, it will be hidden.\x1b[0m");
+    }
+
+    if buttons.iter().any(|v| v == "listenbrainz") {
+        let listenbrainz_name = Text::new("ListenBrainz username:").prompt()?;
+        if !listenbrainz_name.trim().is_empty() {
+            config_text = set_template_option(
+                &config_text,
+                "listenbrainz_name",
+                &format!(
+                    "listenbrainz_name: {}",
+                    yaml_string(listenbrainz_name.trim())
+                ),
+            );
+        }
+    }
+
+    Ok(config_text)
+}
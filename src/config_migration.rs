@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+
+// Bump when config keys or structure change and add migration below
+pub const CONFIG_VERSION: u64 = 1;
+
+// Renamed keys: (config version introducing the change, old key, new key)
+const RENAMED_KEYS: &[(u64, &str, &str)] = &[];
+
+// Options that still work but will be removed: (key, what to use instead)
+const DEPRECATED_KEYS: &[(&str, &str)] = &[];
+
+fn get_version(yaml: &serde_yaml::Value) -> u64 {
+    yaml.get("version").and_then(|v| v.as_u64()).unwrap_or(0)
+}
+
+// Text based migration, so user comments and formatting are preserved
+fn migrate_text(config_text: &str, from_version: u64) -> String {
+    let mut lines: Vec<String> = config_text
+        .lines()
+        .map(|line| {
+            for (version, old_key, new_key) in RENAMED_KEYS {
+                if *version <= from_version {
+                    continue;
+                }
+                // Also rename commented out examples
+                for prefix in ["", "# "] {
+                    let old = format!("{}{}:", prefix, old_key);
+                    if let Some(rest) = line.strip_prefix(&old) {
+                        return format!("{}{}:{}", prefix, new_key, rest);
+                    }
+                }
+            }
+            line.to_string()
+        })
+        .collect();
+
+    // Update version field or insert it after the header comments
+    let version_line = format!("version: {}", CONFIG_VERSION);
+    match lines.iter().position(|line| line.starts_with("version:")) {
+        Some(index) => lines[index] = version_line,
+        None => {
+            let index = lines
+                .iter()
+                .position(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .unwrap_or(lines.len());
+            lines.insert(index, String::new());
+            lines.insert(index, version_line);
+            lines.insert(
+                index,
+                String::from("# Config file format version, used to upgrade old config files. Do not change it."),
+            );
+        }
+    }
+
+    let mut migrated = lines.join("\n");
+    migrated.push('\n');
+    migrated
+}
+
+// Upgrade old config keys if needed (saving backup of the old file) and warn about deprecated
+// options. Returns the config text that should be parsed.
+pub fn migrate(config_file: &Path, config_text: String) -> String {
+    let yaml: serde_yaml::Value = match serde_yaml::from_str(&config_text) {
+        Ok(yaml) => yaml,
+        Err(_) => return config_text, // Parsing error will be reported later
+    };
+    if yaml.is_null() {
+        return config_text;
+    }
+
+    let version = get_version(&yaml);
+    if version > CONFIG_VERSION {
+        println!(
+            "\x1b[33mWARNING: Config file {} is from a newer version of music-discord-rpc (config version {}), some options may not work.\x1b[0m",
+            config_file.display(),
+            version
+        );
+    }
+
+    for (key, replacement) in DEPRECATED_KEYS {
+        if yaml.get(*key).is_some() {
+            println!(
+                "\x1b[33mWARNING: Config option \"{}\" is deprecated, use {} instead.\x1b[0m",
+                key, replacement
+            );
+        }
+    }
+
+    if version >= CONFIG_VERSION {
+        return config_text;
+    }
+
+    let migrated = migrate_text(&config_text, version);
+
+    let backup_file = config_file.with_extension(format!("yaml.v{}.bak", version));
+    if let Err(err) = fs::write(&backup_file, &config_text) {
+        println!(
+            "[config] Could not save config backup, the old config file was left unchanged: {}",
+            err
+        );
+        return migrated;
+    }
+    match fs::write(config_file, &migrated) {
+        Ok(_) => println!(
+            "[config] Upgraded config file to version {}, backup of the old file: {}",
+            CONFIG_VERSION,
+            backup_file.display()
+        ),
+        Err(err) => println!("[config] Could not save upgraded config file: {}", err),
+    }
+
+    migrated
+}
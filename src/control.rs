@@ -0,0 +1,408 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::debug_log;
+use crate::media_state;
+use crate::metrics;
+use crate::sources::Fallback;
+#[cfg(target_os = "linux")]
+use crate::utils;
+
+// Playback commands proxied to the currently displayed player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Next,
+    Previous,
+}
+
+impl PlayerCommand {
+    pub fn parse(command: &str) -> Option<PlayerCommand> {
+        match command {
+            "play" => Some(PlayerCommand::Play),
+            "pause" => Some(PlayerCommand::Pause),
+            "play-pause" => Some(PlayerCommand::PlayPause),
+            "next" => Some(PlayerCommand::Next),
+            "previous" => Some(PlayerCommand::Previous),
+            _ => None,
+        }
+    }
+}
+
+// Player whose status is currently displayed
+#[derive(Debug, Clone)]
+pub enum ControlTarget {
+    #[cfg(target_os = "linux")]
+    Mpris(String), // D-Bus bus name
+    #[cfg(target_os = "macos")]
+    MediaControl,
+    Fallback(Fallback),
+}
+
+static TARGET: Mutex<Option<ControlTarget>> = Mutex::new(None);
+
+// Set by "refresh-cover" command or SIGHUP, taken by the main loop
+static REFRESH_COVER: AtomicBool = AtomicBool::new(false);
+
+// Connections of "tail" clients, events are written to all of them
+static SUBSCRIBERS: Mutex<Vec<UnixStream>> = Mutex::new(Vec::new());
+
+// Called from main loop every time the player is selected
+pub fn set_target(target: Option<ControlTarget>) {
+    // Refresh requested while nothing was displayed should not apply to the next player
+    if target.is_none() {
+        REFRESH_COVER.store(false, Ordering::SeqCst);
+    }
+    if let Ok(mut current) = TARGET.lock() {
+        *current = target;
+    }
+}
+
+// Cover of the current album should be searched again, ignoring the cache
+pub fn take_refresh_cover() -> bool {
+    REFRESH_COVER.swap(false, Ordering::SeqCst)
+}
+
+extern "C" fn on_sighup(_signal: libc::c_int) {
+    REFRESH_COVER.store(true, Ordering::SeqCst);
+}
+
+// SIGHUP (e.g. "systemctl --user reload") works like "refresh-cover" command
+pub fn handle_sighup() {
+    let handler = on_sighup as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGHUP, handler as libc::sighandler_t);
+    }
+}
+
+fn get_target() -> Option<ControlTarget> {
+    TARGET.lock().ok().and_then(|target| target.clone())
+}
+
+// Event shown by "tail" subcommand, e.g. ("cover", "Artist - Album: <url> (from Last.fm)")
+pub fn publish_event(kind: &str, message: &str) {
+    let mut subscribers = match SUBSCRIBERS.lock() {
+        Ok(subscribers) => subscribers,
+        Err(_) => return,
+    };
+    if subscribers.is_empty() {
+        return;
+    }
+    let line = format!("{}\t{}\n", kind, message.replace('\n', " "));
+    // Closed connections are removed
+    subscribers.retain_mut(|stream| stream.write_all(line.as_bytes()).is_ok());
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+// Without XDG_RUNTIME_DIR the socket is in the shared temp directory, inside a directory only
+// the user can access
+fn get_private_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(_) => None,
+        None => Some(env::temp_dir().join(format!("music-discord-rpc-{}", current_uid()))),
+    }
+}
+
+pub fn get_socket_path() -> PathBuf {
+    match get_private_dir() {
+        Some(private_dir) => private_dir,
+        None => PathBuf::from(env::var_os("XDG_RUNTIME_DIR").unwrap_or_default()),
+    }
+    .join("music-discord-rpc.sock")
+}
+
+// Directory created by another user or readable by others could be used to replace the socket
+fn create_private_dir(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+        _ => {}
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+        return Err("the directory is not private to the current user".into());
+    }
+    Ok(())
+}
+
+// Socket is used only if it's owned by the current user, otherwise another user could pretend
+// to be the running instance
+fn connect(socket_path: &Path) -> io::Result<UnixStream> {
+    if fs::metadata(socket_path)?.uid() != current_uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the socket is owned by another user",
+        ));
+    }
+    UnixStream::connect(socket_path)
+}
+
+#[cfg(target_os = "linux")]
+fn send_mpris_command(
+    dbus_address: Option<&str>,
+    bus_name: &str,
+    command: PlayerCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let player = utils::new_player_finder(dbus_address)?
+        .find_all()?
+        .into_iter()
+        .find(|player| player.bus_name() == bus_name)
+        .ok_or("player is no longer available")?;
+
+    match command {
+        PlayerCommand::Play => player.play()?,
+        PlayerCommand::Pause => player.pause()?,
+        PlayerCommand::PlayPause => player.play_pause()?,
+        PlayerCommand::Next => player.next()?,
+        PlayerCommand::Previous => player.previous()?,
+    }
+    Ok(())
+}
+
+// ==> brew install media-control
+#[cfg(target_os = "macos")]
+fn send_media_control_command(command: PlayerCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let arg = match command {
+        PlayerCommand::Play => "play",
+        PlayerCommand::Pause => "pause",
+        PlayerCommand::PlayPause => "toggle-play-pause",
+        PlayerCommand::Next => "next-track",
+        PlayerCommand::Previous => "previous-track",
+    };
+    let status = process::Command::new("media-control").arg(arg).status()?;
+    if !status.success() {
+        return Err(format!("media-control exited with {}", status).into());
+    }
+    Ok(())
+}
+
+#[cfg_attr(target_os = "macos", allow(unused_variables))]
+fn send_player_command(
+    dbus_address: Option<&str>,
+    command: PlayerCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match get_target() {
+        #[cfg(target_os = "linux")]
+        Some(ControlTarget::Mpris(bus_name)) => {
+            send_mpris_command(dbus_address, &bus_name, command)
+        }
+        #[cfg(target_os = "macos")]
+        Some(ControlTarget::MediaControl) => send_media_control_command(command),
+        Some(ControlTarget::Fallback(fallback)) => fallback.send_command(command),
+        None => Err("no player is selected".into()),
+    }
+}
+
+// One command per connection, reply is "ok" or "error: <message>"
+fn handle_client(
+    stream: UnixStream,
+    dbus_address: Option<&str>,
+    debug_log: bool,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let command = line.trim();
+    debug_log!(debug_log, "[control] received command: {}", command);
+
+    // Connection stays open and receives events
+    if command == "tail" {
+        // Slow client should not block the main loop
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+        writeln!(&stream, "ok")?;
+        if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+            subscribers.push(stream);
+        }
+        return Ok(());
+    }
+
+    let reply = match PlayerCommand::parse(command) {
+        Some(player_command) => match send_player_command(dbus_address, player_command) {
+            Ok(_) => String::from("ok"),
+            Err(err) => format!("error: {}", err),
+        },
+        None => match command {
+            "player" => match get_target() {
+                Some(target) => format!("ok: {:?}", target),
+                None => String::from("error: no player is selected"),
+            },
+            "stats" => format!("ok: {}", metrics::snapshot()),
+            // "state" or "state <schema version>"
+            _ if command == "state" || command.starts_with("state ") => {
+                match media_state::negotiate(command.strip_prefix("state ").map(str::trim)) {
+                    Ok(_) => format!("ok: {}", media_state::to_json()),
+                    Err(err) => format!("error: {}", err),
+                }
+            }
+            "refresh-cover" => match get_target() {
+                Some(_) => {
+                    REFRESH_COVER.store(true, Ordering::SeqCst);
+                    String::from("ok")
+                }
+                None => String::from("error: no player is selected"),
+            },
+            _ => format!("error: unknown command: {}", command),
+        },
+    };
+
+    let mut stream = &stream;
+    writeln!(stream, "{}", reply)
+}
+
+// Listen for commands on unix socket in background thread
+pub fn start_server(dbus_address: Option<String>, debug_log: bool) {
+    let socket_path = get_socket_path();
+
+    if let Some(private_dir) = get_private_dir() {
+        if let Err(err) = create_private_dir(&private_dir) {
+            println!(
+                "\x1b[31mWARNING: Could not create control socket directory {}: {}\x1b[0m",
+                private_dir.display(),
+                err
+            );
+            return;
+        }
+    }
+
+    if socket_path.exists() {
+        // Another instance is already running
+        if connect(&socket_path).is_ok() {
+            println!(
+                "\x1b[33mWARNING: Control socket {} is already used by another instance.\x1b[0m",
+                socket_path.display()
+            );
+            return;
+        }
+        let _ = fs::remove_file(&socket_path);
+    }
+
+    let listener = match UnixListener::bind(&socket_path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!(
+                "\x1b[31mWARNING: Could not create control socket {}: {}\x1b[0m",
+                socket_path.display(),
+                err
+            );
+            return;
+        }
+    };
+    let _ = fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600));
+    debug_log!(
+        debug_log,
+        "Control socket listening on: {}",
+        socket_path.display()
+    );
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(err) = handle_client(stream, dbus_address.as_deref(), debug_log) {
+                        debug_log!(debug_log, "[control] request error: {}", err);
+                    }
+                }
+                Err(err) => {
+                    debug_log!(debug_log, "[control] connection error: {}", err);
+                }
+            }
+        }
+    });
+}
+
+fn connect_or_exit() -> UnixStream {
+    let socket_path = get_socket_path();
+    match connect(&socket_path) {
+        Ok(stream) => stream,
+        Err(err) => {
+            println!(
+                "\x1b[31mCould not connect to {}: {}\x1b[0m",
+                socket_path.display(),
+                err
+            );
+            println!("Make sure music-discord-rpc is running.");
+            process::exit(1);
+        }
+    }
+}
+
+// Reply of the running instance without "ok: ", None if it's not running or replied with error
+pub fn query(command: &str) -> Option<String> {
+    let mut stream = connect(&get_socket_path()).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    writeln!(stream, "{}", command).ok()?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply).ok()?;
+    reply.trim().strip_prefix("ok: ").map(str::to_string)
+}
+
+// Used by "ctl" subcommand
+pub fn send_command(command: &str) {
+    let mut stream = connect_or_exit();
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+    let mut reply = String::new();
+    let result =
+        writeln!(stream, "{}", command).and_then(|_| BufReader::new(&stream).read_line(&mut reply));
+    if let Err(err) = result {
+        println!("\x1b[31mCommunication error: {}\x1b[0m", err);
+        process::exit(1);
+    }
+
+    let reply = reply.trim();
+    println!("{}", reply);
+    process::exit(if reply.starts_with("ok") { 0 } else { 1 });
+}
+
+// Used by "tail" subcommand, prints events of the running instance until it exits
+pub fn tail() {
+    let mut stream = connect_or_exit();
+    if let Err(err) = writeln!(stream, "tail") {
+        println!("\x1b[31mCommunication error: {}\x1b[0m", err);
+        process::exit(1);
+    }
+
+    let mut lines = BufReader::new(&stream).lines();
+    match lines.next() {
+        Some(Ok(reply)) if reply == "ok" => {
+            println!("Waiting for events, press Ctrl+C to exit.")
+        }
+        Some(Ok(reply)) => {
+            println!("\x1b[31m{}\x1b[0m", reply);
+            process::exit(1);
+        }
+        _ => {
+            println!("\x1b[31mNo reply from music-discord-rpc.\x1b[0m");
+            process::exit(1);
+        }
+    }
+
+    for line in lines.map_while(Result::ok) {
+        let (kind, message) = line.split_once('\t').unwrap_or(("", &line));
+        let color = match kind {
+            "player" => "35",
+            "metadata" => "36",
+            "cover" => "33",
+            "activity" => "32",
+            _ => "34",
+        };
+        println!("\x1b[{};1m{:<8}\x1b[0m {}", color, kind, message);
+    }
+
+    println!("Connection closed, music-discord-rpc has stopped.");
+    process::exit(0);
+}
@@ -0,0 +1,217 @@
+use reqwest::blocking::Client;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+
+use crate::album_cache::normalize_key;
+use crate::debug_log;
+use crate::utils::get_app_cache_dir;
+
+// Stable hash (FNV-1a) used for cover file names, std hasher output may change between Rust versions
+fn hash_key(key: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn image_extension(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+        "png"
+    } else if data.starts_with(b"GIF8") {
+        "gif"
+    } else if data.len() > 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "webp"
+    } else {
+        "jpg"
+    }
+}
+
+pub fn covers_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("covers")
+}
+
+fn find_cover_file(cache_dir: &Path, name: &str) -> Option<PathBuf> {
+    ["jpg", "png", "gif", "webp"]
+        .iter()
+        .map(|ext| covers_dir(cache_dir).join(format!("{}.{}", name, ext)))
+        .find(|path| path.exists())
+}
+
+// Find already stored cover for this album (any extension)
+pub fn get_cover_path(cache_dir: &Path, album_id: &str) -> Option<PathBuf> {
+    find_cover_file(cache_dir, &hash_key(&normalize_key(album_id)))
+}
+
+// Rename cover stored by older versions under hash of not normalized album key
+pub fn migrate_cover(cache_dir: &Path, old_album_id: &str) {
+    let name = hash_key(&normalize_key(old_album_id));
+    let old_name = hash_key(old_album_id);
+    if name == old_name || find_cover_file(cache_dir, &name).is_some() {
+        return;
+    }
+    if let Some(path) = find_cover_file(cache_dir, &old_name) {
+        let mut new_path = covers_dir(cache_dir).join(name);
+        if let Some(ext) = path.extension() {
+            new_path.set_extension(ext);
+        }
+        let _ = fs::rename(path, new_path);
+    }
+}
+
+// Remove stored cover of the album, so the image of newly found cover is saved
+pub fn remove_cover(cache_dir: &Path, album_id: &str) {
+    if let Some(path) = get_cover_path(cache_dir, album_id) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+// Save cover image bytes, returns path to the stored file
+pub fn store_cover_data(
+    cache_dir: &Path,
+    album_id: &str,
+    data: &[u8],
+    max_size_mb: u64,
+    debug_log: bool,
+) -> Option<PathBuf> {
+    if let Some(path) = get_cover_path(cache_dir, album_id) {
+        touch(&path);
+        return Some(path);
+    }
+
+    if let Err(err) = fs::create_dir_all(covers_dir(cache_dir)) {
+        println!("[cache] Could not create covers directory: {}", err);
+        return None;
+    }
+
+    let path = covers_dir(cache_dir).join(format!(
+        "{}.{}",
+        hash_key(&normalize_key(album_id)),
+        image_extension(data)
+    ));
+    match fs::write(&path, data) {
+        Ok(_) => {
+            debug_log!(
+                debug_log,
+                "[cache] saved cover image for: {} ({})",
+                album_id,
+                path.display()
+            );
+            prune(cache_dir, max_size_mb, debug_log);
+            Some(path)
+        }
+        Err(err) => {
+            println!("[cache] error, unable to write cover image: {}", err);
+            None
+        }
+    }
+}
+
+// Download cover image from url and save it, skipped if already stored
+pub fn store_cover(
+    cache_dir: &Path,
+    album_id: &str,
+    url: &str,
+    max_size_mb: u64,
+    debug_log: bool,
+) -> Option<PathBuf> {
+    if let Some(path) = get_cover_path(cache_dir, album_id) {
+        touch(&path);
+        return Some(path);
+    }
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .ok()?;
+    let data = match client
+        .get(url)
+        .send()
+        .and_then(|res| res.error_for_status())
+    {
+        Ok(res) => res.bytes().ok()?,
+        Err(err) => {
+            debug_log!(debug_log, "[cache] could not download cover image: {}", err);
+            return None;
+        }
+    };
+
+    store_cover_data(cache_dir, album_id, &data, max_size_mb, debug_log)
+}
+
+// Mark cover as recently used, modification time is used for LRU eviction
+fn touch(path: &Path) {
+    if let Ok(file) = File::options().append(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+// Remove least recently used covers until the total size fits in the limit.
+// Returns number of removed files and freed bytes.
+pub fn prune(cache_dir: &Path, max_size_mb: u64, debug_log: bool) -> (usize, u64) {
+    let entries = match fs::read_dir(covers_dir(cache_dir)) {
+        Ok(entries) => entries,
+        Err(_) => return (0, 0),
+    };
+
+    let mut files: Vec<(PathBuf, u64, SystemTime)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), modified))
+        })
+        .collect();
+
+    let max_size = max_size_mb * 1024 * 1024;
+    let mut total_size: u64 = files.iter().map(|(_, size, _)| size).sum();
+    if total_size <= max_size {
+        return (0, 0);
+    }
+
+    // Oldest first
+    files.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for (path, size, _) in files {
+        if total_size <= max_size {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            debug_log!(debug_log, "[cache] removed cover image: {}", path.display());
+            total_size -= size;
+            freed += size;
+            removed += 1;
+        }
+    }
+
+    (removed, freed)
+}
+
+// Used by "cache prune" subcommand
+pub fn prune_command(max_size_mb: u64) {
+    let cache_dir = match get_app_cache_dir() {
+        Some(path) => path,
+        None => {
+            println!("\x1b[31mWARNING: Failed to determine user cache directory.\x1b[0m");
+            process::exit(1);
+        }
+    };
+
+    let (removed, freed) = prune(&cache_dir, max_size_mb, false);
+    println!(
+        "Removed {} cover images ({:.1} MB) from: {}",
+        removed,
+        freed as f64 / (1024.0 * 1024.0),
+        covers_dir(&cache_dir).display()
+    );
+
+    process::exit(0);
+}
@@ -0,0 +1,239 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::album_cache::normalize_key;
+use crate::cache_db;
+use crate::debug_log;
+use crate::utils;
+
+const EXTENSIONS: [(&str, &str); 5] = [
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("webp", "image/webp"),
+    ("gif", "image/gif"),
+];
+
+// Images named "Album Artist - Album.jpg" in covers/ of the config directory
+pub fn covers_dir() -> Option<PathBuf> {
+    Some(utils::get_app_config_dir()?.join("covers"))
+}
+
+// File names can't contain "/", so "AC/DC - Back in Black" is saved as "AC_DC - Back in Black.jpg"
+fn file_key(album_key: &str) -> String {
+    normalize_key(&album_key.replace('/', "_"))
+}
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(
+        modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs(),
+    )
+}
+
+// Latest modified time of the folder and files inside it
+fn folder_version(dir: &Path) -> Option<SystemTime> {
+    let modified = fs::metadata(dir).ok()?.modified().ok()?;
+    Some(
+        fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+            .fold(modified, SystemTime::max),
+    )
+}
+
+// Checks if a file was added, replaced or removed in the folder since the last check
+pub struct FolderWatch {
+    dir: PathBuf,
+    version: Option<SystemTime>,
+}
+
+impl FolderWatch {
+    pub fn new(dir: PathBuf) -> FolderWatch {
+        let version = folder_version(&dir);
+        FolderWatch { dir, version }
+    }
+
+    pub fn changed(&mut self) -> bool {
+        let version = folder_version(&self.dir);
+        std::mem::replace(&mut self.version, version) != version
+    }
+}
+
+// Image uploaded with HTTP PUT to <upload_url>/<file name>, the server replies with link to it.
+// Also used for processed artwork, see square_cover.
+pub fn upload(
+    upload_url: &str,
+    file_name: &str,
+    content_type: &str,
+    data: Vec<u8>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let response = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?
+        .put(format!(
+            "{}/{}",
+            upload_url.trim_end_matches('/'),
+            url_escape::encode_component(file_name)
+        ))
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .header(CONTENT_TYPE, content_type)
+        .body(data)
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    let url = response.trim().to_string();
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("unexpected reply: {}", url).into());
+    }
+    Ok(url)
+}
+
+// Artwork image provided by the player (VLC, data: links, macOS), named by the hash of the image
+pub fn upload_artwork(
+    upload_url: &str,
+    hash: u64,
+    data: Vec<u8>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let format = image::guess_format(&data)?;
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    upload(
+        upload_url,
+        &format!("artwork-{:016x}.{}", hash, extension),
+        format.to_mime_type(),
+        data,
+    )
+}
+
+// Covers dropped into the folder, uploaded to cover_upload_url because Discord accepts only
+// links. Links are saved in cache and the file is uploaded again only when it's replaced.
+pub struct CoverFolder {
+    dir: PathBuf,
+    upload_url: String,
+    uploads: PickleDb, // Album key => (modified time of the file, link)
+    warned: HashSet<PathBuf>,
+    debug_log: bool,
+}
+
+impl CoverFolder {
+    pub fn new(
+        dir: PathBuf,
+        upload_url: String,
+        cache_dir: &Path,
+        cache_on_disk: bool,
+        debug_log: bool,
+    ) -> CoverFolder {
+        let policy = || match cache_on_disk {
+            true => PickleDbDumpPolicy::AutoDump,
+            false => PickleDbDumpPolicy::NeverDump,
+        };
+        CoverFolder {
+            dir,
+            upload_url,
+            uploads: cache_db::load(&cache_dir.join("cover_folder.db"), policy),
+            warned: HashSet::new(),
+            debug_log,
+        }
+    }
+
+    // Image of the first matching album key, names are compared like cache keys
+    fn find_file(&self, album_keys: &[&str]) -> Option<(String, PathBuf)> {
+        let files: Vec<(String, PathBuf)> = fs::read_dir(&self.dir)
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| {
+                        EXTENSIONS
+                            .iter()
+                            .any(|(known, _)| known.eq_ignore_ascii_case(ext))
+                    })
+            })
+            .filter_map(|path| {
+                let stem = path.file_stem()?.to_str()?;
+                Some((file_key(stem), path))
+            })
+            .collect();
+        album_keys.iter().find_map(|album_key| {
+            let key = file_key(album_key);
+            files
+                .iter()
+                .find(|(file_key, _)| *file_key == key)
+                .map(|(_, path)| (key, path.clone()))
+        })
+    }
+
+    fn upload(&self, path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("cover.jpg");
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let content_type = EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == extension)
+            .map(|(_, content_type)| *content_type)
+            .unwrap_or("image/jpeg");
+        upload(&self.upload_url, file_name, content_type, fs::read(path)?)
+    }
+
+    // Link to the dropped image of the album, None if there is no image or it can't be uploaded
+    pub fn get_cover(&mut self, album_keys: &[&str]) -> Option<String> {
+        let (key, path) = self.find_file(album_keys)?;
+        let modified = modified_secs(&path).unwrap_or(0);
+        if let Some((uploaded, url)) = self.uploads.get::<(u64, String)>(&key) {
+            if uploaded == modified {
+                return Some(url);
+            }
+        }
+
+        if self.upload_url.is_empty() {
+            if self.warned.insert(path.clone()) {
+                println!(
+                    "\x1b[33mWARNING: Found cover {}, but cover_upload_url is not set. Discord accepts only links to images.\x1b[0m",
+                    path.display()
+                );
+            }
+            return None;
+        }
+
+        match self.upload(&path) {
+            Ok(url) => {
+                debug_log!(
+                    self.debug_log,
+                    "[cover folder] uploaded {}: {}",
+                    path.display(),
+                    url
+                );
+                if self.uploads.set(&key, &(modified, &url)).is_err() {
+                    println!("[cache] error, unable to write to cover folder file.");
+                }
+                Some(url)
+            }
+            Err(err) => {
+                println!(
+                    "\x1b[31mCould not upload cover {}: {}\x1b[0m",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+}
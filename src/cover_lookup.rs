@@ -0,0 +1,744 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::album_cache;
+use crate::cache_db;
+use crate::control;
+use crate::cover_folder::CoverFolder;
+use crate::debug_log;
+use crate::metrics;
+use crate::store_covers;
+use crate::theaudiodb::{self, ArtistInfo};
+use crate::utils::{self, MediaInfo};
+
+// Max number of artist/album combinations tried for one album
+const MAX_VARIANTS: usize = 6;
+
+// Delay of MusicBrainz in parallel lookup, Last.fm usually answers first and is preferred
+const PARALLEL_STAGGER: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryVariant {
+    pub artist: String,
+    pub album: String,
+}
+
+// Everything needed to look up album cover
+pub struct CoverLookup<'a> {
+    pub cache_enabled: bool,
+    pub album_cache: &'a mut PickleDb,
+    pub artist_variants: &'a mut PickleDb, // Artist name variant that worked for given artist
+    pub lastfm_api_key: &'a str,
+    pub theaudiodb_api_key: &'a str,
+    pub artist_info: &'a mut HashMap<String, ArtistInfo>, // TheAudioDB artists found in this run
+    pub cover_overrides: &'a HashMap<String, String>,
+    pub cover_folder: Option<&'a mut CoverFolder>,
+    pub use_musicbrainz: bool,
+    pub use_stores: bool, // iTunes and Deezer, used after Last.fm and MusicBrainz
+    pub parallel: bool,   // Query Last.fm and MusicBrainz at the same time
+    pub is_cancelled: &'a dyn Fn() -> bool, // Track changed, the result won't be used
+    pub debug_log: bool,
+    pub source: &'static str, // Where the found cover comes from, empty if not found
+}
+
+// Remembered artist name variants are saved only when cache is enabled
+pub fn load_variants_db(path: &Path, cache_enabled: bool) -> PickleDb {
+    let policy = || match cache_enabled {
+        true => PickleDbDumpPolicy::AutoDump,
+        false => PickleDbDumpPolicy::NeverDump,
+    };
+    cache_db::load(path, policy)
+}
+
+fn collapse_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// "アーティスト (Artist)" => "アーティスト"
+fn strip_brackets(text: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' | '[' | '（' | '【' => depth += 1,
+            ')' | ']' | '）' | '】' => depth = (depth - 1).max(0),
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+    collapse_spaces(&result)
+}
+
+// "アーティスト (Artist)" => ["Artist"]
+fn bracket_contents(text: &str) -> Vec<String> {
+    let mut contents = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' | '[' | '（' | '【' => {
+                depth += 1;
+                current.clear();
+            }
+            ')' | ']' | '）' | '】' if depth > 0 => {
+                depth -= 1;
+                contents.push(collapse_spaces(&current));
+            }
+            _ if depth > 0 => current.push(c),
+            _ => {}
+        }
+    }
+    contents
+}
+
+// "Artist feat. Someone" => "Artist"
+fn strip_featured(artist: &str) -> String {
+    // ASCII lowercase keeps byte positions the same
+    let lowercase = artist.to_ascii_lowercase();
+    let cut = [" feat. ", " feat ", " ft. ", " featuring ", " with ", ", "]
+        .iter()
+        .filter_map(|separator| lowercase.find(separator))
+        .min();
+    match cut {
+        Some(index) => collapse_spaces(&artist[..index]),
+        None => collapse_spaces(artist),
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+    if !value.is_empty() && !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+// Artist/album combinations to try, the most likely first
+pub fn get_variants(
+    album_artist: &str,
+    artist: &str,
+    album: &str,
+    preferred_artist: Option<String>,
+) -> Vec<QueryVariant> {
+    let mut artists = Vec::new();
+    if let Some(preferred_artist) = preferred_artist {
+        push_unique(&mut artists, preferred_artist);
+    }
+    for name in [album_artist, artist] {
+        push_unique(&mut artists, name.trim().to_string());
+        push_unique(&mut artists, strip_featured(name));
+        push_unique(&mut artists, strip_brackets(name));
+        for content in bracket_contents(name) {
+            push_unique(&mut artists, content);
+        }
+    }
+
+    let mut albums = Vec::new();
+    push_unique(&mut albums, album.trim().to_string());
+    push_unique(&mut albums, strip_brackets(album));
+
+    if artists.is_empty() || albums.is_empty() {
+        return Vec::new();
+    }
+
+    // First every album name with the main artist, then every artist name with the original
+    // album name and at the end the remaining combinations
+    let mut variants: Vec<QueryVariant> = Vec::new();
+    let combinations = albums
+        .iter()
+        .map(|album| (&artists[0], album))
+        .chain(artists.iter().map(|artist| (artist, &albums[0])))
+        .chain(
+            albums
+                .iter()
+                .flat_map(|album| artists.iter().map(move |artist| (artist, album))),
+        );
+    for (artist, album) in combinations {
+        let variant = QueryVariant {
+            artist: artist.clone(),
+            album: album.clone(),
+        };
+        if !variants.contains(&variant) {
+            variants.push(variant);
+        }
+        if variants.len() == MAX_VARIANTS {
+            break;
+        }
+    }
+    variants
+}
+
+// Album names shared by many unrelated releases
+pub fn is_generic_album_name(album: &str) -> bool {
+    let album = strip_brackets(&album.to_lowercase());
+    [
+        "greatest hits",
+        "best of",
+        "the best of",
+        "hits",
+        "ost",
+        "soundtrack",
+        "original soundtrack",
+        "original motion picture soundtrack",
+        "original game soundtrack",
+        "collection",
+        "the collection",
+        "anthology",
+        "essentials",
+        "the essential",
+        "gold",
+        "live",
+        "unplugged",
+    ]
+    .iter()
+    .any(|name| album == *name || album.starts_with(&format!("{} ", name)))
+}
+
+// Album artist used for compilations
+pub fn is_compilation(album_artist: &str) -> bool {
+    matches!(
+        album_artist.trim().to_lowercase().as_str(),
+        "various artists" | "various" | "va" | "v.a." | "v/a" | "multiple artists"
+    )
+}
+
+// Singles are often tagged with album name same as the title
+pub fn is_single(title: &str, album: &str) -> bool {
+    let normalize = |text: &str| strip_brackets(text.trim()).to_lowercase();
+    !title.trim().is_empty() && normalize(title) == normalize(album)
+}
+
+// Cache key of album cover. Singles use separate namespace, so they don't share cache entry with
+// the album of the same name.
+pub fn get_album_id(media_info: &MediaInfo) -> String {
+    match is_single(&media_info.title, &media_info.album) {
+        true => format!("single: {} - {}", media_info.album_artist, media_info.title),
+        false => format!("{} - {}", media_info.album_artist, media_info.album),
+    }
+}
+
+fn is_found(cover_url: &str) -> bool {
+    !cover_url.is_empty() && cover_url != "missing-cover"
+}
+
+fn check_cancelled(is_cancelled: &dyn Fn() -> bool, debug_log: bool) -> bool {
+    let cancelled = is_cancelled();
+    if cancelled {
+        debug_log!(debug_log, "Cover lookup cancelled, track changed.");
+    }
+    cancelled
+}
+
+// Album search with every query variant, the same for all providers
+#[derive(Clone)]
+struct AlbumSearch {
+    album_id: String,
+    variants: Vec<QueryVariant>,
+    album: String,
+    compilation: bool,
+    verify_track: Option<String>,
+}
+
+// Cover found by a provider and the query variant that worked
+struct ProviderHit {
+    source: &'static str,
+    cover_url: String,
+    variant: Option<QueryVariant>,
+}
+
+// Last.fm with every query variant, then album title only search for compilations
+fn search_lastfm(
+    search: &AlbumSearch,
+    lastfm_api_key: &str,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+    is_cancelled: &dyn Fn() -> bool,
+    debug_log: bool,
+) -> Option<ProviderHit> {
+    for variant in &search.variants {
+        if is_cancelled() {
+            return None;
+        }
+        let cover_url = utils::get_cover_url(
+            &search.album_id,
+            &variant.album,
+            String::new(),
+            cache_enabled,
+            album_cache,
+            &variant.artist,
+            lastfm_api_key,
+        );
+        if is_found(&cover_url) {
+            return Some(ProviderHit {
+                source: "Last.fm",
+                cover_url,
+                variant: Some(variant.clone()),
+            });
+        }
+        debug_log!(
+            debug_log,
+            "[last.fm] cover not found for: {} - {}",
+            variant.artist,
+            variant.album
+        );
+    }
+
+    if search.compilation && !is_cancelled() {
+        let cover_url = utils::get_cover_url_lastfm_album_search(
+            &search.album_id,
+            &search.album,
+            cache_enabled,
+            album_cache,
+            lastfm_api_key,
+        );
+        if is_found(&cover_url) {
+            return Some(ProviderHit {
+                source: "Last.fm",
+                cover_url,
+                variant: None,
+            });
+        }
+    }
+    None
+}
+
+fn search_musicbrainz(
+    search: &AlbumSearch,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+    is_cancelled: &dyn Fn() -> bool,
+    debug_log: bool,
+) -> Option<ProviderHit> {
+    for variant in &search.variants {
+        if is_cancelled() {
+            return None;
+        }
+        let cover_url = utils::get_cover_url_musicbrainz(
+            &search.album_id,
+            &variant.album,
+            String::new(),
+            cache_enabled,
+            album_cache,
+            &variant.artist,
+            search.verify_track.as_deref(),
+            is_cancelled,
+        );
+        if is_found(&cover_url) {
+            return Some(ProviderHit {
+                source: "MusicBrainz",
+                cover_url,
+                variant: Some(variant.clone()),
+            });
+        }
+        debug_log!(
+            debug_log,
+            "[musicbrainz] cover not found for: {} - {}",
+            variant.artist,
+            variant.album
+        );
+    }
+    None
+}
+
+// Search function of a store, album cover url or empty if not found
+type StoreSearch = fn(&str, &str) -> Result<String, Box<dyn std::error::Error>>;
+
+// iTunes and then Deezer with every query variant, neither needs an API key
+fn search_stores(
+    search: &AlbumSearch,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+    is_cancelled: &dyn Fn() -> bool,
+    debug_log: bool,
+) -> Option<ProviderHit> {
+    let stores: [(&'static str, StoreSearch, &AtomicU64, &AtomicU64); 2] = [
+        (
+            "iTunes",
+            store_covers::search_itunes,
+            &metrics::ITUNES_HITS,
+            &metrics::ITUNES_MISSES,
+        ),
+        (
+            "Deezer",
+            store_covers::search_deezer,
+            &metrics::DEEZER_HITS,
+            &metrics::DEEZER_MISSES,
+        ),
+    ];
+    for (source, search_store, hits, misses) in stores {
+        for variant in &search.variants {
+            if is_cancelled() {
+                return None;
+            }
+            match search_store(&variant.artist, &variant.album) {
+                Ok(cover_url) if !cover_url.is_empty() => {
+                    metrics::inc(hits);
+                    println!(
+                        "[{}] fetched image link: {}",
+                        source.to_lowercase(),
+                        cover_url
+                    );
+                    utils::save_cover_url(cache_enabled, album_cache, &search.album_id, &cover_url);
+                    return Some(ProviderHit {
+                        source,
+                        cover_url,
+                        variant: Some(variant.clone()),
+                    });
+                }
+                Ok(_) => debug_log!(
+                    debug_log,
+                    "[{}] cover not found for: {} - {}",
+                    source.to_lowercase(),
+                    variant.artist,
+                    variant.album
+                ),
+                Err(err) => debug_log!(
+                    debug_log,
+                    "[{}] request failed: {}",
+                    source.to_lowercase(),
+                    err
+                ),
+            }
+            metrics::inc(misses);
+        }
+    }
+    None
+}
+
+impl CoverLookup<'_> {
+    // Checked before every request
+    fn cancelled(&self) -> bool {
+        check_cancelled(self.is_cancelled, self.debug_log)
+    }
+
+    // Try Last.fm with every query variant, then MusicBrainz (or both at once with parallel),
+    // then iTunes and Deezer
+    pub fn find_cover(&mut self, album_id: &str, media_info: &MediaInfo) -> String {
+        // If no album or Unknown Album
+        if media_info.album.eq("Unknown Album") {
+            println!("Missing album name or Unknown Album.");
+            return String::from("missing-cover");
+        }
+
+        // Cover set manually in config, singles can also use "Album Artist - Album" key
+        let album_key = format!("{} - {}", media_info.album_artist, media_info.album);
+
+        // Image dropped into the cover folder overrides everything else
+        if let Some(cover_folder) = &mut self.cover_folder {
+            if let Some(url) = cover_folder.get_cover(&[album_id, &album_key]) {
+                return self.found(album_id, "cover folder", url);
+            }
+        }
+        let cover_override = [album_id, album_key.as_str()].iter().find_map(|key| {
+            self.cover_overrides.get(*key).or_else(|| {
+                self.cover_overrides
+                    .iter()
+                    .find(|(override_key, _)| {
+                        album_cache::normalize_key(override_key) == album_cache::normalize_key(key)
+                    })
+                    .map(|(_, url)| url)
+            })
+        });
+        if let Some(url) = cover_override {
+            debug_log!(self.debug_log, "Using cover from cover_overrides: {}", url);
+            return self.found(album_id, "cover_overrides", url.clone());
+        }
+
+        // Load from cache if enabled
+        if self.cache_enabled {
+            if let Some(url) = self.album_cache.get::<String>(album_id) {
+                if url.len() > 5 {
+                    metrics::inc(&metrics::CACHE_HITS);
+                    return self.found(album_id, "cache", url);
+                }
+            }
+        }
+
+        // Track level lookup for singles, album search could find album with the same name
+        if is_single(&media_info.title, &media_info.album) {
+            debug_log!(self.debug_log, "Single, using track searches.");
+            let artist = match media_info.album_artist.is_empty() {
+                true => &media_info.artist,
+                false => &media_info.album_artist,
+            };
+            if self.cancelled() {
+                return String::from("missing-cover");
+            }
+            if !self.lastfm_api_key.is_empty() {
+                let cover_url = utils::get_cover_url_lastfm_track(
+                    album_id,
+                    artist,
+                    &media_info.title,
+                    self.cache_enabled,
+                    self.album_cache,
+                    self.lastfm_api_key,
+                );
+                if is_found(&cover_url) {
+                    return self.found(album_id, "Last.fm", cover_url);
+                }
+            }
+            if self.use_musicbrainz && !self.cancelled() {
+                let cover_url = utils::get_cover_url_musicbrainz_recording(
+                    album_id,
+                    artist,
+                    &media_info.title,
+                    self.cache_enabled,
+                    self.album_cache,
+                );
+                if is_found(&cover_url) {
+                    return self.found(album_id, "MusicBrainz", cover_url);
+                }
+            }
+        }
+
+        let compilation = is_compilation(&media_info.album_artist);
+        if compilation {
+            debug_log!(
+                self.debug_log,
+                "Compilation album, using track artist and album title searches."
+            );
+        }
+
+        // For compilations prefer release group with both album and track title
+        if compilation && self.use_musicbrainz && !self.cancelled() {
+            let cover_url = utils::get_cover_url_musicbrainz_release_group(
+                album_id,
+                &media_info.album,
+                &media_info.title,
+                self.cache_enabled,
+                self.album_cache,
+            );
+            if is_found(&cover_url) {
+                return self.found(album_id, "MusicBrainz", cover_url);
+            }
+        }
+
+        // Track artists of compilations are different for every album
+        let preferred_artist = match compilation {
+            true => None,
+            false => self.artist_variants.get::<String>(&media_info.album_artist),
+        };
+        let variants = get_variants(
+            &media_info.album_artist,
+            &media_info.artist,
+            &media_info.album,
+            preferred_artist,
+        );
+
+        let search = AlbumSearch {
+            album_id: album_id.to_string(),
+            variants,
+            album: media_info.album.clone(),
+            compilation,
+            // For generic album names make sure the MusicBrainz release contains current track
+            verify_track: is_generic_album_name(&media_info.album)
+                .then(|| media_info.title.clone()),
+        };
+        let use_lastfm = !self.lastfm_api_key.is_empty();
+        let hit = match self.parallel && use_lastfm && self.use_musicbrainz {
+            true => self.search_parallel(&search),
+            false => {
+                let is_cancelled = || check_cancelled(self.is_cancelled, self.debug_log);
+                let lastfm_hit = match use_lastfm {
+                    true => search_lastfm(
+                        &search,
+                        self.lastfm_api_key,
+                        self.cache_enabled,
+                        self.album_cache,
+                        &is_cancelled,
+                        self.debug_log,
+                    ),
+                    false => None,
+                };
+                match lastfm_hit {
+                    Some(hit) => Some(hit),
+                    None if self.use_musicbrainz => search_musicbrainz(
+                        &search,
+                        self.cache_enabled,
+                        self.album_cache,
+                        &is_cancelled,
+                        self.debug_log,
+                    ),
+                    None => None,
+                }
+            }
+        };
+        // Stores are tried last, they match only exact artist and album names
+        let hit = match hit {
+            None if self.use_stores && !self.cancelled() => {
+                let is_cancelled = || check_cancelled(self.is_cancelled, self.debug_log);
+                search_stores(
+                    &search,
+                    self.cache_enabled,
+                    self.album_cache,
+                    &is_cancelled,
+                    self.debug_log,
+                )
+            }
+            hit => hit,
+        };
+        if let Some(hit) = hit {
+            if let Some(variant) = &hit.variant {
+                self.remember_variant(&media_info.album_artist, variant);
+            }
+            return self.found(album_id, hit.source, hit.cover_url);
+        }
+        if (self.is_cancelled)() {
+            return String::from("missing-cover");
+        }
+
+        // TheAudioDB album cover, or artist photo if there is no album cover anywhere
+        if !self.theaudiodb_api_key.is_empty() && !self.cancelled() {
+            let artist = match media_info.album_artist.is_empty() {
+                true => &media_info.artist,
+                false => &media_info.album_artist,
+            };
+            match theaudiodb::get_album_cover(self.theaudiodb_api_key, artist, &media_info.album) {
+                Ok(url) if !url.is_empty() => {
+                    println!("[theaudiodb] fetched image link: {}", url);
+                    utils::save_cover_url(self.cache_enabled, self.album_cache, album_id, &url);
+                    return self.found(album_id, "TheAudioDB", url);
+                }
+                Ok(_) => debug_log!(
+                    self.debug_log,
+                    "[theaudiodb] cover not found for: {} - {}",
+                    artist,
+                    media_info.album
+                ),
+                Err(err) => debug_log!(self.debug_log, "[theaudiodb] request failed: {}", err),
+            }
+
+            // Not cached, so the album cover can still be found later
+            if !compilation && !self.cancelled() {
+                let info = theaudiodb::get_artist_info_cached(
+                    self.artist_info,
+                    self.theaudiodb_api_key,
+                    artist,
+                    self.debug_log,
+                );
+                if !info.thumb.is_empty() {
+                    return self.found(album_id, "TheAudioDB artist photo", info.thumb);
+                }
+            }
+        }
+
+        control::publish_event("cover", &format!("{}: not found", album_id));
+        String::from("missing-cover")
+    }
+
+    // Last.fm and MusicBrainz are queried at the same time and the first found cover is used.
+    // Providers run in own threads with in-memory cache, so a slow request of the other one
+    // doesn't delay the result, and only the used cover is saved.
+    fn search_parallel(&mut self, search: &AlbumSearch) -> Option<ProviderHit> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel::<Option<ProviderHit>>();
+        let debug_log = self.debug_log;
+
+        let lastfm_search = search.clone();
+        let lastfm_api_key = self.lastfm_api_key.to_string();
+        let lastfm_stop = stop.clone();
+        let lastfm_sender = sender.clone();
+        thread::spawn(move || {
+            let mut album_cache = album_cache::memory_db();
+            let is_stopped = || lastfm_stop.load(Ordering::SeqCst);
+            let hit = search_lastfm(
+                &lastfm_search,
+                &lastfm_api_key,
+                false,
+                &mut album_cache,
+                &is_stopped,
+                debug_log,
+            );
+            let _ = lastfm_sender.send(hit);
+        });
+
+        let musicbrainz_search = search.clone();
+        let musicbrainz_stop = stop.clone();
+        thread::spawn(move || {
+            thread::sleep(PARALLEL_STAGGER);
+            let mut album_cache = album_cache::memory_db();
+            let is_stopped = || musicbrainz_stop.load(Ordering::SeqCst);
+            let hit = search_musicbrainz(
+                &musicbrainz_search,
+                false,
+                &mut album_cache,
+                &is_stopped,
+                debug_log,
+            );
+            let _ = sender.send(hit);
+        });
+
+        // Wait for the first found cover or until both providers give up
+        let mut pending = 2;
+        let hit = loop {
+            match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(Some(hit)) => break Some(hit),
+                Ok(None) => {
+                    pending -= 1;
+                    if pending == 0 {
+                        break None;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.cancelled() {
+                        break None;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break None,
+            }
+        };
+        stop.store(true, Ordering::SeqCst);
+
+        if let Some(hit) = &hit {
+            debug_log!(
+                self.debug_log,
+                "[parallel] first cover from: {}",
+                hit.source
+            );
+            utils::save_cover_url(
+                self.cache_enabled,
+                self.album_cache,
+                &search.album_id,
+                &hit.cover_url,
+            );
+        }
+        hit
+    }
+
+    // Cover source is shown by "tail" subcommand and in the log of published activity
+    fn found(&mut self, album_id: &str, source: &'static str, cover_url: String) -> String {
+        self.source = source;
+        control::publish_event(
+            "cover",
+            &format!("{}: {} (from {})", album_id, cover_url, source),
+        );
+        cover_url
+    }
+
+    // Next albums of this artist will try the working name first
+    fn remember_variant(&mut self, album_artist: &str, variant: &QueryVariant) {
+        if variant.artist == album_artist
+            || is_compilation(album_artist)
+            || self.artist_variants.get::<String>(album_artist).as_ref() == Some(&variant.artist)
+        {
+            return;
+        }
+        debug_log!(
+            self.debug_log,
+            "Cover found using artist name: {} => {}",
+            album_artist,
+            variant.artist
+        );
+        if self
+            .artist_variants
+            .set(album_artist, &variant.artist)
+            .is_err()
+        {
+            println!("[cache] error, unable to write to artist variants file.");
+        }
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+
+use crate::debug_log;
+use crate::metrics;
+use crate::utils;
+
+// Consecutive failures after which the connection is considered broken, not just a hiccup
+const PERSISTENT_FAILURES: u32 = 3;
+
+// Longest wait between reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// Tracks D-Bus failures, e.g. after the session bus was restarted together with the compositor.
+// Every failure drops the connection, so a new one is opened on the next attempt, and the wait
+// between attempts grows while the failures continue.
+pub struct DbusHealth {
+    retry_interval: Duration,
+    failures: u32,
+    reported: bool,
+    debug_log: bool,
+}
+
+impl DbusHealth {
+    pub fn new(retry_interval: Duration, debug_log: bool) -> DbusHealth {
+        DbusHealth {
+            retry_interval,
+            failures: 0,
+            reported: false,
+            debug_log,
+        }
+    }
+
+    // Returns how long to wait before connecting again
+    pub fn failure(&mut self, err: &dyn std::fmt::Display) -> Duration {
+        self.failures += 1;
+        debug_log!(self.debug_log, "D-Bus failure #{}: {}", self.failures, err);
+        metrics::set_last_error(&format!("D-Bus: {}", err));
+
+        if self.failures >= PERSISTENT_FAILURES && !self.reported {
+            println!(
+                "\x1b[33mWARNING: D-Bus is not responding ({}), it was probably restarted. Reconnecting until it's back...\x1b[0m",
+                err
+            );
+            self.reported = true;
+            // Unique names of connections are reused by the new bus, so cached names are wrong
+            utils::clear_player_names();
+        }
+
+        self.backoff()
+    }
+
+    pub fn success(&mut self) {
+        if self.reported {
+            println!("Reconnected to D-Bus.");
+        }
+        self.failures = 0;
+        self.reported = false;
+    }
+
+    // Wait before the next attempt, zero when D-Bus works
+    pub fn backoff(&self) -> Duration {
+        match self.failures {
+            0 => Duration::ZERO,
+            failures => (self.retry_interval * 2u32.pow((failures - 1).min(6))).min(MAX_BACKOFF),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+#[cfg(target_os = "linux")]
+use mpris::Player;
+use serde_json::{json, Value};
+
+use crate::control;
+use crate::player_match;
+use crate::settings::Cli;
+#[cfg(target_os = "linux")]
+use crate::sources::Source;
+use crate::sources::{self, Fallback};
+use crate::utils::{self, MediaInfo, NowPlayingResult};
+
+// Scheme of the cover link, e.g. "https", "file" or "data", empty without cover
+fn art_url_scheme(art_url: &str) -> &str {
+    if art_url.starts_with("data:") {
+        return "data";
+    }
+    art_url
+        .split_once("://")
+        .map(|(scheme, _)| scheme)
+        .unwrap_or_default()
+}
+
+fn player_json(name: &str, id: &str, names: &[String], settings: &Cli) -> Value {
+    json!({
+        "name": name,
+        "id": id,
+        "names": names,
+        "allowed": settings.allowlist.is_empty() || player_match::is_listed(&settings.allowlist, names),
+        "video_player": player_match::is_listed(&settings.video_players, names),
+    })
+}
+
+// Playback status and which metadata fields are set, placeholders of fallback sources
+// (e.g. "Unknown Artist") are counted as missing
+fn media_info_json(result: &NowPlayingResult) -> Value {
+    let media_info: &MediaInfo = match result {
+        Ok(media_info) => media_info,
+        Err(err) => return json!({ "error": err.to_string() }),
+    };
+    let status = match (media_info.is_playing, media_info.is_stopped) {
+        (true, _) => "Playing",
+        (false, true) => "Stopped",
+        (false, false) => "Paused",
+    };
+    let art_url_scheme = match media_info.art_data.0.is_empty() {
+        true => art_url_scheme(&media_info.art_url),
+        false => "data",
+    };
+    json!({
+        "playback_status": status,
+        "metadata": {
+            "title": !media_info.title.is_empty(),
+            "artist": !media_info.artist.is_empty() && media_info.artist != "Unknown Artist",
+            "album": !media_info.album.is_empty() && media_info.album != "Unknown Album",
+            "length": media_info.duration > 0,
+            "art_url": !media_info.art_url.is_empty() || !media_info.art_data.0.is_empty(),
+            "url": !media_info.url.is_empty(),
+            "track_id": !media_info.track_id.is_empty(),
+        },
+        "art_url_scheme": art_url_scheme,
+        "error": null,
+    })
+}
+
+// Metadata is read directly, so fields missing in the player are not hidden by placeholders
+#[cfg(target_os = "linux")]
+fn mpris_player_json(player: &Player, settings: &Cli) -> Value {
+    let names = utils::get_player_names(player, settings.dbus_address.as_deref());
+    let mut value = player_json(
+        player.identity(),
+        &utils::sanitize_name(player.identity()),
+        &names,
+        settings,
+    );
+    value["bus_name"] = json!(player.bus_name());
+    value["playback_status"] = match player.get_playback_status() {
+        Ok(status) => json!(format!("{:?}", status)),
+        Err(_) => Value::Null,
+    };
+    match player.get_metadata() {
+        Ok(metadata) => {
+            let art_url = metadata.art_url().unwrap_or_default();
+            let is_set = |text: Option<&str>| text.is_some_and(|text| !text.is_empty());
+            value["metadata"] = json!({
+                "title": is_set(metadata.title()),
+                "artist": metadata.artists().is_some_and(|artists| !artists.is_empty()),
+                "album": is_set(metadata.album_name()),
+                "length": metadata.length().is_some(),
+                "art_url": !art_url.is_empty(),
+                "url": is_set(metadata.url()),
+                "track_id": metadata.track_id().is_some(),
+            });
+            value["art_url_scheme"] = json!(art_url_scheme(art_url));
+            value["error"] = Value::Null;
+        }
+        Err(err) => value["error"] = json!(err.to_string()),
+    }
+    value
+}
+
+fn fallback_json(fallback: &Fallback, settings: &Cli) -> Value {
+    let names = vec![fallback.identity().to_string()];
+    let mut value = player_json(
+        fallback.identity(),
+        &utils::sanitize_name(fallback.identity()),
+        &names,
+        settings,
+    );
+    value["description"] = json!(fallback.description());
+    value["available"] = json!(fallback.is_available());
+    if fallback.is_available() {
+        let details = media_info_json(&fallback.get_currently_playing(false));
+        if let (Value::Object(value), Value::Object(details)) = (&mut value, details) {
+            value.extend(details);
+        }
+    }
+    value
+}
+
+// Counters and last error of the running instance, if there is one
+fn running_instance_json() -> Value {
+    control::query("stats")
+        .and_then(|reply| serde_json::from_str(&reply).ok())
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(target_os = "linux")]
+fn players_json(settings: &Cli) -> Result<Vec<Value>, String> {
+    let finder = utils::new_player_finder(settings.dbus_address.as_deref())
+        .map_err(|err| format!("Could not connect to D-Bus: {}", err))?;
+    let players = finder.find_all().unwrap_or_default();
+    Ok(players
+        .iter()
+        .filter(|player| player.bus_name() != "org.mpris.MediaPlayer2.playerctld")
+        .map(|player| mpris_player_json(player, settings))
+        .collect())
+}
+
+// media-control shows only the current player
+#[cfg(target_os = "macos")]
+fn players_json(settings: &Cli) -> Result<Vec<Value>, String> {
+    let result = utils::get_currently_playing();
+    let media_info = match &result {
+        Ok(media_info) => media_info,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let names = vec![media_info.player_id.clone()];
+    let id = utils::sanitize_name(&utils::app_name_from_bundle_id(&media_info.player_id));
+    let mut value = player_json(&media_info.player_id, &id, &names, settings);
+    if let (Value::Object(value), Value::Object(details)) = (&mut value, media_info_json(&result)) {
+        value.extend(details);
+    }
+    Ok(vec![value])
+}
+
+// Player that would be selected now, chosen like in the main loop
+#[cfg(target_os = "linux")]
+fn selected_player_json(settings: &Cli, fallbacks: &[Fallback]) -> Result<Value, String> {
+    let finder = utils::new_player_finder(settings.dbus_address.as_deref())
+        .map_err(|err| format!("Could not connect to D-Bus: {}", err))?;
+    let mpris_player = match settings.allowlist.is_empty() {
+        true => utils::active_player_finder(&finder, None),
+        false => utils::allowlist_player_finder(
+            &finder,
+            &settings.allowlist,
+            &settings.allowlist_priorities,
+            settings.allowlist_sort_by.as_deref() == Some("priority"),
+            settings.dbus_address.as_deref(),
+            settings.stopped_behavior.as_deref().unwrap_or("paused") == "paused",
+            None,
+            false,
+        ),
+    };
+    Ok(
+        match sources::select_source(mpris_player, fallbacks, &settings.allowlist, false) {
+            Some(Source::Mpris(player)) => mpris_player_json(&player, settings),
+            Some(Source::Fallback(fallback)) => fallback_json(&fallback, settings),
+            None => Value::Null,
+        },
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn selected_player_json(settings: &Cli, fallbacks: &[Fallback]) -> Result<Value, String> {
+    // Fallback is used only when media-control has nothing playing
+    let players = players_json(settings)?;
+    match players.into_iter().next() {
+        Some(player) if player["playback_status"] == "Playing" => Ok(player),
+        player => Ok(fallbacks
+            .iter()
+            .find(|fallback| {
+                fallback.is_playing()
+                    && (settings.allowlist.is_empty()
+                        || player_match::is_listed(&settings.allowlist, &[fallback.identity()]))
+            })
+            .map(|fallback| fallback_json(fallback, settings))
+            .or(player)
+            .unwrap_or(Value::Null)),
+    }
+}
+
+// All players (list_players) or the one that would be selected, with stats of the running instance
+pub fn snapshot(settings: &Cli, list_players: bool) -> Value {
+    let fallbacks = sources::get_fallbacks(settings);
+    let mut output = json!({ "version": utils::VERSION });
+
+    let result = match list_players {
+        true => players_json(settings).map(|players| {
+            output["players"] = json!(players);
+            output["fallbacks"] = fallbacks
+                .iter()
+                .map(|fallback| fallback_json(fallback, settings))
+                .collect();
+        }),
+        false => selected_player_json(settings, &fallbacks).map(|player| {
+            output["player"] = player;
+        }),
+    };
+    output["error"] = match result {
+        Ok(_) => Value::Null,
+        Err(err) => json!(err),
+    };
+    output["running_instance"] = running_instance_json();
+    output
+}
+
+// JSON variant of --list-players and --get-player-id, meant to be pasted into bug reports
+pub fn print_json(settings: &Cli) {
+    let output = snapshot(settings, settings.list_players);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&output).unwrap_or_default()
+    );
+}
@@ -0,0 +1,237 @@
+use discord_rich_presence::activity::Activity;
+use discord_rich_presence::error::Error;
+use discord_rich_presence::{DiscordIpc, DiscordIpcClient};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::debug_log;
+use crate::discord_socket;
+use crate::player_match;
+
+type Result<T> = std::result::Result<T, Error>;
+
+// Connection to one Discord client through its IPC socket
+struct SocketClient {
+    client_id: String,
+    path: PathBuf,
+    socket: Option<UnixStream>,
+}
+
+impl DiscordIpc for SocketClient {
+    fn connect_ipc(&mut self) -> Result<()> {
+        let socket = UnixStream::connect(&self.path).map_err(|_| Error::IPCConnectionFailed)?;
+        self.socket = Some(socket);
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+        socket.write_all(data).map_err(Error::WriteError)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+        socket.read_exact(buffer).map_err(Error::ReadError)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let _ = self.send(json!({}), 2);
+        if let Some(socket) = self.socket.take() {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+        Ok(())
+    }
+
+    fn get_client_id(&self) -> &str {
+        &self.client_id
+    }
+}
+
+fn set_activity_payload(activity: &Value) -> Value {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or_default();
+    json!({
+        "cmd": "SET_ACTIVITY",
+        "args": {
+            "pid": std::process::id(),
+            "activity": activity
+        },
+        "nonce": nonce.to_string()
+    })
+}
+
+// Activity is sent to the first Discord client found, or with multiple_discord_clients to every
+// running one. Each socket has its own connection, clients started later are connected on the
+// next activity update.
+pub struct DiscordClients {
+    client_id: String,
+    first: DiscordIpcClient,
+    multiple: bool,
+    allowed_sockets: Vec<String>,
+    clients: Vec<SocketClient>,
+    activity: Value,               // Last activity, null when cleared
+    sockets: Vec<(u64, i64, i64)>, // Discord sockets when connected, see discord_socket::socket_ids
+    debug_log: bool,
+}
+
+impl DiscordClients {
+    pub fn new(
+        client_id: &str,
+        multiple: bool,
+        allowed_sockets: &[String],
+        debug_log: bool,
+    ) -> DiscordClients {
+        DiscordClients {
+            client_id: client_id.to_string(),
+            first: DiscordIpcClient::new(client_id),
+            multiple,
+            allowed_sockets: allowed_sockets.to_vec(),
+            clients: Vec::new(),
+            activity: Value::Null,
+            sockets: Vec::new(),
+            debug_log,
+        }
+    }
+
+    // Connect sockets of newly started Discord clients, returns number of connected clients
+    fn connect_new(&mut self) -> usize {
+        for path in discord_socket::find_sockets() {
+            let name = path.to_string_lossy().to_string();
+            let file_name = path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if !self.allowed_sockets.is_empty()
+                && !player_match::is_listed(&self.allowed_sockets, &[&name, &file_name])
+            {
+                continue;
+            }
+            if !self.clients.iter().any(|client| client.path == path) {
+                self.clients.push(SocketClient {
+                    client_id: self.client_id.clone(),
+                    path,
+                    socket: None,
+                });
+            }
+        }
+
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.socket.is_none())
+        {
+            match client.connect() {
+                Ok(_) => debug_log!(
+                    self.debug_log,
+                    "Connected to Discord client: {}",
+                    client.path.display()
+                ),
+                Err(_) => client.socket = None,
+            }
+        }
+
+        // Not running anymore
+        self.clients.retain(|client| client.socket.is_some());
+        self.clients.len()
+    }
+
+    // Send to every client, the ones that fail are disconnected
+    fn send_all(&mut self) -> Result<()> {
+        self.connect_new();
+        let payload = set_activity_payload(&self.activity);
+        let mut sent = false;
+        for client in self.clients.iter_mut() {
+            match client.send(payload.clone(), 1) {
+                Ok(_) => sent = true,
+                Err(_) => {
+                    debug_log!(
+                        self.debug_log,
+                        "Disconnected from Discord client: {}",
+                        client.path.display()
+                    );
+                    client.socket = None;
+                }
+            }
+        }
+        self.clients.retain(|client| client.socket.is_some());
+        match sent {
+            true => Ok(()),
+            false => Err(Error::NotConnected),
+        }
+    }
+
+    pub fn connect(&mut self) -> Result<()> {
+        self.sockets = discord_socket::socket_ids();
+        if !self.multiple {
+            return self.first.connect();
+        }
+        match self.connect_new() {
+            0 => Err(Error::IPCNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    // Discord client was started (or restarted) after connecting. The connection is opened
+    // again, so the current activity can be sent right away instead of on the next track change.
+    pub fn discord_started(&mut self) -> bool {
+        let sockets = discord_socket::socket_ids();
+        if sockets.iter().all(|socket| self.sockets.contains(socket)) {
+            self.sockets = sockets;
+            return false;
+        }
+        debug_log!(
+            self.debug_log,
+            "New Discord client socket found, reconnecting."
+        );
+        match self.reconnect() {
+            Ok(_) => true,
+            // Not ready yet, tried again on the next check
+            Err(_) => {
+                self.sockets.clear();
+                false
+            }
+        }
+    }
+
+    pub fn reconnect(&mut self) -> Result<()> {
+        if !self.multiple {
+            self.sockets = discord_socket::socket_ids();
+            return self.first.reconnect();
+        }
+        self.close()?;
+        self.connect()
+    }
+
+    pub fn set_activity(&mut self, activity: Activity) -> Result<()> {
+        if !self.multiple {
+            return self.first.set_activity(activity);
+        }
+        self.activity = serde_json::to_value(&activity).unwrap_or_default();
+        self.send_all()
+    }
+
+    pub fn clear_activity(&mut self) -> Result<()> {
+        if !self.multiple {
+            return self.first.clear_activity();
+        }
+        self.activity = Value::Null;
+        self.send_all()
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        if !self.multiple {
+            return self.first.close();
+        }
+        for client in self.clients.iter_mut() {
+            let _ = client.close();
+        }
+        self.clients.clear();
+        Ok(())
+    }
+}
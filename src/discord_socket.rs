@@ -0,0 +1,140 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use crate::debug_log;
+
+// Directories where Discord, Vesktop and their Flatpak and Snap versions create IPC sockets,
+// relative to $XDG_RUNTIME_DIR
+const SOCKET_DIRS: [&str; 7] = [
+    "",
+    "app/com.discordapp.Discord",
+    "app/dev.vencord.Vesktop",
+    ".flatpak/com.discordapp.Discord/xdg-run",
+    ".flatpak/dev.vencord.Vesktop/xdg-run",
+    "snap.discord",
+    "snap.discord-canary",
+];
+
+// Directories are watched again after this time, in case some of them didn't exist before
+#[cfg(target_os = "linux")]
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+fn get_socket_dirs() -> Vec<PathBuf> {
+    let base = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(env::var_os)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    SOCKET_DIRS
+        .iter()
+        .map(|dir| base.join(dir))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+// All Discord IPC sockets, one for every running Discord client. The same socket linked
+// into other directories (e.g. for Flatpak) is returned once.
+pub fn find_sockets() -> Vec<PathBuf> {
+    let mut sockets: Vec<PathBuf> = Vec::new();
+    for dir in get_socket_dirs() {
+        for i in 0..10 {
+            let path = dir.join(format!("discord-ipc-{}", i));
+            if !path.exists() {
+                continue;
+            }
+            let real_path = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if !sockets.iter().any(|socket| {
+                fs::canonicalize(socket).unwrap_or_else(|_| socket.clone()) == real_path
+            }) {
+                sockets.push(path);
+            }
+        }
+    }
+    sockets
+}
+
+// Inode number and creation time of the sockets, a restarted Discord client creates a new socket
+// at the same path, often with the same inode number
+pub fn socket_ids() -> Vec<(u64, i64, i64)> {
+    find_sockets()
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| (metadata.ino(), metadata.ctime(), metadata.ctime_nsec()))
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+pub fn socket_exists() -> bool {
+    get_socket_dirs()
+        .iter()
+        .any(|dir| (0..10).any(|i| dir.join(format!("discord-ipc-{}", i)).exists()))
+}
+
+// Blocks until something is created in one of the socket directories or the recheck interval
+// passes. Returns false if inotify is not available.
+#[cfg(target_os = "linux")]
+fn wait_for_change(debug_log: bool) -> bool {
+    use std::ffi::CString;
+    use std::io::Read;
+    use std::os::fd::FromRawFd;
+    use std::os::unix::ffi::OsStrExt;
+
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        debug_log!(debug_log, "Could not initialize inotify");
+        return false;
+    }
+    // Descriptor is closed when the file is dropped
+    let mut inotify = unsafe { std::fs::File::from_raw_fd(fd) };
+    for dir in get_socket_dirs() {
+        if let Ok(path) = CString::new(dir.as_os_str().as_bytes()) {
+            unsafe {
+                libc::inotify_add_watch(fd, path.as_ptr(), libc::IN_CREATE | libc::IN_MOVED_TO)
+            };
+        }
+    }
+
+    // Checked after adding watches, so a socket created in between is not missed
+    if socket_exists() {
+        return true;
+    }
+
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ready = unsafe { libc::poll(&mut pollfd, 1, RECHECK_INTERVAL.as_millis() as i32) };
+    if ready > 0 {
+        let mut buffer = [0u8; 4096];
+        let _ = inotify.read(&mut buffer);
+    }
+    true
+}
+
+// Waits before the next Discord connection attempt. With "wait_for_discord_socket" sleeps
+// until Discord IPC socket appears instead of retrying every few seconds. If the socket
+// already exists but connection failed (Discord still starting), retries after the interval.
+pub fn wait_for_discord(use_inotify: bool, retry_interval: Duration, debug_log: bool) {
+    #[cfg(target_os = "linux")]
+    if use_inotify && !socket_exists() {
+        debug_log!(debug_log, "Waiting for Discord IPC socket");
+        while !socket_exists() {
+            if !wait_for_change(debug_log) {
+                sleep(retry_interval);
+                return;
+            }
+        }
+        debug_log!(debug_log, "Discord IPC socket found");
+        return;
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (use_inotify, debug_log);
+
+    sleep(retry_interval);
+}
@@ -0,0 +1,180 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Season and episode of TV show titles, so they're shown as "Show — S02E05 • Episode Title".
+// Recognized forms, case insensitive:
+// "Show S02E05 Episode Title", "Show.s2e5", "Show - S02 E05 - Title", "Show S02E05E06" (first episode),
+// "Show 2x05 Title" and "Show Season 2 Episode 5 Title". Jellyfin names files and items like
+// "Show (2019) - S02E05 - Episode Title", the year is kept in the show name.
+static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?i)(?:^|[\s._-])",
+        r"(?:s(?P<season>\d{1,2})[\s._-]?e(?P<episode>\d{1,3})(?:[\s._-]?e\d{1,3})*",
+        r"|(?P<x_season>\d{1,2})x(?P<x_episode>\d{2})",
+        r"|season[\s._]*(?P<long_season>\d{1,2})[\s._,-]*episode[\s._]*(?P<long_episode>\d{1,3}))",
+        r"(?:$|[\s._:-])",
+    ))
+    .expect("episode pattern is valid")
+});
+
+pub struct Episode {
+    pub show: String,
+    pub season: u32,
+    pub episode: u32,
+    pub title: String,
+}
+
+impl Episode {
+    // Season and episode number, e.g. "S02E05"
+    pub fn code(&self) -> String {
+        format!("S{:02}E{:02}", self.season, self.episode)
+    }
+
+    // First line of the watching activity, parts that are unknown are left out
+    pub fn details(&self) -> String {
+        let mut details = self.code();
+        if !self.show.is_empty() {
+            details = format!("{} — {}", self.show, details);
+        }
+        if !self.title.is_empty() {
+            details = format!("{} • {}", details, self.title);
+        }
+        details
+    }
+}
+
+// Separators between the show name, episode and title, e.g. "Show - S02E05 - Title"
+fn trim_separators(text: &str) -> &str {
+    text.trim_matches(|c: char| {
+        c.is_whitespace() || matches!(c, '-' | '–' | '—' | '.' | '_' | ':' | '•' | '|')
+    })
+}
+
+// Episode from the video title, None if it's not an episode. Players like Jellyfin send only the
+// episode title with the episode number, then the show name is taken from the album.
+pub fn parse(title: &str, album: &str) -> Option<Episode> {
+    let captures = PATTERN.captures(title)?;
+    let number = |names: [&str; 3]| {
+        names
+            .iter()
+            .find_map(|name| captures.name(name))
+            .and_then(|number| number.as_str().parse::<u32>().ok())
+    };
+    let season = number(["season", "x_season", "long_season"])?;
+    let episode = number(["episode", "x_episode", "long_episode"])?;
+
+    let matched = captures.get(0)?;
+    let mut show = trim_separators(&title[..matched.start()]).to_string();
+    let episode_title = trim_separators(&title[matched.end()..]).to_string();
+    if show.is_empty() && !album.is_empty() && album != "Unknown Album" && album != title {
+        show = album.to_string();
+    }
+
+    Some(Episode {
+        show,
+        season,
+        episode,
+        title: episode_title,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(title: &str, album: &str) -> (String, u32, u32, String) {
+        let episode = parse(title, album).expect("title is an episode");
+        (episode.show, episode.season, episode.episode, episode.title)
+    }
+
+    fn expected(show: &str, season: u32, episode: u32, title: &str) -> (String, u32, u32, String) {
+        (show.to_string(), season, episode, title.to_string())
+    }
+
+    #[test]
+    fn parses_season_and_episode() {
+        assert_eq!(
+            parsed("Show S02E05 Episode Title", ""),
+            expected("Show", 2, 5, "Episode Title")
+        );
+        assert_eq!(parsed("Show.s2e5", ""), expected("Show", 2, 5, ""));
+        assert_eq!(
+            parsed("Show - S02 E05 - Title", ""),
+            expected("Show", 2, 5, "Title")
+        );
+    }
+
+    #[test]
+    fn keeps_first_of_multiple_episodes() {
+        assert_eq!(
+            parsed("Show S02E05E06 Title", ""),
+            expected("Show", 2, 5, "Title")
+        );
+    }
+
+    #[test]
+    fn parses_other_forms() {
+        assert_eq!(
+            parsed("Show 2x05 Title", ""),
+            expected("Show", 2, 5, "Title")
+        );
+        assert_eq!(
+            parsed("Show Season 2 Episode 5 Title", ""),
+            expected("Show", 2, 5, "Title")
+        );
+    }
+
+    #[test]
+    fn keeps_year_of_jellyfin_names() {
+        assert_eq!(
+            parsed("Show (2019) - S02E05 - Title", ""),
+            expected("Show (2019)", 2, 5, "Title")
+        );
+    }
+
+    #[test]
+    fn takes_show_from_album() {
+        assert_eq!(
+            parsed("S02E05 - Title", "Show"),
+            expected("Show", 2, 5, "Title")
+        );
+        assert_eq!(
+            parsed("S02E05 - Title", "Unknown Album"),
+            expected("", 2, 5, "Title")
+        );
+        assert_eq!(
+            parsed("S02E05 - Title", "S02E05 - Title"),
+            expected("", 2, 5, "Title")
+        );
+        // Show name from the title is preferred
+        assert_eq!(
+            parsed("Show S02E05 Title", "Album"),
+            expected("Show", 2, 5, "Title")
+        );
+    }
+
+    #[test]
+    fn ignores_other_titles() {
+        for title in [
+            "Movie.1920x1080.mkv",
+            "Blink-182 - Song",
+            "Never Gonna Give You Up",
+        ] {
+            assert!(parse(title, "").is_none(), "{}", title);
+        }
+    }
+
+    #[test]
+    fn formats_details() {
+        let episode = |show: &str, title: &str| Episode {
+            show: show.to_string(),
+            season: 2,
+            episode: 5,
+            title: title.to_string(),
+        };
+        assert_eq!(episode("Show", "Title").details(), "Show — S02E05 • Title");
+        assert_eq!(episode("Show", "").details(), "Show — S02E05");
+        assert_eq!(episode("", "Title").details(), "S02E05 • Title");
+        assert_eq!(episode("", "").details(), "S02E05");
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+use crate::debug_log;
+use crate::player_match;
+use crate::processes;
+
+// Running processes are not checked on every refresh, as it's slower than metadata
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Detects running games, so the game's activity is not covered by music.
+// Games started from Steam are detected automatically, others by game_processes.
+pub struct GameDetector {
+    processes: Vec<String>,
+    last_check: Option<Instant>,
+    game: Option<String>,
+}
+
+impl GameDetector {
+    pub fn new(processes: &[String]) -> GameDetector {
+        GameDetector {
+            processes: processes.to_vec(),
+            last_check: None,
+            game: None,
+        }
+    }
+
+    // Name of the running game, "Steam app <id>" for Steam games
+    pub fn get_running(&mut self, debug_log: bool) -> Option<&str> {
+        if self
+            .last_check
+            .is_none_or(|last_check| last_check.elapsed() >= CHECK_INTERVAL)
+        {
+            self.last_check = Some(Instant::now());
+            self.game = if self.processes.is_empty() {
+                None
+            } else {
+                processes::get_process_names()
+                    .into_iter()
+                    .find(|name| player_match::is_listed(&self.processes, &[name]))
+            }
+            .or_else(|| processes::get_steam_game().map(|id| format!("Steam app {}", id)));
+            debug_log!(debug_log, "Running game: {:?}", self.game);
+        }
+        self.game.as_deref()
+    }
+}
@@ -0,0 +1,155 @@
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::debug_log;
+use crate::media_state;
+use crate::settings::{CustomStatus, Hooks};
+use crate::utils::{self, MediaInfo};
+
+#[derive(Debug, Clone, Copy)]
+pub enum HookEvent {
+    TrackChange,
+    Pause,
+    Stop,
+    PlayerChange,
+}
+
+impl HookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            HookEvent::TrackChange => "track_change",
+            HookEvent::Pause => "pause",
+            HookEvent::Stop => "stop",
+            HookEvent::PlayerChange => "player_change",
+        }
+    }
+
+    fn command<'a>(&self, hooks: &'a Hooks) -> Option<&'a String> {
+        match self {
+            HookEvent::TrackChange => hooks.on_track_change.as_ref(),
+            HookEvent::Pause => hooks.on_pause.as_ref(),
+            HookEvent::Stop => hooks.on_stop.as_ref(),
+            HookEvent::PlayerChange => hooks.on_player_change.as_ref(),
+        }
+    }
+}
+
+// Run user command in shell without waiting for it. Metadata is passed as MDRPC_* environment
+// variables and as JSON on stdin, which also has the track in the versioned format of the
+// "state" control command.
+pub fn run(
+    hooks: &Hooks,
+    event: HookEvent,
+    player: &str,
+    media_info: Option<&MediaInfo>,
+    cover_url: &str,
+    debug_log: bool,
+) {
+    let command = match event.command(hooks) {
+        Some(command) if !command.trim().is_empty() => command,
+        _ => return,
+    };
+    debug_log!(debug_log, "[hooks] on_{}: {}", event.name(), command);
+
+    let mut vars = vec![
+        ("event", event.name().to_string()),
+        ("player", player.to_string()),
+    ];
+    if let Some(info) = media_info {
+        vars.extend([
+            ("title", info.title.clone()),
+            ("artist", info.artist.clone()),
+            ("album", info.album.clone()),
+            ("album_artist", info.album_artist.clone()),
+            (
+                "status",
+                String::from(if info.is_playing { "playing" } else { "paused" }),
+            ),
+            ("duration", info.duration.to_string()),
+            ("position", info.position.to_string()),
+            ("url", info.url.clone()),
+            ("cover_url", cover_url.to_string()),
+        ]);
+    }
+
+    let mut stdin_json = vars_json(&vars);
+    stdin_json["schema_version"] = json!(media_state::SCHEMA_VERSION);
+    if let Some(info) = media_info {
+        stdin_json["track"] = media_state::track_json(info, cover_url);
+    }
+    spawn(command, &format!("on_{}", event.name()), &vars, stdin_json);
+}
+
+// Custom status command is run only when the text changes, empty text clears the status
+pub fn update_custom_status(
+    custom_status: &CustomStatus,
+    last_text: &mut String,
+    text: &str,
+    debug_log: bool,
+) {
+    let command = match &custom_status.command {
+        Some(command) if !command.trim().is_empty() => command,
+        _ => return,
+    };
+    // Discord limit of custom status length
+    let text = utils::trim_to_max_bytes(text.to_string(), 128);
+    if text == *last_text {
+        return;
+    }
+    debug_log!(debug_log, "[hooks] custom_status: {}", text);
+
+    let emoji = match text.is_empty() {
+        true => String::new(),
+        false => custom_status.emoji.clone().unwrap_or_default(),
+    };
+    let vars = [("status_text", text.clone()), ("status_emoji", emoji)];
+    spawn(command, "custom_status", &vars, vars_json(&vars));
+    *last_text = text;
+}
+
+fn vars_json(vars: &[(&str, String)]) -> Value {
+    Value::Object(
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), Value::from(value.as_str())))
+            .collect(),
+    )
+}
+
+fn spawn(command: &str, name: &str, vars: &[(&str, String)], stdin_json: Value) {
+    let stdin_json = stdin_json.to_string();
+
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command);
+    for (key, value) in vars {
+        process.env(format!("MDRPC_{}", key.to_uppercase()), value);
+    }
+
+    let mut child = match process.stdin(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            println!(
+                "\x1b[31m[hooks] Could not run {} command: {}\x1b[0m",
+                name, err
+            );
+            return;
+        }
+    };
+
+    // Wait in background so slow scripts don't block activity updates
+    let name = name.to_string();
+    thread::spawn(move || {
+        if let Some(mut stdin) = child.stdin.take() {
+            // Command may not read stdin at all
+            let _ = stdin.write_all(stdin_json.as_bytes());
+        }
+        match child.wait() {
+            Ok(status) if !status.success() => {
+                println!("[hooks] {} command exited with {}", name, status)
+            }
+            Err(err) => println!("[hooks] {} command error: {}", name, err),
+            _ => {}
+        }
+    });
+}
@@ -0,0 +1,94 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cache_db;
+use crate::debug_log;
+
+// Saved track is restored only if the app was not running for at most this long
+const RESTORE_WINDOW: u64 = 120;
+
+// Time of the last update is saved at most this often while the same activity is shown
+const TOUCH_INTERVAL: Duration = Duration::from_secs(60);
+
+fn now() -> u64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+// Last published track and its start time, saved in cache directory. After restart the same
+// track keeps its start time, so the elapsed time on the profile doesn't start from zero again.
+pub struct LastActivity {
+    db: PickleDb,
+    restorable: Option<(String, u64)>,
+    touched: Instant,
+    debug_log: bool,
+}
+
+impl LastActivity {
+    pub fn new(cache_dir: &Path, cache_on_disk: bool, debug_log: bool) -> LastActivity {
+        let path = cache_dir.join("last_activity.db");
+        let policy = || match cache_on_disk {
+            true => PickleDbDumpPolicy::AutoDump,
+            false => PickleDbDumpPolicy::NeverDump,
+        };
+        let db = cache_db::load(&path, policy);
+
+        let updated = db.get::<u64>("updated").unwrap_or(0);
+        let restorable = match now().saturating_sub(updated) <= RESTORE_WINDOW {
+            true => db.get::<String>("track").zip(db.get::<u64>("start")),
+            false => None,
+        };
+        if let Some((track, start)) = &restorable {
+            debug_log!(
+                debug_log,
+                "Last activity before restart: {} (started at {})",
+                track,
+                start
+            );
+        }
+
+        LastActivity {
+            db,
+            restorable,
+            touched: Instant::now(),
+            debug_log,
+        }
+    }
+
+    // Start time of the track published before restart, only for the first published track
+    pub fn restore(&mut self, track: &str) -> Option<u64> {
+        let (saved_track, start) = self.restorable.take()?;
+        if saved_track != track {
+            return None;
+        }
+        debug_log!(self.debug_log, "Restored start time of: {}", track);
+        Some(start)
+    }
+
+    pub fn save(&mut self, track: &str, start: u64) {
+        self.restorable = None;
+        if let Err(err) = self
+            .db
+            .set("track", &track)
+            .and_then(|_| self.db.set("start", &start))
+            .and_then(|_| self.db.set("updated", &now()))
+        {
+            debug_log!(self.debug_log, "Could not save last activity: {}", err);
+        }
+        self.touched = Instant::now();
+    }
+
+    // The same activity is still shown
+    pub fn touch(&mut self) {
+        if self.touched.elapsed() < TOUCH_INTERVAL {
+            return;
+        }
+        if let Err(err) = self.db.set("updated", &now()) {
+            debug_log!(self.debug_log, "Could not save last activity: {}", err);
+        }
+        self.touched = Instant::now();
+    }
+}
@@ -0,0 +1,243 @@
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::debug_log;
+
+// Translations compiled into the binary, English texts are the defaults below
+const BUNDLED_LOCALES: &[(&str, &str)] = &[("pl", include_str!("../locales/pl.yaml"))];
+
+// Built-in texts displayed in the activity and console, can be translated with a locale file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Strings {
+    pub playing: String,
+    pub paused: String,
+    pub album_prefix: String,
+    pub artist_prefix: String,
+    pub live: String,
+    pub loved: String,
+    pub loved_on_lastfm: String,
+    pub button_yt: String,
+    pub button_lastfm: String,
+    pub button_lastfm_track: String,
+    pub button_listenbrainz: String,
+    pub button_musicbrainz: String,
+    pub button_play: String,
+    pub button_watch: String,
+    pub button_spotify: String,
+    pub button_shameless_ad: String,
+    pub button_stream: String,
+    pub button_apple_music: String,
+    pub button_tidal: String,
+    pub button_deezer: String,
+    pub button_qobuz: String,
+    pub messages: Messages,
+    pub help: HashMap<String, String>, // Subcommand descriptions, "subcommand [nested]": "text"
+}
+
+impl Default for Strings {
+    fn default() -> Strings {
+        Strings {
+            playing: String::from("playing"),
+            paused: String::from("paused"),
+            album_prefix: String::from("album: "),
+            artist_prefix: String::from("by: "),
+            live: String::from("LIVE"),
+            loved: String::from("❤"),
+            loved_on_lastfm: String::from("Loved on Last.fm"),
+            button_yt: String::from("Search this song on YouTube"),
+            button_lastfm: String::from("Last.fm profile"),
+            button_lastfm_track: String::from("Open on Last.fm"),
+            button_listenbrainz: String::from("Listenbrainz profile"),
+            button_musicbrainz: String::from("Open on MusicBrainz"),
+            button_play: String::from("Play Now"),
+            button_watch: String::from("Watch Now"),
+            button_spotify: String::from("Play on Spotify"),
+            button_shameless_ad: String::from("Get This RPC"),
+            button_stream: String::from("Watch Stream"),
+            button_apple_music: String::from("Open in Apple Music"),
+            button_tidal: String::from("Listen on Tidal"),
+            button_deezer: String::from("Listen on Deezer"),
+            button_qobuz: String::from("Listen on Qobuz"),
+            messages: Messages::default(),
+            help: HashMap::new(),
+        }
+    }
+}
+
+// Console messages of the main loop, {name} is replaced with a value
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Messages {
+    pub player_found: String,
+    pub player_found_media_control: String,
+    pub waiting_for_player: String,
+    pub waiting_for_allowlist_player: String,
+    pub waiting_for_allowlist_player_media_control: String,
+    pub discord_connected: String,
+    pub discord_waiting: String,
+    pub discord_reconnected: String,
+    pub discord_reconnect_waiting: String,
+    pub metadata_error: String,
+    pub activity_set: String,
+    pub activity_error: String,
+}
+
+impl Default for Messages {
+    fn default() -> Messages {
+        Messages {
+            player_found: String::from("Found active player with MPRIS support."),
+            player_found_media_control: String::from("Found active player using media-control."),
+            waiting_for_player: String::from(
+                "Could not find any player with MPRIS support. Waiting for any player...",
+            ),
+            waiting_for_allowlist_player: String::from(
+                "Could not find any active player from your allowlist with MPRIS support. Waiting for any player from your allowlist...",
+            ),
+            waiting_for_allowlist_player_media_control: String::from(
+                "Could not find any active player from your allowlist. Waiting for any player from your allowlist...",
+            ),
+            discord_connected: String::from("Connected to Discord."),
+            discord_waiting: String::from(
+                "Could not connect to Discord. Waiting for discord to start...",
+            ),
+            discord_reconnected: String::from("Reconnected to Discord."),
+            discord_reconnect_waiting: String::from(
+                "Could not reconnect to Discord. Waiting for discord to start...",
+            ),
+            metadata_error: String::from("Could not get metadata from player: {error}"),
+            activity_set: String::from("=> Set activity [{status}]: {song}"),
+            activity_error: String::from("Could not set activity."),
+        }
+    }
+}
+
+impl Strings {
+    // Labels from button_labels setting have priority over the locale file
+    pub fn apply_button_labels(&mut self, labels: &HashMap<String, String>) {
+        for (button, label) in labels {
+            let label = label.clone();
+            match button.as_str() {
+                "yt" => self.button_yt = label,
+                "lastfm" => self.button_lastfm = label,
+                "lastfmTrack" => self.button_lastfm_track = label,
+                "listenbrainz" => self.button_listenbrainz = label,
+                "musicbrainz" => self.button_musicbrainz = label,
+                "mprisUrl" => {
+                    self.button_play = label.clone();
+                    self.button_watch = label;
+                }
+                "spotify" => self.button_spotify = label,
+                "shamelessAd" => self.button_shameless_ad = label,
+                "stream" => self.button_stream = label,
+                "appleMusic" => self.button_apple_music = label,
+                "tidal" => self.button_tidal = label,
+                "deezer" => self.button_deezer = label,
+                "qobuz" => self.button_qobuz = label,
+                _ => println!(
+                    "\x1b[33mWARNING: Unknown button in button_labels: {}\x1b[0m",
+                    button
+                ),
+            }
+        }
+    }
+}
+
+// Replace {name} in message with values
+pub fn fill(message: &str, values: &[(&str, &str)]) -> String {
+    let mut message = message.to_string();
+    for (name, value) in values {
+        message = message.replace(&format!("{{{}}}", name), value);
+    }
+    message
+}
+
+// Language code from locale setting or from LC_ALL, LC_MESSAGES, LANG, e.g. "pl_PL.UTF-8" => "pl"
+pub fn get_language(locale: Option<&str>) -> String {
+    let locale = match locale.map(str::trim) {
+        Some(locale) if !locale.is_empty() && locale != "auto" => locale.to_string(),
+        _ => ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default(),
+    };
+    locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+// Values from overlay replace values in base, nested maps are merged
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        // Empty file or key
+        (_, Value::Null) => {}
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Bundled translation of selected language, then texts from user locale file.
+// Missing keys use the default English text.
+pub fn load(locale: Option<&str>, locale_file: Option<&Path>, debug_log: bool) -> Strings {
+    let language = get_language(locale);
+    let mut yaml = Value::Mapping(Default::default());
+
+    match BUNDLED_LOCALES.iter().find(|(code, _)| *code == language) {
+        Some((_, text)) => match serde_yaml::from_str(text) {
+            Ok(translation) => merge(&mut yaml, translation),
+            Err(err) => println!(
+                "\x1b[31mWARNING: Bundled locale \"{}\" has errors: {}\x1b[0m",
+                language, err
+            ),
+        },
+        None => debug_log!(
+            debug_log,
+            "No bundled translation for language \"{}\", using English.",
+            language
+        ),
+    }
+
+    if let Some(path) = locale_file {
+        let translation = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| serde_yaml::from_str(&text).map_err(|err| err.to_string()));
+        match translation {
+            Ok(translation) => {
+                merge(&mut yaml, translation);
+                debug_log!(debug_log, "Locale loaded from file: {}", path.display());
+            }
+            Err(err) => println!(
+                "\x1b[31mWARNING: Locale file {} has errors and will not be used: {}\x1b[0m",
+                path.display(),
+                err
+            ),
+        }
+    }
+
+    match serde_yaml::from_value::<Strings>(yaml) {
+        Ok(strings) => strings,
+        Err(err) => {
+            println!(
+                "\x1b[31mWARNING: Could not load translation, using English: {}\x1b[0m",
+                err
+            );
+            Strings::default()
+        }
+    }
+}
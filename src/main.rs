@@ -1,21 +1,71 @@
+use discord_rich_presence::activity;
 use discord_rich_presence::activity::StatusDisplayType;
-use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
-use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use pickledb::PickleDbDumpPolicy;
 use url_escape;
 
-#[cfg(target_os = "linux")]
-use mpris::PlayerFinder;
-
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::ops::Sub;
 use std::path::PathBuf;
 use std::thread::sleep;
-use std::time::{Duration, SystemTime};
-
+use std::time::{Duration, Instant, SystemTime};
+
+mod ads;
+mod album_cache;
+mod album_info;
+mod apple_music;
+mod browser_extension;
+mod build_info;
+mod cache_db;
+mod card;
+mod clock;
 mod config_editor;
+mod config_migration;
+mod control;
+mod cover_cache;
+mod cover_folder;
+mod cover_lookup;
+mod dbus_health;
+mod diagnostics;
+mod discord_clients;
+mod discord_socket;
+mod episode;
+mod games;
+mod hooks;
+mod last_activity;
+mod locale;
+mod media_state;
+mod metrics;
+mod mpv;
+mod network_worker;
+mod pipewire;
+mod player_match;
+mod player_watch;
+mod playlist;
+mod presence_budget;
+mod processes;
+mod quirks;
+mod recording;
+mod refresh;
+mod repeat;
+mod report;
+mod scripting;
+mod service_links;
 mod settings;
+mod snapcast;
+mod sources;
+mod square_cover;
+mod store_covers;
+mod streaming;
+mod tags;
+mod template;
+mod theaudiodb;
+mod update;
 mod utils;
+mod video_title;
+mod vlc;
+mod youtube;
 
 // Load api key from .env file durning compilation
 const LASTFM_API_KEY: &'static str = match option_env!("LASTFM_API_KEY") {
@@ -23,6 +73,9 @@ const LASTFM_API_KEY: &'static str = match option_env!("LASTFM_API_KEY") {
     None => "",
 };
 
+// Max time to wait for album cover before the activity is set without it, enough for cache hits
+const COVER_WAIT_TIME: Duration = Duration::from_millis(300);
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set home path, If $HOME is not set, do not write or read anything from the user's disk
     let (home_exists, home_dir) = match env::var("HOME") {
@@ -54,11 +107,60 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
         Some(settings::Commands::Restart {}) => utils::restart_service(),
-        Some(settings::Commands::Config {}) => config_editor::setup(),
+        Some(settings::Commands::Config {}) => config_editor::setup(settings.config_file.clone()),
+        Some(settings::Commands::Setup {}) => config_editor::wizard(settings.config_file.clone()),
+        Some(settings::Commands::Cache { action }) => match action {
+            settings::CacheCommands::Prune {} => {
+                cover_cache::prune_command(settings.cover_cache_max_size.unwrap_or(50))
+            }
+        },
+        Some(settings::Commands::Cover { ref action }) => match action {
+            settings::CoverCommands::Set { album, url } => album_cache::set_command(album, url),
+            settings::CoverCommands::Unset { album } => album_cache::unset_command(album),
+        },
+        Some(settings::Commands::Lastfm { ref action }) => match action {
+            settings::LastfmCommands::Test { artist, album } => utils::test_lastfm_api_key(
+                settings.lastfm_api_key.as_deref().unwrap_or(LASTFM_API_KEY),
+                settings.lastfm_api_key.is_none(),
+                artist,
+                album,
+            ),
+        },
+        Some(settings::Commands::Ctl { ref command }) => control::send_command(command),
+        Some(settings::Commands::Tail {}) => control::tail(),
+        Some(settings::Commands::Report { ref output }) => {
+            report::run(&settings, output.as_deref())
+        }
+        Some(settings::Commands::Update { check }) => update::run(check),
+        // Printed in load_settings, before config file is loaded
+        Some(settings::Commands::Completions { .. }) | Some(settings::Commands::Manpage {}) => {}
         None => {}
     }
     #[cfg(target_os = "macos")]
     match settings.suboptions.command {
+        Some(settings::Commands::Cache { action }) => match action {
+            settings::CacheCommands::Prune {} => {
+                cover_cache::prune_command(settings.cover_cache_max_size.unwrap_or(50))
+            }
+        },
+        Some(settings::Commands::Cover { ref action }) => match action {
+            settings::CoverCommands::Set { album, url } => album_cache::set_command(album, url),
+            settings::CoverCommands::Unset { album } => album_cache::unset_command(album),
+        },
+        Some(settings::Commands::Lastfm { ref action }) => match action {
+            settings::LastfmCommands::Test { artist, album } => utils::test_lastfm_api_key(
+                settings.lastfm_api_key.as_deref().unwrap_or(LASTFM_API_KEY),
+                settings.lastfm_api_key.is_none(),
+                artist,
+                album,
+            ),
+        },
+        Some(settings::Commands::Ctl { ref command }) => control::send_command(command),
+        Some(settings::Commands::Tail {}) => control::tail(),
+        Some(settings::Commands::Report { ref output }) => {
+            report::run(&settings, output.as_deref())
+        }
+        Some(settings::Commands::Update { check }) => update::run(check),
         Some(_) => {
             println!("Subcommands to manage the daemon are not available on macOS.");
             println!(
@@ -71,33 +173,105 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // User settings
 
+    // Diagnostic snapshot for bug reports
+    if settings.json && (settings.list_players || settings.get_player_id) {
+        diagnostics::print_json(&settings);
+        return Ok(());
+    }
+
+    // Fallback sources for players without (working) MPRIS support
+    let fallbacks = sources::get_fallbacks(&settings);
+
     // Use api key provided by user
     let lastfm_api_key = settings.lastfm_api_key.unwrap_or(LASTFM_API_KEY.into());
     if lastfm_api_key.is_empty() {
-        println!("\x1b[31mWARNING: Last.fm API key is not set. Album covers from Last.fm will not be available.\x1b[0m");
+        println!("\x1b[31mWARNING: Last.fm API key is not set. Album covers from Last.fm will not be available, MusicBrainz, iTunes and Deezer will be used instead.\x1b[0m");
     }
 
-    // Main loop interval
-    let mut interval = settings.interval.unwrap_or(10);
-    if interval < 5 {
-        interval = 5
+    // Main loop interval, interval_ms allows refreshing faster than the 5 seconds minimum
+    let interval = refresh::configured_interval(
+        settings.interval,
+        settings.interval_ms,
+        settings.min_interval_ms,
+    );
+    debug_log!(settings.debug_log, "interval: {:?}", interval);
+    // Connecting to Discord and D-Bus is retried at most every 5 seconds, even with shorter interval
+    let retry_interval = interval.max(Duration::from_secs(5));
+    let mut refresh_interval =
+        refresh::RefreshInterval::new(interval, !settings.disable_adaptive_interval);
+    let mut streaming_detector = streaming::StreamingDetector::new(&settings.streaming_processes);
+    let mut game_detector = games::GameDetector::new(&settings.game_processes);
+
+    // Prometheus metrics endpoint
+    if let Some(address) = &settings.metrics_address {
+        metrics::start_server(address, settings.debug_log);
     }
-    debug_log!(settings.debug_log, "interval: {}", interval);
+
+    // Control socket for playback commands
+    if !settings.disable_control_socket {
+        control::start_server(settings.dbus_address.clone(), settings.debug_log);
+    }
+    control::handle_sighup();
+
+    // User script, by default script.rhai in config directory
+    let script = settings
+        .script_file
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| {
+            utils::get_app_config_dir()
+                .map(|path| path.join("script.rhai"))
+                .filter(|path| path.exists())
+        })
+        .and_then(|path| scripting::load(&path, settings.debug_log));
+
+    // Texts displayed in the activity and console, in language from settings or LANG
+    let locale_file = settings.locale_file.as_ref().map(PathBuf::from);
+    let mut strings = locale::load(
+        settings.locale.as_deref(),
+        locale_file.as_deref(),
+        settings.debug_log,
+    );
+    strings.apply_button_labels(&settings.button_labels);
 
     // Nicknames for buttons
     let lastfm_name = settings.lastfm_name.unwrap_or_default();
     let listenbrainz_name = settings.listenbrainz_name.unwrap_or_default();
 
-    // "Listening to ..."
+    // "Listening to ...", status_display replaces the part chosen by rpc_name
     let rpc_name = settings.rpc_name.unwrap_or(String::from("artist"));
+    let status_display =
+        settings
+            .status_display
+            .clone()
+            .unwrap_or(String::from(match rpc_name.as_str() {
+                "track" => "details",
+                "none" => "name",
+                _ => "state",
+            }));
+
+    // Clear activity on player change or keep it until the new player's activity is published
+    let player_change_behavior = settings
+        .player_change_behavior
+        .clone()
+        .unwrap_or(String::from("clear"));
+    // Set on player change if Discord connection (audio or video presence) can be reused
+    let mut reused_connection: Option<bool> = None;
+
+    // What to do when player reports stopped
+    let stopped_behavior = settings.stopped_behavior.unwrap_or(String::from("paused"));
+    #[cfg(target_os = "linux")]
+    let allowlist_sort_by_priority = settings.allowlist_sort_by.as_deref() == Some("priority");
+    let stopped_keep_time = Duration::from_secs(settings.stopped_keep_time.unwrap_or(30));
+
+    // What to do while an advertisement plays
+    let ad_behavior = settings.ad_behavior.unwrap_or(String::from("show"));
+
+    // Time after track change in which position regressions are not seeks
+    let transition_window = Duration::from_secs(settings.transition_window.unwrap_or(5));
 
     // Icon displayed next to the album cover
     let small_image = settings.small_image.unwrap_or(String::from("playPause"));
-    let mut lastfm_avatar = String::new();
-    if small_image == "lastfmAvatar" && !lastfm_name.is_empty() {
-        lastfm_avatar = utils::get_lastfm_avatar(&lastfm_name, &lastfm_api_key);
-        debug_log!(settings.debug_log, "lastfm_avatar: {}", lastfm_avatar);
-    }
     let lastfm_icon_text = if !lastfm_name.is_empty() {
         lastfm_name.to_string() + " on Last.fm"
     } else {
@@ -108,11 +282,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let force_player_name = settings.force_player_name.unwrap_or_default();
     let force_player_id = settings.force_player_id.unwrap_or_default();
 
-    // Enable/disable use of cache
-    let mut cache_enabled: bool = !settings.disable_cache;
-    if !home_exists {
-        cache_enabled = false;
-    }
+    // Cache is always kept in memory and also stored on disk when possible (portable mode doesn't
+    // need $HOME), so covers are not fetched again for every track even without disk access
+    let cache_enabled: bool = !settings.disable_cache;
+    let mut cache_on_disk = cache_enabled && (home_exists || utils::is_portable());
 
     // Allowlist of music players
     let allowlist_enabled: bool = match settings.allowlist.len() {
@@ -127,31 +300,56 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut last_album_artist: String = String::new();
     let mut last_album_id: String = String::new();
     let mut last_track_position: u64 = 0;
+    #[cfg(target_os = "linux")]
+    let mut last_position_read = Instant::now();
+    let mut last_track_id: String = String::new();
+    let mut last_rating: Option<f64> = None;
+    let mut last_playlist_context = playlist::PlaylistContext::default();
+    let mut last_track_change = Instant::now();
+    let mut repeat_counter = repeat::RepeatCounter::new();
+    let mut last_activity_update = Instant::now();
+    let mut clock_watch = clock::ClockWatch::new();
     let mut last_is_playing: bool = false;
+    let mut last_player_name: String = String::new();
+    let mut last_bus_name: Option<String> = None; // Tells apart instances of the same player
+    let mut last_custom_status: String = String::new();
 
     let mut _cover_url: String = "".to_string();
+    let mut cover_source: &str = "";
+    let mut current_album_info = album_info::AlbumInfo::default();
     let mut is_first_time_audio: bool = true;
     let mut is_first_time_video: bool = true;
     let mut is_interrupted: bool = false;
     let mut is_activity_set: bool = false;
+    let mut is_streaming: bool = false;
+    let mut is_game_running: bool = false;
+    let mut is_over_budget: bool = false;
 
     // Preventing stdout spam while waiting for player or discord
     #[cfg(target_os = "linux")]
-    let mut dbus_notif: bool = false;
+    let mut dbus_health = dbus_health::DbusHealth::new(retry_interval, settings.debug_log);
     let mut player_notif: u8 = 0;
     let mut discord_notif: bool = false;
 
-    let mut client_audio = DiscordIpcClient::new("1129859263741837373");
-    let mut client_video = DiscordIpcClient::new("1356756023813210293");
-    let mut client: &mut DiscordIpcClient = &mut client_audio;
+    let new_client = |client_id: &str| {
+        discord_clients::DiscordClients::new(
+            client_id,
+            settings.multiple_discord_clients,
+            &settings.discord_sockets,
+            settings.debug_log,
+        )
+    };
+    let mut client_audio = new_client("1129859263741837373");
+    let mut client_video = new_client("1356756023813210293");
+    let mut client: &mut discord_clients::DiscordClients = &mut client_audio;
 
     // Set cache path
-    let cache_dir = match env::var("XDG_CACHE_HOME") {
-        Ok(xgd_cache_home) => PathBuf::from(xgd_cache_home).join("music-discord-rpc"),
-        Err(_) => home_dir.join(".cache/music-discord-rpc"),
-    };
+    let cache_dir =
+        utils::get_app_cache_dir().unwrap_or(home_dir.join(".cache").join("music-discord-rpc"));
+    let cover_cache_max_size = settings.cover_cache_max_size.unwrap_or(50);
+    let album_cache_max_entries = settings.album_cache_max_entries.unwrap_or(10000);
 
-    if cache_enabled {
+    if cache_on_disk {
         debug_log!(
             settings.debug_log,
             "Cache location: {}",
@@ -159,34 +357,197 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
         if let Err(err) = fs::create_dir_all(&cache_dir) {
             println!("Could not create cache directory: {}", err);
+            cache_on_disk = false;
         }
     }
+    if cache_enabled && !cache_on_disk {
+        println!("Cache is kept only in memory and will be lost on exit.");
+    }
 
     // Cache file
     let db_path = cache_dir.join("album_cache.db");
-    let mut album_cache = match PickleDb::load(
-        &db_path,
-        PickleDbDumpPolicy::AutoDump,
-        SerializationMethod::Json,
-    ) {
-        Ok(db) => {
-            if cache_enabled {
-                println!("Cache loaded from file: {}", &db_path.display());
-            }
-            db
+    let access_db_path = cache_dir.join("album_cache_access.db");
+    if cache_on_disk {
+        album_cache::migrate_keys(&cache_dir, settings.debug_log);
+        album_cache::compact(
+            &db_path,
+            &access_db_path,
+            album_cache_max_entries,
+            settings.debug_log,
+        );
+    }
+    let album_cache = if !cache_on_disk {
+        album_cache::memory_db()
+    } else {
+        let db = cache_db::load(&db_path, || PickleDbDumpPolicy::AutoDump);
+        // New file is written on the first change
+        match db_path.exists() {
+            true => println!("Cache loaded from file: {}", &db_path.display()),
+            false => println!("Generated new cache file: {}", &db_path.display()),
         }
-        Err(_) => {
-            if cache_enabled {
-                println!("Generated new cache file: {}", &db_path.display());
-            }
-            PickleDb::new(
-                &db_path,
-                PickleDbDumpPolicy::AutoDump,
-                SerializationMethod::Json,
-            )
+        db
+    };
+
+    // Album details from MusicBrainz and artist bio are fetched only if used in any template
+    let used_in_templates = |names: &[&str]| {
+        [
+            &settings.large_text_format,
+            &settings.details_format,
+            &settings.state_format,
+        ]
+        .into_iter()
+        .chain(
+            settings
+                .player_quirks
+                .values()
+                .map(|quirks| &quirks.details_format),
+        )
+        .filter_map(|format| format.as_ref())
+        .any(|format| names.iter().any(|name| format.contains(name)))
+    };
+    let album_info_enabled = used_in_templates(&["{year}", "{tracks}"]);
+    let theaudiodb_api_key = settings.theaudiodb_api_key.clone().unwrap_or_default();
+    let artist_bio_enabled = !theaudiodb_api_key.is_empty() && used_in_templates(&["{bio}"]);
+    // Tags of local files are read for the default hover text or if used in any template
+    let tags_enabled = settings.large_text_format.is_none()
+        || used_in_templates(&["{year}", "{label}", "{catalog}"]);
+    let mut tag_info = tags::TagInfo::default();
+    let mut last_tag_url = String::new();
+    // Played and remaining time in text are refreshed periodically, not only on changes
+    let time_text_interval = used_in_templates(&["{elapsed}", "{remaining}"])
+        .then(|| Duration::from_secs(settings.time_text_interval.unwrap_or(15)));
+    // Loved status is checked on Last.fm only for the heart icon or if used in any template
+    let lastfm_loved_enabled = !lastfm_name.is_empty()
+        && !lastfm_api_key.is_empty()
+        && (settings.lastfm_loved_icon || used_in_templates(&["{loved}"]));
+    // Track list position and playlist name are read from the player only if used in any template
+    #[cfg(target_os = "linux")]
+    let mut playlist_reader = used_in_templates(&["{queue}", "{playlist}"]).then(|| {
+        playlist::PlaylistReader::new(settings.dbus_address.as_deref(), settings.debug_log)
+    });
+
+    // Images dropped into the cover folder override other cover sources
+    let cover_folder_dir = match settings.cover_folder {
+        true => cover_folder::covers_dir(),
+        false => None,
+    };
+    if let Some(dir) = &cover_folder_dir {
+        if let Err(err) = fs::create_dir_all(dir) {
+            println!("Could not create cover folder: {}", err);
         }
+    }
+
+    // Processed artwork is uploaded like images from the cover folder
+    let square_covers_upload_url = match (settings.square_covers, &settings.cover_upload_url) {
+        (true, Some(upload_url)) if !upload_url.is_empty() => Some(upload_url.clone()),
+        (true, _) => {
+            println!("\x1b[33mWARNING: square_covers requires cover_upload_url, artwork will not be changed.\x1b[0m");
+            None
+        }
+        (false, _) => None,
     };
 
+    // HTTP requests are made in background thread, which owns the cache
+    let mut network_worker = network_worker::NetworkWorker::start(network_worker::WorkerState {
+        cache_enabled,
+        album_cache,
+        album_access: match cache_on_disk {
+            true => album_cache::load_access_db(&access_db_path, true),
+            false => album_cache::memory_db(),
+        },
+        album_cache_max_entries,
+        artist_variants: cover_lookup::load_variants_db(
+            &cache_dir.join("artist_variants.db"),
+            cache_on_disk,
+        ),
+        album_info_db: album_info::load_db(&cache_dir.join("album_info.db"), cache_on_disk),
+        album_info_enabled,
+        cache_dir: cache_dir.clone(),
+        cache_fsync: cache_on_disk && settings.cache_dump_policy.as_deref() == Some("fsync"),
+        cover_cache_max_size,
+        lastfm_api_key: lastfm_api_key.clone(),
+        theaudiodb_api_key,
+        artist_info: HashMap::new(),
+        artist_bio_enabled,
+        cover_overrides: settings.cover_overrides.clone(),
+        cover_folder: cover_folder_dir.clone().map(|dir| {
+            cover_folder::CoverFolder::new(
+                dir,
+                settings.cover_upload_url.clone().unwrap_or_default(),
+                &cache_dir,
+                cache_on_disk,
+                settings.debug_log,
+            )
+        }),
+        use_musicbrainz: !settings.disable_musicbrainz_cover,
+        use_stores: !settings.disable_store_covers,
+        parallel_cover_lookup: settings.cover_lookup_strategy.as_deref() == Some("parallel"),
+        youtube_thumbnail_quality: settings
+            .youtube_thumbnail_quality
+            .clone()
+            .unwrap_or(String::from("max")),
+        square_covers: square_covers_upload_url.map(|upload_url| square_cover::SquareCovers {
+            crop: settings.square_covers_mode.as_deref() == Some("crop"),
+            rounded: settings.square_covers_rounded,
+            upload_url,
+        }),
+        artwork_upload_url: settings
+            .cover_upload_url
+            .clone()
+            .filter(|upload_url| !upload_url.is_empty()),
+        debug_log: settings.debug_log,
+    });
+    // Started player is found right away instead of after the interval
+    #[cfg(target_os = "linux")]
+    player_watch::start(
+        settings.dbus_address.clone(),
+        network_worker.waker(),
+        settings.debug_log,
+    );
+
+    // Daily limit of time with visible activity
+    let mut presence_budget = settings.daily_presence_limit.map(|limit| {
+        presence_budget::PresenceBudget::new(
+            limit,
+            settings.daily_presence_reset_hour.unwrap_or(0),
+            &cache_dir,
+            cache_on_disk,
+            settings.debug_log,
+        )
+    });
+
+    let mut cover_folder_watch = cover_folder_dir.map(cover_folder::FolderWatch::new);
+
+    // Track published before restart, its start time is kept
+    let mut last_activity =
+        last_activity::LastActivity::new(&cache_dir, cache_on_disk, settings.debug_log);
+
+    // Request of cover that wasn't found in time, activity is updated when it's ready
+    let mut pending_cover: Option<u64> = None;
+
+    // Image with the current track for OBS or screenshots
+    let mut now_playing_card = match settings.now_playing_card {
+        true => match card::NowPlayingCard::new(
+            &cache_dir,
+            cache_on_disk && settings.cache_cover_images,
+            settings.now_playing_card_font.as_deref(),
+        ) {
+            Ok(card) => {
+                println!("Now playing card: {}", card.path().display());
+                Some(card)
+            }
+            Err(err) => {
+                println!("\x1b[31mCould not create now playing card: {}\x1b[0m", err);
+                None
+            }
+        },
+        false => None,
+    };
+
+    if small_image == "lastfmAvatar" && !lastfm_name.is_empty() {
+        network_worker.request_lastfm_avatar(&lastfm_name);
+    }
+
     loop {
         debug_log!(
             settings.debug_log,
@@ -195,23 +556,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // On Linux try to connect to MPRIS
         #[cfg(target_os = "linux")]
-        let player = match PlayerFinder::new() {
-            Ok(player) => {
-                dbus_notif = false;
-                player
-            }
+        let player = match utils::new_player_finder(settings.dbus_address.as_deref()) {
+            Ok(player) => player,
             Err(err) => {
-                if !dbus_notif {
-                    println!("Could not connect to D-Bus: {}", err);
-                    dbus_notif = true;
-                }
-                sleep(Duration::from_secs(interval));
+                sleep(dbus_health.failure(&err));
                 continue;
             }
         };
 
         // List available players and exit
         if settings.list_players {
+            // Names of listed players, checked against the one added with --save
+            let mut listed_names: Vec<String> = Vec::new();
+
             #[cfg(target_os = "linux")]
             match player.find_all() {
                 Ok(player_list) => {
@@ -223,7 +580,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         println!("List of available music players with MPRIS support:");
                         for music_player in &player_list {
                             if music_player.bus_name() != "org.mpris.MediaPlayer2.playerctld" {
-                                println!(" * {}", music_player.identity());
+                                let names = utils::get_player_names(
+                                    music_player,
+                                    settings.dbus_address.as_deref(),
+                                );
+                                // Instances of the same player differ only by the bus name
+                                let instances = player_list
+                                    .iter()
+                                    .filter(|other| other.identity() == music_player.identity())
+                                    .count();
+                                match instances > 1 {
+                                    true => println!(
+                                        " * {} ({}) [instance, {}]",
+                                        names[0],
+                                        names[1..].join(", "),
+                                        match music_player.get_playback_status() {
+                                            Ok(mpris::PlaybackStatus::Playing) => "playing",
+                                            Ok(mpris::PlaybackStatus::Paused) => "paused",
+                                            _ => "stopped",
+                                        }
+                                    ),
+                                    false => {
+                                        println!(" * {} ({})", names[0], names[1..].join(", "))
+                                    }
+                                }
+                                listed_names.extend(names);
                             }
                         }
                         println!("");
@@ -238,6 +619,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             r#" music-discord-rpc -a "{}" -a "Second Player" -a "Any other player""#,
                             player_list[0].identity()
                         );
+                        println!();
+                        println!("Or save the player to the allowlist in the config file:");
+                        println!();
+                        println!(
+                            r#" music-discord-rpc -l --save "{}""#,
+                            player_list[0].identity()
+                        );
                     }
                 }
                 Err(_) => {
@@ -256,6 +644,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 match utils::get_currently_playing() {
                     Ok(player) => {
                         println!("Player name: {}", player.player_id);
+                        listed_names.push(player.player_id.clone());
                         println!("");
                         println!(
                             "You can use this name together with the -a flag to add this player to the allowlist:"
@@ -274,66 +663,155 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 };
             }
 
+            for fallback in &fallbacks {
+                if fallback.is_available() {
+                    println!();
+                    println!(
+                        "{} is also available through {}",
+                        fallback.identity(),
+                        fallback.description()
+                    );
+                    println!(
+                        r#"Use "{}" as the player name to add it to the allowlist."#,
+                        fallback.identity()
+                    );
+                    listed_names.push(fallback.identity().to_string());
+                }
+            }
+
+            // Chosen player is added to the allowlist in config file, without editing it by hand
+            if let Some(name) = &settings.save {
+                if !player_match::matches(name, &listed_names) {
+                    println!(
+                        "\n\x1b[33mWARNING: \"{}\" doesn't match any player listed above, it's saved anyway.\x1b[0m",
+                        name
+                    );
+                }
+                config_editor::save_allowlist_player(settings.config_file.clone(), name);
+            }
+
             return Ok(());
         }
 
         // Find active player (and filter them by name if enabled)
         #[cfg(target_os = "linux")]
         let player_finder = if allowlist_enabled {
-            utils::allowlist_player_finder(&player, &settings.allowlist, settings.debug_log)
+            utils::allowlist_player_finder(
+                &player,
+                &settings.allowlist,
+                &settings.allowlist_priorities,
+                allowlist_sort_by_priority,
+                settings.dbus_address.as_deref(),
+                stopped_behavior == "paused",
+                last_bus_name.as_deref(),
+                settings.debug_log,
+            )
         } else {
-            player.find_active()
+            utils::active_player_finder(&player, last_bus_name.as_deref())
         };
+        #[cfg(target_os = "linux")]
+        match &player_finder {
+            Err(mpris::FindingError::DBusError(err)) => {
+                dbus_health.failure(err);
+            }
+            _ => dbus_health.success(),
+        }
 
-        // Connect with player
+        // Connect with player (or fallback source)
         #[cfg(target_os = "linux")]
-        let player = match player_finder {
-            Ok(player) => {
+        let player = match sources::select_source(
+            player_finder,
+            &fallbacks,
+            &settings.allowlist,
+            settings.debug_log,
+        ) {
+            Some(player) => {
                 if player_notif != 1 {
-                    println!("Found active player with MPRIS support.");
+                    println!("{}", strings.messages.player_found);
                     player_notif = 1;
                 }
+                control::set_target(Some(player.control_target()));
+                refresh_interval.player_found();
                 player
             }
-            Err(_) => {
+            None => {
+                if player_notif == 1 {
+                    hooks::run(
+                        &settings.hooks,
+                        hooks::HookEvent::Stop,
+                        &last_player_name,
+                        None,
+                        "",
+                        settings.debug_log,
+                    );
+                    hooks::update_custom_status(
+                        &settings.custom_status,
+                        &mut last_custom_status,
+                        "",
+                        settings.debug_log,
+                    );
+                    if let Some(card) = &mut now_playing_card {
+                        card.clear();
+                    }
+                    control::publish_event("player", "No player found");
+                    media_state::clear();
+                }
                 if player_notif != 2 {
                     if allowlist_enabled {
-                        println!(
-                            "Could not find any active player from your allowlist with MPRIS support. Waiting for any player from your allowlist..."
-                        );
+                        println!("{}", strings.messages.waiting_for_allowlist_player);
                     } else {
-                        println!(
-                            "Could not find any player with MPRIS support. Waiting for any player..."
-                        );
+                        println!("{}", strings.messages.waiting_for_player);
                     }
 
                     player_notif = 2;
                     discord_notif = false;
                 }
 
+                control::set_target(None);
                 is_interrupted = true;
                 utils::clear_activity(&mut is_activity_set, &mut client);
-                sleep(Duration::from_secs(interval));
+                refresh_interval.no_player();
+                // Wait longer while D-Bus is failing
+                network_worker.wait_for_player(refresh_interval.get().max(dbus_health.backoff()));
                 continue;
             }
         };
 
         // On macOS use media info fetching function to determine if anything is playing now
         #[cfg(target_os = "macos")]
-        let player = match utils::get_currently_playing() {
+        let player = match sources::get_currently_playing(
+            &fallbacks,
+            &settings.allowlist,
+            settings.debug_log,
+        ) {
             Ok(player) => {
                 if allowlist_enabled {
-                    let mut is_player_on_allowlist = false;
-                    for allowlist_entry in &settings.allowlist {
-                        if *allowlist_entry == player.player_id {
-                            is_player_on_allowlist = true;
-                            break;
+                    if !player_match::is_listed(&settings.allowlist, &[&player.player_id]) {
+                        if player_notif == 1 {
+                            hooks::run(
+                                &settings.hooks,
+                                hooks::HookEvent::Stop,
+                                &last_player_name,
+                                None,
+                                "",
+                                settings.debug_log,
+                            );
+                            hooks::update_custom_status(
+                                &settings.custom_status,
+                                &mut last_custom_status,
+                                "",
+                                settings.debug_log,
+                            );
+                            if let Some(card) = &mut now_playing_card {
+                                card.clear();
+                            }
+                            control::publish_event("player", "No player found");
+                            media_state::clear();
                         }
-                    }
-                    if !is_player_on_allowlist {
                         if player_notif != 2 {
                             println!(
-                            	"Could not find any active player from your allowlist. Waiting for any player from your allowlist..."
+                                "{}",
+                                strings.messages.waiting_for_allowlist_player_media_control
                             );
                             player_notif = 2;
                             discord_notif = false;
@@ -341,18 +819,49 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                         is_interrupted = true;
                         utils::clear_activity(&mut is_activity_set, &mut client);
-                        sleep(Duration::from_secs(interval));
+                        refresh_interval.no_player();
+                        sleep(refresh_interval.get());
                         continue;
                     }
                 }
 
                 if player_notif != 1 {
-                    println!("Found active player using media-control.");
+                    println!("{}", strings.messages.player_found_media_control);
                     player_notif = 1;
                 }
+
+                // Fallback backends use their name as player_id
+                let target = fallbacks
+                    .iter()
+                    .find(|fallback| fallback.identity() == player.player_id)
+                    .map(|fallback| control::ControlTarget::Fallback(fallback.clone()))
+                    .unwrap_or(control::ControlTarget::MediaControl);
+                control::set_target(Some(target));
+                refresh_interval.player_found();
                 player
             }
             Err(e) => {
+                if player_notif == 1 {
+                    hooks::run(
+                        &settings.hooks,
+                        hooks::HookEvent::Stop,
+                        &last_player_name,
+                        None,
+                        "",
+                        settings.debug_log,
+                    );
+                    hooks::update_custom_status(
+                        &settings.custom_status,
+                        &mut last_custom_status,
+                        "",
+                        settings.debug_log,
+                    );
+                    if let Some(card) = &mut now_playing_card {
+                        card.clear();
+                    }
+                    control::publish_event("player", "No player found");
+                    media_state::clear();
+                }
                 if player_notif != 2 {
                     println!("{}", e);
 
@@ -362,21 +871,130 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 is_interrupted = true;
                 utils::clear_activity(&mut is_activity_set, &mut client);
-                sleep(Duration::from_secs(interval));
+                refresh_interval.no_player();
+                sleep(refresh_interval.get());
                 continue;
             }
         };
 
         #[cfg(target_os = "linux")]
-        let mut player_name = player.identity().to_string();
+        let mut player_name = player.identity();
         #[cfg(target_os = "macos")]
         let mut player_name = player.player_id.clone();
 
         // Use video presence if player is in video_players list
-        let is_video_player = settings
-            .video_players
-            .iter()
-            .any(|video_player_name| video_player_name == &player_name);
+        #[cfg(target_os = "linux")]
+        let is_video_player = player_match::is_listed(
+            &settings.video_players,
+            &player.names(settings.dbus_address.as_deref()),
+        );
+        #[cfg(target_os = "macos")]
+        let is_video_player = player_match::is_listed(&settings.video_players, &[&player_name]);
+
+        // Player specific workarounds
+        #[cfg(target_os = "linux")]
+        let quirks = quirks::get(
+            &settings.player_quirks,
+            &player.names(settings.dbus_address.as_deref()),
+        );
+        #[cfg(target_os = "macos")]
+        let quirks = quirks::get(&settings.player_quirks, &[&player_name]);
+
+        // Artwork and links from Apple Music catalog
+        #[cfg(target_os = "linux")]
+        let is_apple_music =
+            apple_music::is_apple_music(&player.names(settings.dbus_address.as_deref()));
+        #[cfg(target_os = "macos")]
+        let is_apple_music = apple_music::is_apple_music(&[&player_name]);
+        // Switching between instances of the same player is a player change too
+        #[cfg(target_os = "linux")]
+        let bus_name = player.bus_name().map(String::from);
+        #[cfg(target_os = "macos")]
+        let bus_name: Option<String> = None;
+        if player_name != last_player_name || bus_name != last_bus_name {
+            hooks::run(
+                &settings.hooks,
+                hooks::HookEvent::PlayerChange,
+                &player_name,
+                None,
+                "",
+                settings.debug_log,
+            );
+            last_player_name = player_name.clone();
+            control::publish_event(
+                "player",
+                &match &bus_name {
+                    Some(bus_name) => format!("Selected player: {} ({})", player_name, bus_name),
+                    None => format!("Selected player: {}", player_name),
+                },
+            );
+            last_bus_name = bus_name;
+        }
+
+        // E.g. browsers refreshed more often to notice tab changes
+        let player_interval = settings
+            .player_overrides
+            .get(&player_name)
+            .filter(|player_override| {
+                player_override.interval.is_some() || player_override.interval_ms.is_some()
+            })
+            .map(|player_override| {
+                refresh::configured_interval(
+                    player_override.interval,
+                    player_override.interval_ms,
+                    settings.min_interval_ms,
+                )
+            });
+        if let Some(player_interval) = player_interval {
+            debug_log!(
+                settings.debug_log,
+                "interval of {}: {:?}",
+                player_name,
+                player_interval
+            );
+        }
+        refresh_interval.set_player_interval(player_interval);
+
+        // Activity type from player settings or workarounds, else watching for video players
+        let activity_type = match settings
+            .player_overrides
+            .get(&player_name)
+            .and_then(|player_override| player_override.activity_type.as_deref())
+            .or(quirks.activity_type())
+        {
+            Some("listening") => activity::ActivityType::Listening,
+            Some("watching") => activity::ActivityType::Watching,
+            Some("playing") => activity::ActivityType::Playing,
+            Some("competing") => activity::ActivityType::Competing,
+            other => {
+                if let Some(other) = other {
+                    println!(
+                        "\x1b[33mWARNING: Unknown activity_type of {}: {}\x1b[0m",
+                        player_name, other
+                    );
+                }
+                match is_video_player {
+                    true => activity::ActivityType::Watching,
+                    false => activity::ActivityType::Listening,
+                }
+            }
+        };
+        let is_game_activity = matches!(
+            activity_type,
+            activity::ActivityType::Playing | activity::ActivityType::Competing
+        );
+
+        // Activity name from player settings or config, "Playing <player>" for game activities
+        let activity_name = settings
+            .player_overrides
+            .get(&player_name)
+            .and_then(|player_override| player_override.activity_name.clone())
+            .or_else(|| settings.activity_name.clone())
+            .or_else(|| is_game_activity.then(|| player_name.clone()))
+            .map(|name| utils::trim_to_max_bytes(name, 128))
+            .unwrap_or_default();
+        debug_log!(settings.debug_log, "activity_name: {}", activity_name);
+
         if is_video_player {
             client = &mut client_video;
             debug_log!(settings.debug_log, "Using video player presence");
@@ -407,9 +1025,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             return Ok(());
         }
 
+        // Spotify style activity is used only for Spotify itself
+        let is_spotify = settings.spotify_mode && player_id == "spotify";
+
         #[cfg(target_os = "macos")]
         let last_player_id = player.player_id.clone();
 
+        // Where metadata comes from, logged with published activity
+        #[cfg(target_os = "linux")]
+        let metadata_source = player.kind();
+        #[cfg(target_os = "macos")]
+        let metadata_source = fallbacks
+            .iter()
+            .map(|fallback| fallback.identity())
+            .find(|identity| *identity == player.player_id)
+            .unwrap_or("media-control");
+
         // Set different name and ID for RPC if enabled by argument
         if !force_player_name.is_empty() {
             player_name = force_player_name.to_string();
@@ -419,18 +1050,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
 
         // Connect with Discord
+        let keep_connection = reused_connection.take() == Some(is_video_player);
         if (is_first_time_audio && !is_video_player) || (is_first_time_video && is_video_player) {
             match client.connect() {
                 Ok(_) => {
-                    println!("Connected to Discord.");
+                    println!("{}", strings.messages.discord_connected);
+                    control::publish_event("discord", &strings.messages.discord_connected);
                     discord_notif = false;
                 }
                 Err(_) => {
                     if !discord_notif {
-                        println!("Could not connect to Discord. Waiting for discord to start...");
+                        println!("{}", strings.messages.discord_waiting);
                         discord_notif = true;
                     }
-                    sleep(Duration::from_secs(interval));
+                    discord_socket::wait_for_discord(
+                        settings.wait_for_discord_socket,
+                        retry_interval,
+                        settings.debug_log,
+                    );
                     continue;
                 }
             };
@@ -439,83 +1076,155 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             } else {
                 is_first_time_audio = false;
             }
+        } else if keep_connection {
+            // Old activity stays until it's replaced
+            is_interrupted = true;
         } else {
             match client.reconnect() {
                 Ok(_) => {
+                    metrics::inc(&metrics::DISCORD_RECONNECTS);
                     if discord_notif {
-                        println!("Reconnected to Discord.");
+                        println!("{}", strings.messages.discord_reconnected);
                     }
+                    control::publish_event("discord", "Reconnected to Discord");
                     is_interrupted = true;
                     discord_notif = false;
                 }
                 Err(_) => {
                     if !discord_notif {
-                        println!("Could not reconnect to Discord. Waiting for discord to start...");
+                        println!("{}", strings.messages.discord_reconnect_waiting);
                         discord_notif = true;
                     }
-                    sleep(Duration::from_secs(interval));
+                    discord_socket::wait_for_discord(
+                        settings.wait_for_discord_socket,
+                        retry_interval,
+                        settings.debug_log,
+                    );
                     continue;
                 }
             };
         }
 
+        let mut stopped_since: Option<Instant> = None;
+        // D-Bus connection used to check for other players, reused until it fails
+        #[cfg(target_os = "linux")]
+        let mut dbus_finder: Option<mpris::PlayerFinder> = None;
         loop {
             debug_log!(
                 settings.debug_log,
                 "───────────────────────────────Loop─2───────────────────────────────────"
             );
+            let loop_start = Instant::now();
 
             // Check if should switch for other mpris source
             #[cfg(target_os = "linux")]
             {
-                let new_player = match PlayerFinder::new() {
-                    Ok(player) => {
-                        dbus_notif = false;
-                        if allowlist_enabled {
-                            utils::allowlist_player_finder(
-                                &player,
-                                &settings.allowlist,
-                                settings.debug_log,
-                            )
-                        } else {
-                            player.find_active()
-                        }
-                    }
+                let finder = match dbus_finder.take().map_or_else(
+                    || utils::new_player_finder(settings.dbus_address.as_deref()),
+                    Ok,
+                ) {
+                    Ok(finder) => dbus_finder.insert(finder),
                     Err(err) => {
-                        if !dbus_notif {
-                            println!("Could not connect to D-Bus: {}", err);
-                            dbus_notif = true;
-                        }
-                        sleep(Duration::from_secs(interval));
+                        sleep(dbus_health.failure(&err));
                         break;
                     }
                 };
+                let new_player = if allowlist_enabled {
+                    utils::allowlist_player_finder(
+                        finder,
+                        &settings.allowlist,
+                        &settings.allowlist_priorities,
+                        allowlist_sort_by_priority,
+                        settings.dbus_address.as_deref(),
+                        stopped_behavior == "paused",
+                        player.bus_name(),
+                        settings.debug_log,
+                    )
+                } else {
+                    utils::active_player_finder(finder, player.bus_name())
+                };
+                // Broken connection is replaced with a new one in the next iteration
+                let dbus_error = match &new_player {
+                    Err(mpris::FindingError::DBusError(err)) => {
+                        dbus_health.failure(err);
+                        dbus_finder = None;
+                        true
+                    }
+                    _ => {
+                        dbus_health.success();
+                        false
+                    }
+                };
 
-                if let Ok(new_p) = new_player {
-                    if new_p.identity() != player.identity() {
+                match sources::select_source(
+                    new_player,
+                    &fallbacks,
+                    &settings.allowlist,
+                    settings.debug_log,
+                ) {
+                    Some(new_p) if !new_p.is_same(&player) => {
                         debug_log!(settings.debug_log, "Detected player change.");
-                        utils::clear_activity(&mut is_activity_set, &mut client);
+                        metrics::inc(&metrics::PLAYER_CHANGES);
+                        let is_new_video_player = player_match::is_listed(
+                            &settings.video_players,
+                            &new_p.names(settings.dbus_address.as_deref()),
+                        );
+                        if player_change_behavior == "republish"
+                            && is_new_video_player == is_video_player
+                        {
+                            reused_connection = Some(is_video_player);
+                        } else {
+                            utils::clear_activity(&mut is_activity_set, client);
+                        }
                         // sleep(Duration::from_secs(interval));
                         break;
                     }
+                    // Selected player is no longer on the allowlist, e.g. its identity changed
+                    // (playerctld) or it moved to another bus name, so it must be selected again
+                    None if allowlist_enabled && !dbus_error => {
+                        debug_log!(
+                            settings.debug_log,
+                            "Selected player no longer matches the allowlist."
+                        );
+                        utils::clear_activity(&mut is_activity_set, client);
+                        break;
+                    }
+                    _ => {}
                 }
             }
 
             // Get metadata from player
             #[cfg(target_os = "linux")]
-            let media_info = match utils::get_currently_playing(&player, settings.debug_log) {
-                Ok(metadata) => metadata,
-                Err(err) => {
-                    println!("Could not get metadata from player: {}", err);
-                    utils::clear_activity(&mut is_activity_set, &mut client);
-                    break;
-                }
-            };
+            let mut media_info =
+                match utils::catch_panic(|| player.get_currently_playing(settings.debug_log))
+                    .unwrap_or_else(|err| Err(format!("panic: {}", err).into()))
+                {
+                    Ok(metadata) => metadata,
+                    Err(err) => {
+                        let message = locale::fill(
+                            &strings.messages.metadata_error,
+                            &[("error", &err.to_string())],
+                        );
+                        println!("{}", message);
+                        metrics::set_last_error(&message);
+                        utils::clear_activity(&mut is_activity_set, client);
+                        break;
+                    }
+                };
             #[cfg(target_os = "macos")]
-            let media_info = match utils::get_currently_playing() {
+            let mut media_info = match utils::catch_panic(|| {
+                sources::get_currently_playing(&fallbacks, &settings.allowlist, settings.debug_log)
+            })
+            .unwrap_or_else(|err| Err(format!("panic: {}", err).into()))
+            {
                 Ok(metadata) => metadata,
                 Err(err) => {
-                    println!("Could not get metadata from player: {}", err);
+                    let message = locale::fill(
+                        &strings.messages.metadata_error,
+                        &[("error", &err.to_string())],
+                    );
+                    println!("{}", message);
+                    metrics::set_last_error(&message);
                     utils::clear_activity(&mut is_activity_set, &mut client);
                     break;
                 }
@@ -526,33 +1235,200 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             #[cfg(target_os = "macos")]
             if media_info.player_id != last_player_id {
                 debug_log!(settings.debug_log, "Detected player change.");
-                utils::clear_activity(&mut is_activity_set, client);
+                metrics::inc(&metrics::PLAYER_CHANGES);
+                let is_new_video_player =
+                    player_match::is_listed(&settings.video_players, &[&media_info.player_id]);
+                if player_change_behavior == "republish" && is_new_video_player == is_video_player {
+                    reused_connection = Some(is_video_player);
+                } else {
+                    utils::clear_activity(&mut is_activity_set, client);
+                }
                 break;
             }
 
-            if settings.only_when_playing && !media_info.is_playing {
+            // Some players update position with a delay after seeking, the second read is up to date.
+            // It's needed only when the position went back or jumped compared to the expected one.
+            #[cfg(target_os = "linux")]
+            if quirks.lagging_position()
+                && media_info.is_track_position
+                && quirks::position_jumped(
+                    last_track_position,
+                    match last_is_playing {
+                        true => last_position_read.elapsed().as_secs(),
+                        false => 0,
+                    },
+                    media_info.position,
+                )
+            {
+                sleep(quirks::POSITION_REREAD_DELAY);
+                if let Ok(Ok(reread)) = utils::catch_panic(|| player.get_currently_playing(false)) {
+                    if reread.title == media_info.title && reread.track_id == media_info.track_id {
+                        debug_log!(
+                            settings.debug_log,
+                            "Position after re-read: {} -> {}",
+                            media_info.position,
+                            reread.position
+                        );
+                        media_info.position = reread.position;
+                    }
+                }
+            }
+            quirks.apply(&mut media_info);
+            // File name shown by video players for files without title tag is made readable
+            if is_video_player && !settings.disable_video_title_cleanup {
+                if let Some(title) = video_title::clean_file_name(&media_info.title) {
+                    debug_log!(
+                        settings.debug_log,
+                        "Video title: {} => {}",
+                        media_info.title,
+                        title
+                    );
+                    media_info.title = title;
+                }
+            }
+            if let Some(fixed) = quirks.fix_times(&mut media_info) {
+                debug_log!(settings.debug_log, "Implausible track times: {}", fixed);
+            }
+
+            // Pause players with lower priority, so the audio matches the activity
+            #[cfg(target_os = "linux")]
+            if settings.exclusive_playback && allowlist_enabled && media_info.is_playing {
+                if let Some(finder) = &dbus_finder {
+                    if let Err(err) = utils::pause_lower_players(
+                        finder,
+                        &player.names(settings.dbus_address.as_deref()),
+                        &settings.allowlist,
+                        &settings.allowlist_priorities,
+                        settings.dbus_address.as_deref(),
+                        settings.debug_log,
+                    ) {
+                        debug_log!(settings.debug_log, "[exclusive playback] {}", err);
+                    }
+                }
+            }
+
+            // Hide activity while streaming, it's published again when streaming ends
+            if settings.pause_while_streaming && streaming_detector.is_active(settings.debug_log) {
+                if !is_streaming {
+                    println!("Streaming detected, activity hidden.");
+                    is_streaming = true;
+                }
                 is_interrupted = true;
                 utils::clear_activity(&mut is_activity_set, client);
-                sleep(Duration::from_secs(interval));
+                metrics::observe_loop(loop_start.elapsed());
+                sleep(refresh_interval.get());
                 continue;
+            } else if is_streaming {
+                println!("Streaming ended, activity restored.");
+                is_streaming = false;
             }
 
-            let album_id = format!("{} - {}", media_info.album_artist, media_info.album);
+            // Discord shows one activity, so let the game have it
+            if settings.yield_to_games {
+                if let Some(game) = game_detector.get_running(settings.debug_log) {
+                    if !is_game_running {
+                        println!("Game detected ({}), activity hidden.", game);
+                        is_game_running = true;
+                    }
+                    is_interrupted = true;
+                    utils::clear_activity(&mut is_activity_set, client);
+                    metrics::observe_loop(loop_start.elapsed());
+                    sleep(refresh_interval.get());
+                    continue;
+                } else if is_game_running {
+                    println!("Game closed, activity restored.");
+                    is_game_running = false;
+                }
+            }
+
+            // Activity stays hidden until the next day when the daily limit is used up
+            if let Some(budget) = &mut presence_budget {
+                budget.update(is_activity_set);
+                if budget.is_exceeded() {
+                    if !is_over_budget {
+                        println!("Daily presence limit reached, activity hidden until reset.");
+                        is_over_budget = true;
+                    }
+                    is_interrupted = true;
+                    utils::clear_activity(&mut is_activity_set, client);
+                    metrics::observe_loop(loop_start.elapsed());
+                    sleep(refresh_interval.get());
+                    continue;
+                } else if is_over_budget {
+                    println!("Daily presence limit was reset, activity restored.");
+                    is_over_budget = false;
+                }
+            }
+
+            // User script can change metadata and activity or skip this update
+            let script_result = match &script {
+                Some(script) => script.run(&mut media_info, &player_name, settings.debug_log),
+                None => scripting::ScriptResult::default(),
+            };
+            if script_result.skip {
+                debug_log!(settings.debug_log, "[script] update skipped by script.");
+                metrics::observe_loop(loop_start.elapsed());
+                sleep(refresh_interval.base());
+                continue;
+            }
+
+            // Skip ads, so they don't replace the previous track or clear activity during them
+            if ad_behavior != "show" && ads::is_advertisement(&media_info, &settings.ad_patterns) {
+                debug_log!(
+                    settings.debug_log,
+                    "Advertisement detected: {} - {}",
+                    media_info.artist,
+                    media_info.title
+                );
+                if ad_behavior == "clear" {
+                    is_interrupted = true;
+                    utils::clear_activity(&mut is_activity_set, client);
+                }
+                metrics::observe_loop(loop_start.elapsed());
+                sleep(refresh_interval.get());
+                continue;
+            }
+
+            // Stopped player may still have the last track, show it as paused or clear activity
+            if media_info.is_stopped {
+                let stopped_at = *stopped_since.get_or_insert_with(Instant::now);
+                let clear = match stopped_behavior.as_str() {
+                    "clear" => true,
+                    "keep" => stopped_at.elapsed() >= stopped_keep_time,
+                    _ => false,
+                };
+                if clear {
+                    debug_log!(settings.debug_log, "Player is stopped, clearing activity.");
+                    is_interrupted = true;
+                    utils::clear_activity(&mut is_activity_set, client);
+                    sleep(refresh_interval.base());
+                    continue;
+                }
+            } else {
+                stopped_since = None;
+            }
+
+            if settings.only_when_playing && !media_info.is_playing {
+                is_interrupted = true;
+                utils::clear_activity(&mut is_activity_set, client);
+                sleep(refresh_interval.base());
+                continue;
+            }
 
             // If all metadata values are unknown then break
-            if (media_info.artist.to_lowercase() == "unknown artist")
-                && (media_info.album.to_lowercase() == "unknown album")
-                && (media_info.title.to_lowercase() == "unknown title")
+            if media_info.artist.eq_ignore_ascii_case("unknown artist")
+                && media_info.album.eq_ignore_ascii_case("unknown album")
+                && media_info.title.eq_ignore_ascii_case("unknown title")
             {
                 debug_log!(settings.debug_log, "Unknown metadata, skipping...");
-                sleep(Duration::from_secs(interval));
+                sleep(refresh_interval.base());
                 break;
             }
 
             // If artist or track is empty then break
             if (media_info.artist.len() == 0) | (media_info.title.len() == 0) {
                 debug_log!(settings.debug_log, "Unknown metadata, skipping...");
-                sleep(Duration::from_secs(interval));
+                sleep(refresh_interval.base());
                 break;
             }
 
@@ -572,11 +1448,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 media_info.is_playing,
                 last_is_playing
             );
-            if (media_info.title != last_title)
+            // Position in the track list and playlist name, changes of them are published too
+            #[cfg(target_os = "linux")]
+            let playlist_context = match (&mut playlist_reader, &player) {
+                (Some(reader), sources::Source::Mpris(mpris_player)) => {
+                    reader.get(mpris_player, &media_info.track_id)
+                }
+                _ => playlist::PlaylistContext::default(),
+            };
+            #[cfg(target_os = "macos")]
+            let playlist_context = playlist::PlaylistContext::default();
+
+            // Track id changes also when the next track has the same metadata
+            let track_changed = (media_info.title != last_title)
                 | (media_info.album != last_album)
                 | (media_info.artist != last_artist)
                 | (media_info.album_artist != last_album_artist)
+                | (media_info.track_id != last_track_id);
+            if track_changed {
+                last_track_change = Instant::now();
+            }
+            // Rating can be changed by user while the track plays
+            if track_changed
                 | (media_info.is_playing != last_is_playing)
+                | (media_info.rating != last_rating)
+                | (playlist_context != last_playlist_context)
             {
                 metadata_changed = true;
             }
@@ -588,13 +1484,129 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 last_track_position
             );
 
-            // Check if song repeated
-            if (media_info.position < last_track_position) && !metadata_changed {
+            // Check if song repeated. Crossfading players can report position of the previous track
+            // for a moment after the next one started, so it's ignored right after track change.
+            if (media_info.position < last_track_position)
+                && !metadata_changed
+                && last_track_change.elapsed() >= transition_window
+            {
                 debug_log!(settings.debug_log, "Detected a potential song seek/replay");
                 metadata_changed = true;
             }
+            // The same track played again from the start, e.g. with repeat of one track
+            let same_track = (media_info.title == last_title)
+                & (media_info.artist == last_artist)
+                & (media_info.album == last_album);
+            if repeat_counter.update(
+                same_track,
+                last_track_position,
+                media_info.position,
+                media_info.duration,
+            ) {
+                debug_log!(
+                    settings.debug_log,
+                    "repeat_count: {}",
+                    repeat_counter.count()
+                );
+                metadata_changed = true;
+            }
             last_track_position = media_info.position; // update it before loop continue
+            #[cfg(target_os = "linux")]
+            {
+                last_position_read = Instant::now();
+            }
             debug_log!(settings.debug_log, "metadata_changed: {}", metadata_changed);
+            if metadata_changed {
+                refresh_interval.changed();
+                control::publish_event(
+                    "metadata",
+                    &format!(
+                        "{} - {} ({}) [{}]",
+                        media_info.artist,
+                        media_info.title,
+                        media_info.album,
+                        if media_info.is_playing {
+                            "playing"
+                        } else {
+                            "paused"
+                        }
+                    ),
+                );
+            }
+
+            // Discord started after the activity was set (e.g. the same long mix since the app
+            // start) or restarted, the last activity is published again for it
+            if client.discord_started() {
+                metrics::inc(&metrics::DISCORD_RECONNECTS);
+                control::publish_event("discord", "Reconnected to Discord");
+                is_interrupted = true;
+            }
+
+            // Thumbnail or avatar fetched after the activity was set without it
+            if network_worker.receive() {
+                metadata_changed = true;
+            }
+
+            // Image added to or replaced in the cover folder, the cover is searched again
+            if cover_folder_watch
+                .as_mut()
+                .is_some_and(|watch| watch.changed())
+            {
+                debug_log!(settings.debug_log, "Cover folder changed.");
+                last_album_id.clear();
+                metadata_changed = true;
+            }
+
+            // Wrong cover of the current album, its cache entry is removed and it's searched again
+            let refresh_cover = control::take_refresh_cover();
+            if refresh_cover {
+                let album_id = cover_lookup::get_album_id(&media_info);
+                println!("Refreshing album cover of: {}", album_id);
+                control::publish_event("cover", &format!("{}: refreshing", album_id));
+                last_album_id.clear();
+                metadata_changed = true;
+            }
+
+            // {elapsed} and {remaining} in templates are outdated
+            if let Some(interval) = time_text_interval {
+                if media_info.is_playing
+                    && is_activity_set
+                    && last_activity_update.elapsed() >= interval
+                {
+                    debug_log!(settings.debug_log, "Refreshing time in activity text");
+                    metadata_changed = true;
+                }
+            }
+
+            // After suspend or clock change the start time is computed again from track position
+            if let Some(jump) = clock_watch.jumped() {
+                debug_log!(
+                    settings.debug_log,
+                    "System clock jumped by {}s, updating timestamps",
+                    jump
+                );
+                metadata_changed |= is_activity_set;
+            }
+
+            // Cover found after the activity was set without it
+            let mut cover_received = false;
+            if let Some(cover_request) = pending_cover {
+                if let Some(cover) = network_worker.get_cover(cover_request, Duration::ZERO) {
+                    pending_cover = None;
+                    if cover_lookup::get_album_id(&media_info) == last_album_id {
+                        debug_log!(settings.debug_log, "Cover found: {}", cover.cover_url);
+                        // Second activity update only if there is anything new to display
+                        cover_received = cover.cover_url != "missing-cover"
+                            || !cover.album_info.year.is_empty()
+                            || !cover.album_info.tracks.is_empty()
+                            || !cover.album_info.bio.is_empty();
+                        metadata_changed |= cover_received;
+                        _cover_url = cover.cover_url;
+                        cover_source = cover.source;
+                        current_album_info = cover.album_info;
+                    }
+                }
+            }
 
             if !metadata_changed && !is_interrupted {
                 debug_log!(
@@ -602,89 +1614,93 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "The same metadata and status, skipping..."
                 );
 
-                sleep(Duration::from_secs(interval));
+                if let Some(card) = &mut now_playing_card {
+                    card.update_progress(settings.debug_log);
+                }
+                if is_activity_set {
+                    last_activity.touch();
+                }
+                // Track could be loved while listening, the reply updates the activity
+                if lastfm_loved_enabled && is_activity_set {
+                    network_worker.is_lastfm_loved(
+                        &lastfm_name,
+                        &media_info.artist,
+                        &media_info.title,
+                    );
+                }
+
+                metrics::observe_loop(loop_start.elapsed());
+                network_worker.wait(refresh_interval.get());
                 continue;
             }
 
+            // Nothing below runs while the same track plays, so idle refreshes are cheap
+            let album_id = cover_lookup::get_album_id(&media_info);
+
             // Get unix time of track start if supported, else return time now
-            let time_start: u64 = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            let mut time_start: u64 = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+            {
                 Ok(n) => n.as_secs().sub(media_info.position),
                 Err(_) => 0,
             };
-
-            // Fetch album cover
-            if album_id != last_album_id {
-                if lastfm_api_key.is_empty() {
-                    _cover_url = "missing-cover".to_string()
-                } else {
-                    _cover_url = utils::get_cover_url(
-                        &album_id,
-                        media_info.album.as_str(),
-                        _cover_url,
-                        cache_enabled,
-                        &mut album_cache,
-                        media_info.album_artist.as_str(),
-                        &lastfm_api_key,
-                    );
-
-                    // Fallback for Apple Music for album names with " - EP" and " - Single"
-                    if _cover_url.is_empty() || _cover_url == "missing-cover" {
-                        let album_name = media_info.album.trim();
-                        let album_name_without_suffix = if album_name.ends_with(" - EP") {
-                            &album_name[..album_name.len() - 5]
-                        } else if album_name.ends_with(" - Single") {
-                            &album_name[..album_name.len() - 9]
-                        } else {
-                            ""
-                        };
-
-                        if !album_name_without_suffix.is_empty() {
-                            debug_log!(
-                            settings.debug_log,
-                            "Album cover not found, attempting to use album name without the 'EP' or 'Single' suffix (Apple Music)."
-                            );
-                            debug_log!(
-                                settings.debug_log,
-                                "{} => {}",
-                                album_name,
-                                album_name_without_suffix
-                            );
-
-                            _cover_url = utils::get_cover_url(
-                                &album_id,
-                                album_name_without_suffix,
-                                _cover_url,
-                                cache_enabled,
-                                &mut album_cache,
-                                media_info.album_artist.as_str(),
-                                &lastfm_api_key,
-                            );
-                        }
-                    }
+            // Without track position the start time would be reset by restart of the app
+            let track_key = format!(
+                "{} - {} - {}",
+                player_name, media_info.artist, media_info.title
+            );
+            if !media_info.is_track_position {
+                if let Some(start) = last_activity.restore(&track_key) {
+                    time_start = start;
                 }
+            }
 
-                // Use Musicbrainz cover if Last.fm fails
-                if !settings.disable_musicbrainz_cover {
-                    if _cover_url.is_empty() || _cover_url == "missing-cover" {
-                        _cover_url = utils::get_cover_url_musicbrainz(
-                            &album_id,
-                            media_info.album.as_str(),
-                            _cover_url,
-                            cache_enabled,
-                            &mut album_cache,
-                            media_info.album_artist.as_str(),
+            // Fetch album cover, the lookup of previous track is cancelled
+            let use_player_cover = quirks.prefers_player_cover()
+                && media_info.art_url.starts_with("http")
+                && !settings.disable_mpris_art_url;
+            if album_id != last_album_id && use_player_cover {
+                debug_log!(
+                    settings.debug_log,
+                    "Using player artwork instead of cover search."
+                );
+                network_worker.cancel_cover();
+                pending_cover = None;
+                _cover_url = String::new();
+                cover_source = "";
+                current_album_info = album_info::AlbumInfo::default();
+            } else if album_id != last_album_id {
+                let cover_request =
+                    network_worker.request_cover(&album_id, &media_info, refresh_cover);
+                match network_worker.get_cover(cover_request, COVER_WAIT_TIME) {
+                    Some(cover) => {
+                        pending_cover = None;
+                        _cover_url = cover.cover_url;
+                        cover_source = cover.source;
+                        current_album_info = cover.album_info;
+                    }
+                    None => {
+                        debug_log!(
+                            settings.debug_log,
+                            "Cover not found yet, setting activity without it."
                         );
+                        pending_cover = Some(cover_request);
+                        _cover_url = String::new();
+                        cover_source = "";
+                        current_album_info = album_info::AlbumInfo::default();
                     }
                 }
             }
 
+            // Where the displayed cover comes from, logged with published activity
+            let mut image_source = cover_source;
             let image: String = if _cover_url.is_empty() || _cover_url == "missing-cover" {
                 match media_info.art_url.is_empty() {
                     true => "missing-cover".to_string(),
                     false => {
                         if media_info.art_url.starts_with("http") && !settings.disable_mpris_art_url
                         {
-                            media_info.art_url
+                            image_source = "player artwork";
+                            network_worker.get_thumbnail(&media_info.art_url)
                         } else {
                             "missing-cover".to_string()
                         }
@@ -693,6 +1709,237 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             } else {
                 _cover_url.clone()
             };
+            // Exact artwork of the track playing in Apple Music
+            let apple_music_track = match is_apple_music {
+                true => network_worker.get_apple_music_track(&media_info).cloned(),
+                false => None,
+            };
+            let image = match &apple_music_track {
+                Some(track) if !track.artwork_url.is_empty() => {
+                    image_source = "Apple Music";
+                    track.artwork_url.clone()
+                }
+                _ => image,
+            };
+            // Artwork image provided by the player is uploaded, Discord accepts only links
+            let image = match image == "missing-cover"
+                && !media_info.art_data.0.is_empty()
+                && !settings.disable_mpris_art_url
+            {
+                true => match network_worker.get_uploaded_artwork(&media_info.art_data) {
+                    Some(url) => {
+                        image_source = "player artwork";
+                        url
+                    }
+                    None => {
+                        debug_log!(
+                            settings.debug_log,
+                            "Player provides artwork image ({} bytes), it's shown after upload to cover_upload_url.",
+                            media_info.art_data.0.len()
+                        );
+                        image
+                    }
+                },
+                false => image,
+            };
+            let image = utils::sanitize_activity_image(image, settings.debug_log);
+
+            // Store cover image on disk
+            if cache_on_disk
+                && settings.cache_cover_images
+                && (album_id != last_album_id || cover_received)
+                && pending_cover.is_none()
+            {
+                if image.starts_with("http") {
+                    network_worker.store_cover(&album_id, &image);
+                } else if !media_info.art_data.0.is_empty() {
+                    cover_cache::store_cover_data(
+                        &cache_dir,
+                        &album_id,
+                        &media_info.art_data.0,
+                        cover_cache_max_size,
+                        settings.debug_log,
+                    );
+                }
+            }
+
+            if let Some(card) = &mut now_playing_card {
+                card.set_track(&media_info, &album_id, settings.debug_log);
+            }
+
+            // Run user hooks
+            let track_changed = media_info.title != last_title
+                || media_info.artist != last_artist
+                || media_info.album != last_album;
+            let hook_event = if track_changed {
+                Some(hooks::HookEvent::TrackChange)
+            } else if last_is_playing && !media_info.is_playing {
+                Some(hooks::HookEvent::Pause)
+            } else {
+                None
+            };
+            // Track for the "state" control command, media_info fields are moved below
+            let track_state = media_state::TrackState::new(&media_info, &image);
+            let track_status = media_state::status(&media_info);
+
+            if let Some(event) = hook_event {
+                hooks::run(
+                    &settings.hooks,
+                    event,
+                    &last_player_name,
+                    Some(&media_info),
+                    &image,
+                    settings.debug_log,
+                );
+            }
+
+            // Release details from tags of local files, read once per track
+            if tags_enabled && media_info.url != last_tag_url {
+                tag_info = tags::read(&media_info.url, settings.debug_log);
+                last_tag_url = media_info.url.clone();
+            }
+
+            // Values available in text templates
+            let rating = utils::format_rating(
+                media_info.rating,
+                settings.rating_style.as_deref().unwrap_or("stars"),
+            );
+            let elapsed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|now| now.as_secs().saturating_sub(time_start))
+                .unwrap_or(0);
+            let elapsed_text = utils::format_time(elapsed);
+            let repeat_count_text = repeat_counter.text();
+            // Season and episode of TV shows, e.g. "Show — S02E05 • Episode Title"
+            let episode = match is_video_player && !settings.disable_episode_parsing {
+                true => episode::parse(&media_info.title, &media_info.album),
+                false => None,
+            };
+            let (show_text, episode_text, episode_title_text) = match &episode {
+                Some(episode) => (episode.show.clone(), episode.code(), episode.title.clone()),
+                None => (String::new(), String::new(), String::new()),
+            };
+            // Deck of DJ software, e.g. "A" for "Deck A: Artist - Title" in Mixxx
+            let deck_text = quirks::deck(&[
+                last_bus_name.as_deref().unwrap_or_default(),
+                media_info.track_id.as_str(),
+                media_info.url.as_str(),
+            ]);
+            let remaining_text = match media_info.is_track_position && media_info.duration > 0 {
+                true => utils::format_time(media_info.duration.saturating_sub(elapsed)),
+                false => String::new(),
+            };
+            let is_loved = lastfm_loved_enabled
+                && network_worker.is_lastfm_loved(
+                    &lastfm_name,
+                    &media_info.artist,
+                    &media_info.title,
+                );
+            // Page of the track for the lastfmTrack button
+            let lastfm_track_url: String = format!(
+                "https://www.last.fm/music/{}/_/{}",
+                url_escape::encode_component(&media_info.artist),
+                url_escape::encode_component(&media_info.title)
+            );
+            // Page of the exact recording for the musicbrainz button, searched only if it's used
+            let recording_url = match settings.button.iter().any(|button| button == "musicbrainz") {
+                true => network_worker
+                    .get_recording(&media_info)
+                    .map(recording::page_url),
+                false => None,
+            };
+            let template_values = [
+                ("album", media_info.album.as_str()),
+                ("artist", media_info.artist.as_str()),
+                ("album_artist", media_info.album_artist.as_str()),
+                ("title", media_info.title.as_str()),
+                // Year from tags of local files is more accurate than MusicBrainz search
+                (
+                    "year",
+                    match tag_info.year.is_empty() {
+                        true => current_album_info.year.as_str(),
+                        false => tag_info.year.as_str(),
+                    },
+                ),
+                ("tracks", current_album_info.tracks.as_str()),
+                ("label", tag_info.label.as_str()),
+                ("catalog", tag_info.catalog.as_str()),
+                ("bio", current_album_info.bio.as_str()),
+                ("player", player_name.as_str()),
+                ("rating", rating.as_str()),
+                ("queue", playlist_context.queue.as_str()),
+                ("playlist", playlist_context.playlist.as_str()),
+                ("elapsed", elapsed_text.as_str()),
+                ("remaining", remaining_text.as_str()),
+                ("repeat_count", repeat_count_text.as_str()),
+                ("show", show_text.as_str()),
+                ("episode", episode_text.as_str()),
+                ("episode_title", episode_title_text.as_str()),
+                ("deck", deck_text.as_str()),
+                (
+                    "loved",
+                    match is_loved {
+                        true => strings.loved.as_str(),
+                        false => "",
+                    },
+                ),
+            ];
+
+            // Custom status shows the playing track, cleared when paused
+            let custom_status_text = match media_info.is_playing {
+                true => template::render(
+                    settings
+                        .custom_status
+                        .text_format
+                        .as_deref()
+                        .unwrap_or("{title} - {artist}"),
+                    &template_values,
+                ),
+                false => String::new(),
+            };
+            hooks::update_custom_status(
+                &settings.custom_status,
+                &mut last_custom_status,
+                &custom_status_text,
+                settings.debug_log,
+            );
+
+            // Text shown on album cover hover
+            let large_text = match &settings.large_text_format {
+                Some(format) => template::render(format, &template_values),
+                None => String::new(),
+            };
+            // Default is "album: Album", with year and label from tags e.g. "album: Album (2003, Warp Records)"
+            let large_text = match (large_text.len() > 1, tag_info.summary()) {
+                (true, _) => large_text,
+                (false, summary) if !summary.is_empty() => {
+                    format!("{}{} ({})", strings.album_prefix, media_info.album, summary)
+                }
+                (false, _) => format!("{}{}", strings.album_prefix, media_info.album),
+            };
+            // Number of plays in a row, e.g. "album: Album 🔁 x5"
+            let large_text = match settings.show_repeat_count && repeat_counter.count() > 1 {
+                true => format!("{} 🔁 x{}", large_text, repeat_counter.count()),
+                false => large_text,
+            };
+
+            // Custom layout of the first and second line of activity, e.g. "{player} • {title}"
+            let details_text = settings
+                .details_format
+                .as_deref()
+                .or(quirks.details_format())
+                .map(|format| template::render(format, &template_values))
+                .or_else(|| episode.as_ref().map(|episode| episode.details()));
+            let state_text = settings
+                .state_format
+                .as_ref()
+                .map(|format| template::render(format, &template_values));
+
+            // Live stream presence for Twitch and YouTube streams
+            let live_channel = match settings.disable_live_presence {
+                true => None,
+                false => utils::get_live_stream_channel(&media_info),
+            };
 
             // Save last refresh info
             last_title = media_info.title.clone();
@@ -700,31 +1947,122 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             last_artist = media_info.artist.clone();
             last_album_artist = media_info.album_artist;
             last_album_id = album_id.to_string();
+            last_track_id = media_info.track_id.clone();
+            last_rating = media_info.rating;
+            last_playlist_context = playlist_context;
             last_is_playing = media_info.is_playing;
 
             // Set activity
             let song_name: String = format!("{} - {}", media_info.artist, media_info.title);
-            let title = if media_info.title.len() > 1 {
-                utils::trim_to_max_bytes(media_info.title, 256)
+            let title = details_text.unwrap_or(media_info.title);
+            let title = if title.len() > 1 {
+                utils::trim_to_max_bytes(title, 256)
             } else {
-                format!("{} ", media_info.title) // Discord activity min 2 char len bug fix
+                format!("{} ", title) // Discord activity min 2 char len bug fix
             };
-            let artist = match rpc_name.as_str() {
-                "artist" => {
+            let artist = match (state_text, rpc_name.as_str()) {
+                (Some(state_text), _) => {
+                    if state_text.len() > 1 {
+                        utils::trim_to_max_bytes(state_text, 256)
+                    } else {
+                        format!("{} ", state_text) // Discord activity min 2 char len bug fix
+                    }
+                }
+                (None, "artist") => {
                     if media_info.artist.len() > 1 {
                         utils::trim_to_max_bytes(media_info.artist, 256)
                     } else {
                         format!("{} ", media_info.artist) // Discord activity min 2 char len bug fix
                     }
                 }
-                _ => utils::trim_to_max_bytes(format!("by: {}", media_info.artist), 256),
+                _ => utils::trim_to_max_bytes(
+                    format!("{}{}", strings.artist_prefix, media_info.artist),
+                    256,
+                ),
             };
-            let album = utils::trim_to_max_bytes(format!("album: {}", media_info.album), 256);
+            let artist = match &live_channel {
+                Some(channel) if channel.is_empty() => strings.live.clone(),
+                Some(channel) => {
+                    utils::trim_to_max_bytes(format!("{} • {}", strings.live, channel), 256)
+                }
+                None => artist,
+            };
+            let album = utils::trim_to_max_bytes(large_text, 256);
             let status_text: String = if media_info.is_playing {
                 "playing".to_string()
             } else {
                 "paused".to_string()
             };
+            let status_label = match media_info.is_playing {
+                true => &strings.playing,
+                false => &strings.paused,
+            };
+
+            // Values set by user script
+            let title = match script_result.details {
+                Some(details) => utils::trim_to_max_bytes(details, 256),
+                None => title,
+            };
+            let artist = match script_result.state {
+                Some(state) => utils::trim_to_max_bytes(state, 256),
+                None => artist,
+            };
+            let album = match script_result.large_text {
+                Some(large_text) => utils::trim_to_max_bytes(large_text, 256),
+                None => album,
+            };
+            let image = match script_result.large_image {
+                Some(large_image) => {
+                    image_source = "script";
+                    utils::sanitize_activity_image(large_image, settings.debug_log)
+                }
+                None => image,
+            };
+            if image == "missing-cover" {
+                image_source = "none";
+            }
+
+            // Link to the track on Spotify, without it the normal activity is used
+            let spotify_url = match is_spotify {
+                true => utils::get_spotify_track_url(&media_info.url),
+                false => None,
+            };
+            if is_spotify && spotify_url.is_none() {
+                debug_log!(
+                    settings.debug_log,
+                    "No Spotify track id in url: {}, using normal activity.",
+                    media_info.url
+                );
+            }
+
+            // Links to the track on other services for their buttons, resolved with Odesli from
+            // the track link, or searches of the song
+            let service_links = match settings.disable_odesli {
+                false => spotify_url
+                    .clone()
+                    .or(apple_music_track.as_ref().map(|track| track.url.clone()))
+                    .or(Some(media_info.url.clone())
+                        .filter(|url| service_links::is_link_source(url)))
+                    .filter(|_| {
+                        settings
+                            .button
+                            .iter()
+                            .any(|button| service_links::is_service_button(button))
+                    })
+                    .and_then(|url| network_worker.get_service_links(&url).cloned()),
+                true => None,
+            };
+            let service_urls: HashMap<&str, String> = settings
+                .button
+                .iter()
+                .filter(|button| service_links::is_service_button(button))
+                .map(|button| {
+                    (
+                        button.as_str(),
+                        service_links::get_url(button, service_links.as_ref(), &song_name),
+                    )
+                })
+                .collect();
 
             let mut assets = activity::Assets::new().large_image(&image);
 
@@ -732,55 +2070,94 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 assets = assets.large_text(&album);
             }
 
-            // Icon displayed next to the album cover
-            match small_image.as_str() {
-                "player" => {
-                    if !settings.disable_mpris_art_url && image.contains("ytimg.com/") {
-                        assets = assets.small_image("youtube").small_text("YouTube")
-                    } else {
-                        assets = assets.small_image(&player_id).small_text(&player_name)
+            // Icon displayed next to the album cover, if hidden only the one set by user script is shown
+            if !settings.hide_small_image {
+                match small_image.as_str() {
+                    "player" => match quirks::cover_icon(&image) {
+                        Some((icon, text)) if !settings.disable_mpris_art_url => {
+                            assets = assets.small_image(icon).small_text(text)
+                        }
+                        _ => assets = assets.small_image(&player_id).small_text(&player_name),
+                    },
+                    "lastfmAvatar" => {
+                        if !network_worker.lastfm_avatar().is_empty() {
+                            assets = assets
+                                .small_image(network_worker.lastfm_avatar())
+                                .small_text(&lastfm_icon_text);
+                        }
                     }
+                    "none" => {}
+                    _ => assets = assets.small_image(&status_text).small_text(status_label),
                 }
-                "lastfmAvatar" => {
-                    if !lastfm_avatar.is_empty() {
-                        assets = assets
-                            .small_image(&lastfm_avatar)
-                            .small_text(&lastfm_icon_text);
-                    }
+
+                // Heart instead of the configured icon if the track is loved on Last.fm
+                if is_loved && settings.lastfm_loved_icon {
+                    assets = assets
+                        .small_image("loved")
+                        .small_text(&strings.loved_on_lastfm);
+                }
+
+                // Display paused icon anyway if playpack is paused or stopped
+                if status_text != "playing" {
+                    assets = assets.small_image(&status_text).small_text(status_label)
                 }
-                "none" => {}
-                _ => assets = assets.small_image(&status_text).small_text(&status_text),
             }
 
-            // Display paused icon anyway if playpack is paused or stopped
-            if status_text != "playing" {
-                assets = assets.small_image(&status_text).small_text(&status_text)
+            if let Some(small_image) = &script_result.small_image {
+                assets = assets.small_image(small_image);
+            }
+            if let Some(small_text) = &script_result.small_text {
+                assets = assets.small_text(small_text);
             }
 
             let mut payload = activity::Activity::new()
                 .details(&title)
                 .assets(assets)
-                .activity_type(if is_video_player {
-                    activity::ActivityType::Watching
-                } else {
-                    activity::ActivityType::Listening
+                .activity_type(match live_channel {
+                    Some(_) if !is_game_activity => activity::ActivityType::Watching,
+                    _ => activity_type.clone(),
                 });
 
             // "Listening to ..."
-            match rpc_name.as_str() {
-                "none" => payload = payload.status_display_type(StatusDisplayType::Name),
-                "track" => payload = payload.status_display_type(StatusDisplayType::Details),
-                "artist" | _ => payload = payload.status_display_type(StatusDisplayType::State),
+            match status_display.as_str() {
+                "name" => payload = payload.status_display_type(StatusDisplayType::Name),
+                "details" => payload = payload.status_display_type(StatusDisplayType::Details),
+                _ => payload = payload.status_display_type(StatusDisplayType::State),
+            }
+
+            // "Listening to <activity_name>"
+            if activity_name.len() > 1 {
+                payload = payload.name(&activity_name);
+            }
+
+            // "Listening to Spotify"
+            if spotify_url.is_some() {
+                payload = payload
+                    .name("Spotify")
+                    .status_display_type(StatusDisplayType::Name);
+            }
+
+            if let Some(name) = &script_result.name {
+                payload = payload.name(name);
             }
 
             // Don't display Unknown Artist for videos
-            if !(is_video_player && (artist.to_lowercase() == "by: unknown artist")
-                || artist.to_lowercase() == "unknown artist")
-            {
+            let is_unknown_artist = is_video_player
+                && (artist.to_lowercase()
+                    == format!("{}unknown artist", strings.artist_prefix.to_lowercase()))
+                || artist.to_lowercase() == "unknown artist";
+            let show_artist = !is_unknown_artist && !settings.hide_artist;
+            if show_artist {
                 payload = payload.state(&artist);
             }
 
-            payload = if media_info.is_track_position && (media_info.duration > 0) {
+            payload = if settings.hide_progress_bar {
+                payload
+            } else if live_channel.is_some() {
+                // Only elapsed time for streams
+                payload
+                    .timestamps(activity::Timestamps::new().start(time_start.try_into().unwrap()))
+            } else if media_info.is_track_position && (media_info.duration > 0) {
                 let time_end = time_start + media_info.duration;
                 if media_info.is_playing {
                     payload.timestamps(
@@ -793,6 +2170,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         activity::Timestamps::new().start(time_start.try_into().unwrap()),
                     )
                 }
+            } else if media_info.is_track_position {
+                // Only elapsed time when the length is unknown or was implausible
+                payload
+                    .timestamps(activity::Timestamps::new().start(time_start.try_into().unwrap()))
             } else {
                 payload.timestamps(activity::Timestamps::new().end(time_start.try_into().unwrap()))
             };
@@ -811,11 +2192,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 url_escape::encode_component(&listenbrainz_name)
             );
 
-            // Add YouTube URL to song title
-            payload = payload.details_url(&yt_url);
+            // Add YouTube URL (or Spotify track URL) to song title
+            payload = payload.details_url(spotify_url.as_deref().unwrap_or(&yt_url));
 
-            // Add activity buttons
+            // Add activity buttons, their names are logged with published activity
             let mut buttons = Vec::new();
+            let mut button_sources: Vec<&str> = Vec::new();
+            if let Some(url) = &spotify_url {
+                buttons.push(activity::Button::new(&strings.button_spotify, url));
+                button_sources.push("spotify");
+            }
+            if live_channel.is_some() {
+                buttons.push(activity::Button::new(
+                    &strings.button_stream,
+                    &media_info.url,
+                ));
+                button_sources.push("stream");
+            }
             let mut first_button = "";
             for button in &settings.button {
                 let initial_len = buttons.len();
@@ -832,42 +2225,72 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 match button.as_str() {
                     "yt" => {
-                        buttons.push(activity::Button::new(
-                            "Search this song on YouTube",
-                            &yt_url,
-                        ));
+                        buttons.push(activity::Button::new(&strings.button_yt, &yt_url));
                     }
                     "lastfm" => {
                         if lastfm_name.len() > 0 {
-                            buttons.push(activity::Button::new("Last.fm profile", &lastfm_url));
+                            buttons
+                                .push(activity::Button::new(&strings.button_lastfm, &lastfm_url));
                         }
                     }
+                    "lastfmTrack" => {
+                        buttons.push(activity::Button::new(
+                            &strings.button_lastfm_track,
+                            &lastfm_track_url,
+                        ));
+                    }
                     "listenbrainz" => {
                         if listenbrainz_name.len() > 0 {
                             buttons.push(activity::Button::new(
-                                "Listenbrainz profile",
+                                &strings.button_listenbrainz,
                                 &listenbrainz_url,
                             ));
                         }
                     }
+                    // Already added as "Play on Spotify" or "Watch Stream" button
+                    "mprisUrl" if spotify_url.is_some() || live_channel.is_some() => continue,
                     "mprisUrl" => {
                         if media_info.url.is_empty() {
                             // if mpris url is empty or not set convert button to yt button
-                            buttons.push(activity::Button::new(
-                                "Search this song on YouTube",
-                                &yt_url,
-                            ));
+                            buttons.push(activity::Button::new(&strings.button_yt, &yt_url));
                         } else {
                             if is_video_player {
-                                buttons.push(activity::Button::new("Watch Now", &media_info.url));
+                                buttons.push(activity::Button::new(
+                                    &strings.button_watch,
+                                    &media_info.url,
+                                ));
                             } else {
-                                buttons.push(activity::Button::new("Play Now", &media_info.url));
+                                buttons.push(activity::Button::new(
+                                    &strings.button_play,
+                                    &media_info.url,
+                                ));
                             }
                         }
                     }
+                    "musicbrainz" => match &recording_url {
+                        Some(url) => {
+                            buttons.push(activity::Button::new(&strings.button_musicbrainz, url))
+                        }
+                        None => continue,
+                    },
+                    "appleMusic" => match &apple_music_track {
+                        Some(track) => buttons.push(activity::Button::new(
+                            &strings.button_apple_music,
+                            &track.url,
+                        )),
+                        None => continue,
+                    },
+                    "tidal" | "deezer" | "qobuz" => {
+                        let label = match button.as_str() {
+                            "tidal" => &strings.button_tidal,
+                            "deezer" => &strings.button_deezer,
+                            _ => &strings.button_qobuz,
+                        };
+                        buttons.push(activity::Button::new(label, &service_urls[button.as_str()]));
+                    }
                     "shamelessAd" => {
                         buttons.push(activity::Button::new(
-                            "Get This RPC",
+                            &strings.button_shameless_ad,
                             "https://github.com/patryk-ku/music-discord-rpc",
                         ));
                     }
@@ -877,9 +2300,35 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // Make sure buttons wont repeat
                 if initial_len < buttons.len() {
                     first_button = button;
+                    button_sources.push(button);
                 }
             }
 
+            // Also the automatic Spotify and stream buttons
+            if settings.hide_buttons {
+                buttons.clear();
+                button_sources.clear();
+            }
+
+            // Buttons set by user script replace the configured ones
+            if let Some(script_buttons) = &script_result.buttons {
+                buttons = script_buttons
+                    .iter()
+                    .map(|(label, url)| activity::Button::new(label, url))
+                    .collect();
+                button_sources = vec!["script"];
+            }
+            let source_chain = format!(
+                "[sources] metadata: {}{}, cover: {}, buttons: {}",
+                metadata_source,
+                if script.is_some() { "+script" } else { "" },
+                image_source,
+                match button_sources.is_empty() {
+                    true => String::from("none"),
+                    false => button_sources.join("+"),
+                }
+            );
+
             payload = match buttons.is_empty() {
                 false => payload.buttons(buttons),
                 true => payload,
@@ -887,12 +2336,35 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             match client.set_activity(payload) {
                 Ok(_) => {
+                    metrics::inc(&metrics::ACTIVITY_UPDATES);
                     is_interrupted = false;
                     is_activity_set = true;
-                    println!("=> Set activity [{status_text}]: {song_name}");
+                    last_activity_update = Instant::now();
+                    let message = locale::fill(
+                        &strings.messages.activity_set,
+                        &[("status", status_label), ("song", &song_name)],
+                    );
+                    println!("{}", message);
+                    println!("{}", source_chain);
+                    last_activity.save(&track_key, time_start);
+                    control::publish_event("activity", &message);
+                    media_state::set(media_state::MediaState::new(
+                        &player_name,
+                        track_status,
+                        track_state,
+                        Some(media_state::PresenceState {
+                            details: title.clone(),
+                            state: show_artist.then(|| artist.clone()),
+                            large_text: (!settings.hide_album_name).then(|| album.clone()),
+                            large_image: image.clone(),
+                            cover_source: image_source.to_string(),
+                        }),
+                    ));
                 }
-                Err(_) => {
-                    println!("Could not set activity.");
+                Err(err) => {
+                    metrics::inc(&metrics::ACTIVITY_ERRORS);
+                    metrics::set_last_error(&format!("Could not set activity: {}", err));
+                    println!("{}", strings.messages.activity_error);
                     is_interrupted = true;
                     is_activity_set = false;
                     client.close()?;
@@ -900,9 +2372,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             };
 
-            sleep(Duration::from_secs(interval));
+            metrics::observe_loop(loop_start.elapsed());
+            network_worker.wait(refresh_interval.get());
         }
 
-        sleep(Duration::from_secs(interval));
+        sleep(refresh_interval.base());
     }
 }
@@ -0,0 +1,158 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils::{self, MediaInfo};
+
+// Version of the JSON given to external tools ("state" control command, hooks). It's increased
+// only when fields are removed, renamed or change meaning, new fields can be added at any time.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Versions the app can still produce, tools can ask for the one they understand
+const SUPPORTED_VERSIONS: [u32; 1] = [1];
+
+// Track as reported by the player, durations are in seconds
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TrackState {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub duration: u64,
+    pub position: Option<u64>, // None if the player doesn't report track position
+    pub url: String,
+    pub cover_url: String,
+    pub rating: Option<f64>,
+}
+
+impl TrackState {
+    pub fn new(media_info: &MediaInfo, cover_url: &str) -> TrackState {
+        TrackState {
+            title: media_info.title.clone(),
+            artist: media_info.artist.clone(),
+            album: media_info.album.clone(),
+            album_artist: media_info.album_artist.clone(),
+            duration: media_info.duration,
+            position: media_info.is_track_position.then_some(media_info.position),
+            url: media_info.url.clone(),
+            cover_url: match cover_url.starts_with("http") {
+                true => cover_url.to_string(),
+                false => String::new(),
+            },
+            rating: media_info.rating,
+        }
+    }
+}
+
+// Activity as it was sent to Discord, hidden fields are None
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PresenceState {
+    pub details: String,
+    pub state: Option<String>,
+    pub large_text: Option<String>,
+    pub large_image: String,
+    pub cover_source: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MediaState {
+    pub schema_version: u32,
+    pub app_version: &'static str,
+    pub updated_at: u64, // Unix time
+    pub player: String,
+    pub status: &'static str, // "playing", "paused" or "stopped"
+    pub track: TrackState,
+    pub presence: Option<PresenceState>, // None if there is no activity on Discord
+}
+
+// Playback status of the track
+pub fn status(media_info: &MediaInfo) -> &'static str {
+    match (media_info.is_playing, media_info.is_stopped) {
+        (true, _) => "playing",
+        (false, true) => "stopped",
+        (false, false) => "paused",
+    }
+}
+
+impl MediaState {
+    pub fn new(
+        player: &str,
+        status: &'static str,
+        track: TrackState,
+        presence: Option<PresenceState>,
+    ) -> MediaState {
+        MediaState {
+            schema_version: SCHEMA_VERSION,
+            app_version: utils::VERSION,
+            updated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            player: player.to_string(),
+            status,
+            track,
+            presence,
+        }
+    }
+}
+
+// Displayed track, shared with the control socket thread
+static CURRENT: Mutex<Option<MediaState>> = Mutex::new(None);
+
+pub fn set(state: MediaState) {
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = Some(state);
+    }
+}
+
+// No player is selected
+pub fn clear() {
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = None;
+    }
+}
+
+// Activity was cleared, the track is still known
+pub fn clear_presence() {
+    if let Ok(mut current) = CURRENT.lock() {
+        if let Some(state) = current.as_mut() {
+            state.presence = None;
+        }
+    }
+}
+
+// Tools ask for the version they understand, error if it can't be produced
+pub fn negotiate(requested: Option<&str>) -> Result<(), String> {
+    let supported = || {
+        SUPPORTED_VERSIONS
+            .iter()
+            .map(|version| version.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    match requested {
+        None => Ok(()),
+        Some(requested) => match requested.parse::<u32>() {
+            Ok(version) if SUPPORTED_VERSIONS.contains(&version) => Ok(()),
+            _ => Err(format!(
+                "unsupported schema version: {}, supported: {}",
+                requested,
+                supported()
+            )),
+        },
+    }
+}
+
+// Current state, "null" if no player is selected. Requested version is checked by negotiate(),
+// older versions will be converted here when the schema changes.
+pub fn to_json() -> String {
+    match CURRENT.lock().ok().and_then(|current| current.clone()) {
+        Some(state) => serde_json::to_string(&state).unwrap_or(String::from("null")),
+        None => String::from("null"),
+    }
+}
+
+// Track part for hooks, in the same format as in the "state" command
+pub fn track_json(media_info: &MediaInfo, cover_url: &str) -> serde_json::Value {
+    serde_json::to_value(TrackState::new(media_info, cover_url)).unwrap_or_default()
+}
@@ -0,0 +1,212 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::debug_log;
+
+pub static ACTIVITY_UPDATES: AtomicU64 = AtomicU64::new(0);
+pub static ACTIVITY_ERRORS: AtomicU64 = AtomicU64::new(0);
+pub static DISCORD_RECONNECTS: AtomicU64 = AtomicU64::new(0);
+pub static PLAYER_CHANGES: AtomicU64 = AtomicU64::new(0);
+pub static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+pub static LASTFM_HITS: AtomicU64 = AtomicU64::new(0);
+pub static LASTFM_MISSES: AtomicU64 = AtomicU64::new(0);
+pub static MUSICBRAINZ_HITS: AtomicU64 = AtomicU64::new(0);
+pub static MUSICBRAINZ_MISSES: AtomicU64 = AtomicU64::new(0);
+pub static ITUNES_HITS: AtomicU64 = AtomicU64::new(0);
+pub static ITUNES_MISSES: AtomicU64 = AtomicU64::new(0);
+pub static DEEZER_HITS: AtomicU64 = AtomicU64::new(0);
+pub static DEEZER_MISSES: AtomicU64 = AtomicU64::new(0);
+static LOOP_DURATION_COUNT: AtomicU64 = AtomicU64::new(0);
+static LOOP_DURATION_SUM_US: AtomicU64 = AtomicU64::new(0);
+static LOOP_DURATION_LAST_US: AtomicU64 = AtomicU64::new(0);
+
+// Last error message and its unix time, shown in diagnostic snapshots
+static LAST_ERROR: Mutex<Option<(String, u64)>> = Mutex::new(None);
+
+pub fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+// Time spent on one loop iteration (without the interval sleep)
+pub fn observe_loop(duration: Duration) {
+    let micros = duration.as_micros() as u64;
+    LOOP_DURATION_COUNT.fetch_add(1, Ordering::Relaxed);
+    LOOP_DURATION_SUM_US.fetch_add(micros, Ordering::Relaxed);
+    LOOP_DURATION_LAST_US.store(micros, Ordering::Relaxed);
+}
+
+pub fn set_last_error(message: &str) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    if let Ok(mut last_error) = LAST_ERROR.lock() {
+        *last_error = Some((message.to_string(), time));
+    }
+}
+
+// Counters and last error, sent to "--json" diagnostic commands through control socket
+pub fn snapshot() -> Value {
+    let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let last_error = match LAST_ERROR
+        .lock()
+        .ok()
+        .and_then(|last_error| last_error.clone())
+    {
+        Some((message, time)) => json!({ "message": message, "time": time }),
+        None => Value::Null,
+    };
+    json!({
+        "last_error": last_error,
+        "counters": {
+            "activity_updates": get(&ACTIVITY_UPDATES),
+            "activity_errors": get(&ACTIVITY_ERRORS),
+            "discord_reconnects": get(&DISCORD_RECONNECTS),
+            "player_changes": get(&PLAYER_CHANGES),
+            "cache_hits": get(&CACHE_HITS),
+            "lastfm_hits": get(&LASTFM_HITS),
+            "lastfm_misses": get(&LASTFM_MISSES),
+            "musicbrainz_hits": get(&MUSICBRAINZ_HITS),
+            "musicbrainz_misses": get(&MUSICBRAINZ_MISSES),
+            "itunes_hits": get(&ITUNES_HITS),
+            "itunes_misses": get(&ITUNES_MISSES),
+            "deezer_hits": get(&DEEZER_HITS),
+            "deezer_misses": get(&DEEZER_MISSES),
+            "loops": get(&LOOP_DURATION_COUNT),
+        },
+    })
+}
+
+// Prometheus text exposition format
+fn render() -> String {
+    let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let seconds = |micros: u64| micros as f64 / 1_000_000.0;
+    let mut out = String::new();
+
+    out.push_str("# HELP music_discord_rpc_activity_updates_total Discord activity updates.\n");
+    out.push_str("# TYPE music_discord_rpc_activity_updates_total counter\n");
+    out.push_str(&format!(
+        "music_discord_rpc_activity_updates_total{{result=\"success\"}} {}\n",
+        get(&ACTIVITY_UPDATES)
+    ));
+    out.push_str(&format!(
+        "music_discord_rpc_activity_updates_total{{result=\"error\"}} {}\n",
+        get(&ACTIVITY_ERRORS)
+    ));
+
+    out.push_str(
+        "# HELP music_discord_rpc_cover_requests_total Album cover lookups by provider.\n",
+    );
+    out.push_str("# TYPE music_discord_rpc_cover_requests_total counter\n");
+    for (provider, result, counter) in [
+        ("cache", "hit", &CACHE_HITS),
+        ("lastfm", "hit", &LASTFM_HITS),
+        ("lastfm", "miss", &LASTFM_MISSES),
+        ("musicbrainz", "hit", &MUSICBRAINZ_HITS),
+        ("musicbrainz", "miss", &MUSICBRAINZ_MISSES),
+        ("itunes", "hit", &ITUNES_HITS),
+        ("itunes", "miss", &ITUNES_MISSES),
+        ("deezer", "hit", &DEEZER_HITS),
+        ("deezer", "miss", &DEEZER_MISSES),
+    ] {
+        out.push_str(&format!(
+            "music_discord_rpc_cover_requests_total{{provider=\"{}\",result=\"{}\"}} {}\n",
+            provider,
+            result,
+            get(counter)
+        ));
+    }
+
+    out.push_str("# HELP music_discord_rpc_discord_reconnects_total Reconnections to Discord.\n");
+    out.push_str("# TYPE music_discord_rpc_discord_reconnects_total counter\n");
+    out.push_str(&format!(
+        "music_discord_rpc_discord_reconnects_total {}\n",
+        get(&DISCORD_RECONNECTS)
+    ));
+
+    out.push_str("# HELP music_discord_rpc_player_changes_total Changes of the selected player.\n");
+    out.push_str("# TYPE music_discord_rpc_player_changes_total counter\n");
+    out.push_str(&format!(
+        "music_discord_rpc_player_changes_total {}\n",
+        get(&PLAYER_CHANGES)
+    ));
+
+    out.push_str(
+        "# HELP music_discord_rpc_loop_duration_seconds Time spent on one loop iteration.\n",
+    );
+    out.push_str("# TYPE music_discord_rpc_loop_duration_seconds summary\n");
+    out.push_str(&format!(
+        "music_discord_rpc_loop_duration_seconds_sum {}\n",
+        seconds(get(&LOOP_DURATION_SUM_US))
+    ));
+    out.push_str(&format!(
+        "music_discord_rpc_loop_duration_seconds_count {}\n",
+        get(&LOOP_DURATION_COUNT)
+    ));
+
+    out.push_str("# HELP music_discord_rpc_last_loop_duration_seconds Time spent on the last loop iteration.\n");
+    out.push_str("# TYPE music_discord_rpc_last_loop_duration_seconds gauge\n");
+    out.push_str(&format!(
+        "music_discord_rpc_last_loop_duration_seconds {}\n",
+        seconds(get(&LOOP_DURATION_LAST_US))
+    ));
+
+    out
+}
+
+fn handle_client(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", render())
+    } else {
+        ("404 Not Found", String::from("Not Found\n"))
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Serve /metrics endpoint in background thread
+pub fn start_server(address: &str, debug_log: bool) {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!(
+                "\x1b[31mWARNING: Could not start metrics endpoint on {}: {}\x1b[0m",
+                address, err
+            );
+            return;
+        }
+    };
+    println!("Metrics available at: http://{}/metrics", address);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(err) = handle_client(stream) {
+                        debug_log!(debug_log, "[metrics] request error: {}", err);
+                    }
+                }
+                Err(err) => {
+                    debug_log!(debug_log, "[metrics] connection error: {}", err);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,256 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::control::PlayerCommand;
+use crate::debug_log;
+use crate::utils::{ArtData, MediaInfo};
+
+// Client for mpv JSON IPC (--input-ipc-server), used when mpv runs without the MPRIS script
+#[derive(Debug, Clone)]
+pub struct MpvIpc {
+    pub socket_path: PathBuf,
+}
+
+impl MpvIpc {
+    pub fn new(socket_path: &str) -> MpvIpc {
+        MpvIpc {
+            socket_path: PathBuf::from(socket_path),
+        }
+    }
+
+    fn connect(&self) -> Result<BufReader<UnixStream>, Box<dyn std::error::Error>> {
+        let stream = UnixStream::connect(&self.socket_path)?;
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+        Ok(BufReader::new(stream))
+    }
+
+    // Send a single command and wait for the reply with matching request_id (events are skipped)
+    fn command(
+        &self,
+        connection: &mut BufReader<UnixStream>,
+        request_id: u64,
+        command: Value,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let request = json!({ "command": command, "request_id": request_id });
+        connection
+            .get_mut()
+            .write_all(format!("{}\n", request).as_bytes())?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if connection.read_line(&mut line)? == 0 {
+                return Err("mpv closed the IPC connection".into());
+            }
+
+            let reply: Value = match serde_json::from_str(&line) {
+                Ok(reply) => reply,
+                Err(_) => continue,
+            };
+            if reply["request_id"].as_u64() != Some(request_id) {
+                continue;
+            }
+
+            return match reply["error"].as_str() {
+                Some("success") => Ok(reply["data"].clone()),
+                Some(err) => Err(err.to_string().into()),
+                None => Err("invalid reply from mpv".into()),
+            };
+        }
+    }
+
+    fn get_property(
+        &self,
+        connection: &mut BufReader<UnixStream>,
+        request_id: u64,
+        name: &str,
+    ) -> Option<Value> {
+        self.command(connection, request_id, json!(["get_property", name]))
+            .ok()
+    }
+
+    // Check if mpv is running and has any file loaded
+    pub fn is_available(&self) -> bool {
+        let mut connection = match self.connect() {
+            Ok(connection) => connection,
+            Err(_) => return false,
+        };
+        !self
+            .get_property(&mut connection, 1, "idle-active")
+            .and_then(|idle| idle.as_bool())
+            .unwrap_or(true)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        let mut connection = match self.connect() {
+            Ok(connection) => connection,
+            Err(_) => return false,
+        };
+        !self
+            .get_property(&mut connection, 1, "pause")
+            .and_then(|pause| pause.as_bool())
+            .unwrap_or(true)
+    }
+
+    pub fn send_command(&self, command: PlayerCommand) -> Result<(), Box<dyn std::error::Error>> {
+        let command = match command {
+            PlayerCommand::Play => json!(["set_property", "pause", false]),
+            PlayerCommand::Pause => json!(["set_property", "pause", true]),
+            PlayerCommand::PlayPause => json!(["cycle", "pause"]),
+            PlayerCommand::Next => json!(["playlist-next"]),
+            PlayerCommand::Previous => json!(["playlist-prev"]),
+        };
+        let mut connection = self.connect()?;
+        self.command(&mut connection, 1, command)?;
+        Ok(())
+    }
+
+    pub fn get_currently_playing(
+        &self,
+        debug_log: bool,
+    ) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+        let mut connection = match self.connect() {
+            Ok(connection) => connection,
+            Err(err) => {
+                return Err(format!(
+                    "Could not connect to mpv IPC socket {}: {}",
+                    self.socket_path.display(),
+                    err
+                )
+                .into())
+            }
+        };
+
+        let idle = self
+            .get_property(&mut connection, 1, "idle-active")
+            .and_then(|idle| idle.as_bool())
+            .unwrap_or(false);
+        if idle {
+            return Err("mpv is idle, nothing is playing.".into());
+        }
+
+        let metadata = self
+            .get_property(&mut connection, 2, "metadata")
+            .unwrap_or(Value::Null);
+        debug_log!(debug_log, "mpv metadata: {:#?}", metadata);
+
+        let media_title = self
+            .get_property(&mut connection, 3, "media-title")
+            .and_then(|title| title.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+        let is_playing = !self
+            .get_property(&mut connection, 4, "pause")
+            .and_then(|pause| pause.as_bool())
+            .unwrap_or(true);
+        let mut duration = self
+            .get_property(&mut connection, 5, "duration")
+            .and_then(|duration| duration.as_f64());
+        let mut position = self
+            .get_property(&mut connection, 6, "time-pos")
+            .and_then(|position| position.as_f64());
+        let path = self
+            .get_property(&mut connection, 7, "path")
+            .and_then(|path| path.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+
+        // mpv metadata keys depend on the container (ARTIST, Artist, artist...)
+        let tag = |key: &str| -> String {
+            match metadata.as_object() {
+                Some(tags) => tags
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                    .and_then(|(_, value)| value.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                None => String::new(),
+            }
+        };
+
+        // media-title is the title tag, the video title for links played with yt-dlp or the one
+        // set with --force-media-title, and the file name only if there is nothing better
+        let mut title = media_title.clone();
+        if title.is_empty() {
+            title = tag("title");
+        }
+        if title.is_empty() {
+            title = "Unknown Title".to_string();
+        }
+        let mut artist = tag("artist");
+        if artist.is_empty() {
+            artist = tag("uploader");
+        }
+        if artist.is_empty() {
+            artist = "Unknown Artist".to_string();
+        }
+        let mut album = tag("album");
+        let mut album_artist = tag("album_artist");
+        if album_artist.is_empty() {
+            album_artist = tag("album artist");
+        }
+
+        // For files with chapters (mixes, full albums) use the current chapter as a track
+        let chapter = self
+            .get_property(&mut connection, 8, "chapter")
+            .and_then(|chapter| chapter.as_i64())
+            .unwrap_or(-1);
+        if chapter >= 0 {
+            let chapters = self
+                .get_property(&mut connection, 9, "chapter-list")
+                .unwrap_or(Value::Null);
+            let current = &chapters[chapter as usize];
+            if let Some(chapter_title) = current["title"].as_str() {
+                if !chapter_title.is_empty() {
+                    debug_log!(debug_log, "mpv chapter: {} - {}", chapter, chapter_title);
+                    if album.is_empty() {
+                        album = title.clone();
+                    }
+                    title = chapter_title.to_string();
+
+                    let start = current["time"].as_f64().unwrap_or(0.0);
+                    let end = chapters[chapter as usize + 1]["time"]
+                        .as_f64()
+                        .or(duration)
+                        .unwrap_or(0.0);
+                    position = position.map(|p| (p - start).max(0.0));
+                    duration = Some((end - start).max(0.0));
+                }
+            }
+        }
+
+        if album.is_empty() {
+            album = "Unknown Album".to_string();
+        }
+        if album_artist.is_empty() {
+            album_artist = artist.clone();
+        }
+
+        let url = if path.starts_with("http://") || path.starts_with("https://") {
+            path
+        } else {
+            String::new()
+        };
+
+        Ok(MediaInfo {
+            title,
+            artist,
+            album_artist,
+            album,
+            is_playing,
+            is_stopped: false,
+            duration: duration.unwrap_or(0.0) as u64,
+            position: position.unwrap_or(0.0) as u64,
+            is_track_position: position.is_some(),
+            art_url: String::new(),
+            art_data: ArtData::default(),
+            url,
+            track_id: String::new(),
+            rating: None,
+            #[cfg(target_os = "macos")]
+            player_id: "mpv".to_string(),
+        })
+    }
+}
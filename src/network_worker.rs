@@ -0,0 +1,733 @@
+use pickledb::PickleDb;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::album_cache;
+use crate::album_info::{self, AlbumInfo};
+use crate::apple_music::{self, AppleMusicTrack};
+use crate::cache_db;
+use crate::cover_cache;
+use crate::cover_folder::{self, CoverFolder};
+use crate::cover_lookup::CoverLookup;
+use crate::debug_log;
+use crate::recording;
+use crate::service_links;
+use crate::square_cover::{self, SquareCovers};
+use crate::theaudiodb::{self, ArtistInfo};
+use crate::utils::{self, ArtData, MediaInfo};
+use crate::youtube;
+
+// Loved status of the track on Last.fm is checked again after this time
+const LOVED_CHECK_INTERVAL: Duration = Duration::from_secs(120);
+
+// Loved status is kept for this many tracks, the list is cleared when full
+const MAX_CACHED_LOVED: usize = 1000;
+
+// Links of uploaded artwork images are kept for this many images, the list is cleared when full
+const MAX_CACHED_ARTWORK: usize = 200;
+
+// Everything owned by the worker thread
+pub struct WorkerState {
+    pub cache_enabled: bool,
+    pub album_cache: PickleDb,
+    pub album_access: PickleDb,
+    pub album_cache_max_entries: usize,
+    pub artist_variants: PickleDb,
+    pub album_info_db: PickleDb,
+    pub album_info_enabled: bool,
+    pub cache_dir: PathBuf,
+    pub cache_fsync: bool,
+    pub cover_cache_max_size: u64,
+    pub lastfm_api_key: String,
+    pub theaudiodb_api_key: String,
+    pub artist_info: HashMap<String, ArtistInfo>,
+    pub artist_bio_enabled: bool,
+    pub cover_overrides: HashMap<String, String>,
+    pub cover_folder: Option<CoverFolder>,
+    pub use_musicbrainz: bool,
+    pub use_stores: bool,
+    pub parallel_cover_lookup: bool,
+    pub youtube_thumbnail_quality: String,
+    pub square_covers: Option<SquareCovers>,
+    pub artwork_upload_url: Option<String>,
+    pub debug_log: bool,
+}
+
+struct CoverRequest {
+    id: u64,
+    album_id: String,
+    media_info: MediaInfo,
+    refresh: bool, // Cached cover is removed before the lookup
+}
+
+// Cover link and album details found for the track
+pub struct CoverResult {
+    id: u64,
+    pub cover_url: String,
+    pub source: &'static str,
+    pub album_info: AlbumInfo,
+}
+
+enum Job {
+    Cover(Box<CoverRequest>),
+    StoreCover {
+        album_id: String,
+        url: String,
+    },
+    Thumbnail(String),
+    UploadArtwork {
+        hash: u64,
+        data: Vec<u8>,
+    },
+    LastfmAvatar(String),
+    LastfmLoved {
+        username: String,
+        artist: String,
+        title: String,
+    },
+    AppleMusic {
+        key: String,
+        media_info: Box<MediaInfo>,
+    },
+    ServiceLinks(String),
+    Recording {
+        key: String,
+        media_info: Box<MediaInfo>,
+    },
+}
+
+enum Reply {
+    Cover(CoverResult),
+    Thumbnail {
+        art_url: String,
+        url: String,
+    },
+    UploadedArtwork {
+        hash: u64,
+        url: String,
+    },
+    LastfmAvatar(String),
+    LastfmLoved {
+        key: String,
+        loved: bool,
+    },
+    AppleMusic {
+        key: String,
+        track: AppleMusicTrack,
+    },
+    ServiceLinks {
+        source_url: String,
+        links: HashMap<String, String>,
+    },
+    Recording {
+        key: String,
+        recording_id: String,
+    },
+    Wake,
+}
+
+// Wakes up the main loop waiting in NetworkWorker::wait, e.g. when a player appears
+#[derive(Clone)]
+pub struct Waker(Sender<Reply>);
+
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+impl Waker {
+    pub fn wake(&self) {
+        let _ = self.0.send(Reply::Wake);
+    }
+}
+
+// All HTTP requests are made in background thread, so they never delay activity updates.
+// Only the newest cover request is resolved: older ones waiting in the queue are dropped and a
+// lookup of a track that is no longer playing stops before its next HTTP request.
+pub struct NetworkWorker {
+    jobs: Sender<Job>,
+    replies: Receiver<Reply>,
+    wake_sender: Sender<Reply>,
+    woken: bool, // Waker was used since last wait
+    latest_id: Arc<AtomicU64>,
+    cover: Option<CoverResult>,
+    updated: bool, // Thumbnail or avatar fetched since last check
+    youtube_thumbnail_quality: String,
+    square_covers: bool,
+    thumbnails: HashMap<String, String>,
+    requested_thumbnails: HashSet<String>,
+    artwork_upload: bool,
+    uploaded_artwork: HashMap<u64, String>,
+    requested_artwork: HashSet<u64>,
+    lastfm_avatar: String,
+    lastfm_loved: HashMap<String, bool>,
+    lastfm_loved_checked: HashMap<String, Instant>,
+    apple_music_tracks: HashMap<String, AppleMusicTrack>,
+    requested_apple_music: HashSet<String>,
+    service_links: HashMap<String, HashMap<String, String>>,
+    requested_service_links: HashSet<String>,
+    recordings: HashMap<String, String>,
+    requested_recordings: HashSet<String>,
+}
+
+impl NetworkWorker {
+    pub fn start(mut state: WorkerState) -> NetworkWorker {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (reply_sender, reply_receiver) = mpsc::channel::<Reply>();
+        let latest_id = Arc::new(AtomicU64::new(0));
+        let youtube_thumbnail_quality = state.youtube_thumbnail_quality.clone();
+        let square_covers = state.square_covers.is_some();
+        let artwork_upload = state.artwork_upload_url.is_some();
+
+        let worker_latest_id = latest_id.clone();
+        let wake_sender = reply_sender.clone();
+        thread::spawn(move || {
+            let mut last_sync = SystemTime::now();
+            while let Ok(job) = job_receiver.recv() {
+                // Skip cover requests replaced by newer ones, other jobs are kept
+                let mut cover_request = None;
+                let mut other_jobs = Vec::new();
+                for job in std::iter::once(job).chain(job_receiver.try_iter()) {
+                    match job {
+                        Job::Cover(request) => {
+                            if let Some(old_request) = cover_request.replace(request) {
+                                debug_log!(
+                                    state.debug_log,
+                                    "Skipping cover lookup of: {}",
+                                    old_request.album_id
+                                );
+                            }
+                        }
+                        job => other_jobs.push(job),
+                    }
+                }
+
+                let mut replies = Vec::new();
+                if let Some(request) = cover_request {
+                    replies.push(Reply::Cover(state.find_cover(&request, &worker_latest_id)));
+                }
+                for job in other_jobs {
+                    replies.extend(state.run(job));
+                }
+                if state.cache_fsync {
+                    cache_db::sync_changed(&state.cache_dir, &mut last_sync);
+                }
+                for reply in replies {
+                    if reply_sender.send(reply).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        NetworkWorker {
+            jobs: job_sender,
+            replies: reply_receiver,
+            wake_sender,
+            woken: false,
+            latest_id,
+            cover: None,
+            updated: false,
+            youtube_thumbnail_quality,
+            square_covers,
+            thumbnails: HashMap::new(),
+            requested_thumbnails: HashSet::new(),
+            artwork_upload,
+            uploaded_artwork: HashMap::new(),
+            requested_artwork: HashSet::new(),
+            lastfm_avatar: String::new(),
+            lastfm_loved: HashMap::new(),
+            lastfm_loved_checked: HashMap::new(),
+            apple_music_tracks: HashMap::new(),
+            requested_apple_music: HashSet::new(),
+            service_links: HashMap::new(),
+            requested_service_links: HashSet::new(),
+            recordings: HashMap::new(),
+            requested_recordings: HashSet::new(),
+        }
+    }
+
+    // Queue cover lookup, returns id of the request used to get the result
+    pub fn request_cover(&mut self, album_id: &str, media_info: &MediaInfo, refresh: bool) -> u64 {
+        let id = self.latest_id.fetch_add(1, Ordering::SeqCst) + 1;
+        self.cover = None;
+        let request = CoverRequest {
+            id,
+            album_id: album_cache::normalize_key(album_id),
+            media_info: MediaInfo {
+                art_data: ArtData::default(),
+                ..media_info.clone()
+            },
+            refresh,
+        };
+        let _ = self.jobs.send(Job::Cover(Box::new(request)));
+        id
+    }
+
+    // Lookup in progress stops before its next HTTP request and its result is dropped
+    pub fn cancel_cover(&mut self) {
+        self.latest_id.fetch_add(1, Ordering::SeqCst);
+        self.cover = None;
+    }
+
+    // Cover found for request with given id, waits at most timeout
+    pub fn get_cover(&mut self, id: u64, timeout: Duration) -> Option<CoverResult> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.cover.as_ref().is_some_and(|cover| cover.id == id) {
+                return self.cover.take();
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(remaining) {
+                Ok(reply) => self.handle_reply(reply),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return None
+                }
+            }
+        }
+    }
+
+    // Handle finished jobs, returns true if thumbnail or avatar used in activity was fetched
+    pub fn receive(&mut self) -> bool {
+        while let Ok(reply) = self.replies.try_recv() {
+            self.handle_reply(reply);
+        }
+        std::mem::take(&mut self.updated)
+    }
+
+    #[cfg_attr(target_os = "macos", allow(dead_code))]
+    pub fn waker(&self) -> Waker {
+        Waker(self.wake_sender.clone())
+    }
+
+    // Sleep between activity updates, but wake up as soon as cover, thumbnail or avatar is
+    // fetched, so the activity is updated with it without waiting for the next refresh. Players
+    // appearing or disappearing wake it up too.
+    pub fn wait(&mut self, timeout: Duration) {
+        self.wait_until(timeout, |worker| {
+            worker.cover.is_some() || worker.updated || worker.woken
+        });
+    }
+
+    // Sleep while no player is running, results of lookups are not waited for
+    pub fn wait_for_player(&mut self, timeout: Duration) {
+        self.wait_until(timeout, |worker| worker.woken);
+    }
+
+    fn wait_until(&mut self, timeout: Duration, done: impl Fn(&NetworkWorker) -> bool) {
+        let deadline = Instant::now() + timeout;
+        while !done(self) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(remaining) {
+                Ok(reply) => self.handle_reply(reply),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(remaining);
+                    break;
+                }
+            }
+        }
+        self.woken = false;
+    }
+
+    fn handle_reply(&mut self, reply: Reply) {
+        match reply {
+            // Results of older requests are dropped
+            Reply::Cover(cover) => {
+                if cover.id == self.latest_id.load(Ordering::SeqCst) {
+                    self.cover = Some(cover);
+                }
+            }
+            // Checked links are remembered, so requests are made only once per video
+            Reply::Thumbnail { art_url, url } => {
+                if self.thumbnails.len() >= youtube::MAX_CACHED_THUMBNAILS {
+                    self.thumbnails.clear();
+                    self.requested_thumbnails.clear();
+                }
+                self.updated |= url != art_url;
+                self.thumbnails.insert(art_url, url);
+            }
+            // Failed uploads are remembered too, so the image isn't uploaded on every refresh
+            Reply::UploadedArtwork { hash, url } => {
+                if self.uploaded_artwork.len() >= MAX_CACHED_ARTWORK {
+                    self.uploaded_artwork.clear();
+                    self.requested_artwork.clear();
+                }
+                self.updated |= !url.is_empty();
+                self.uploaded_artwork.insert(hash, url);
+            }
+            Reply::LastfmAvatar(url) => {
+                self.updated |= !url.is_empty();
+                self.lastfm_avatar = url;
+            }
+            Reply::LastfmLoved { key, loved } => {
+                if self.lastfm_loved.len() >= MAX_CACHED_LOVED {
+                    self.lastfm_loved.clear();
+                    self.lastfm_loved_checked.clear();
+                }
+                self.updated |= self.lastfm_loved.get(&key).copied().unwrap_or(false) != loved;
+                self.lastfm_loved.insert(key, loved);
+            }
+            Reply::AppleMusic { key, track } => {
+                if self.apple_music_tracks.len() >= apple_music::MAX_CACHED_TRACKS {
+                    self.apple_music_tracks.clear();
+                    self.requested_apple_music.clear();
+                }
+                self.updated |= !track.url.is_empty();
+                self.apple_music_tracks.insert(key, track);
+            }
+            Reply::ServiceLinks { source_url, links } => {
+                if self.service_links.len() >= service_links::MAX_CACHED_TRACKS {
+                    self.service_links.clear();
+                    self.requested_service_links.clear();
+                }
+                self.updated |= !links.is_empty();
+                self.service_links.insert(source_url, links);
+            }
+            Reply::Recording { key, recording_id } => {
+                if self.recordings.len() >= recording::MAX_CACHED_RECORDINGS {
+                    self.recordings.clear();
+                    self.requested_recordings.clear();
+                }
+                self.updated |= !recording_id.is_empty();
+                self.recordings.insert(key, recording_id);
+            }
+            Reply::Wake => self.woken = true,
+        }
+    }
+
+    // Download cover image to the disk cache
+    pub fn store_cover(&self, album_id: &str, url: &str) {
+        let _ = self.jobs.send(Job::StoreCover {
+            album_id: album_id.to_string(),
+            url: url.to_string(),
+        });
+    }
+
+    // Better YouTube thumbnail (or square version of the artwork with square_covers) if already
+    // checked, else art_url is used until it's checked
+    pub fn get_thumbnail(&mut self, art_url: &str) -> String {
+        if !self.square_covers
+            && !youtube::can_replace_thumbnail(art_url, &self.youtube_thumbnail_quality)
+        {
+            return art_url.to_string();
+        }
+        if let Some(url) = self.thumbnails.get(art_url) {
+            return url.clone();
+        }
+        if self.requested_thumbnails.insert(art_url.to_string()) {
+            let _ = self.jobs.send(Job::Thumbnail(art_url.to_string()));
+        }
+        art_url.to_string()
+    }
+
+    // Link of the artwork image provided by the player, uploaded to cover_upload_url once per
+    // image because Discord accepts only links. None until it's uploaded or without upload URL.
+    pub fn get_uploaded_artwork(&mut self, art_data: &ArtData) -> Option<String> {
+        if !self.artwork_upload || art_data.0.is_empty() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        art_data.0.hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some(url) = self.uploaded_artwork.get(&hash) {
+            return Some(url.clone()).filter(|url| !url.is_empty());
+        }
+        if self.requested_artwork.insert(hash) {
+            let _ = self.jobs.send(Job::UploadArtwork {
+                hash,
+                data: art_data.0.clone(),
+            });
+        }
+        None
+    }
+
+    pub fn request_lastfm_avatar(&self, username: &str) {
+        let _ = self.jobs.send(Job::LastfmAvatar(username.to_string()));
+    }
+
+    // Empty until fetched
+    pub fn lastfm_avatar(&self) -> &str {
+        &self.lastfm_avatar
+    }
+
+    // Whether the track is loved on Last.fm, false until checked. The user can love the track
+    // while listening, so it's checked again when the result is older than LOVED_CHECK_INTERVAL.
+    pub fn is_lastfm_loved(&mut self, username: &str, artist: &str, title: &str) -> bool {
+        let key = format!("{} - {}", artist, title);
+        let is_outdated = self
+            .lastfm_loved_checked
+            .get(&key)
+            .is_none_or(|checked| checked.elapsed() >= LOVED_CHECK_INTERVAL);
+        if is_outdated {
+            self.lastfm_loved_checked
+                .insert(key.clone(), Instant::now());
+            let _ = self.jobs.send(Job::LastfmLoved {
+                username: username.to_string(),
+                artist: artist.to_string(),
+                title: title.to_string(),
+            });
+        }
+        self.lastfm_loved.get(&key).copied().unwrap_or(false)
+    }
+
+    // Track in Apple Music catalog if already found, else it's searched in background
+    pub fn get_apple_music_track(&mut self, media_info: &MediaInfo) -> Option<&AppleMusicTrack> {
+        let key = apple_music::track_key(media_info);
+        if self.requested_apple_music.insert(key.clone()) {
+            let _ = self.jobs.send(Job::AppleMusic {
+                key: key.clone(),
+                media_info: Box::new(MediaInfo {
+                    art_data: ArtData::default(),
+                    ..media_info.clone()
+                }),
+            });
+        }
+        self.apple_music_tracks
+            .get(&key)
+            .filter(|track| !track.url.is_empty())
+    }
+
+    // Links of the track on other services if already found, else they are resolved in background
+    pub fn get_service_links(&mut self, source_url: &str) -> Option<&HashMap<String, String>> {
+        if self.requested_service_links.insert(source_url.to_string()) {
+            let _ = self.jobs.send(Job::ServiceLinks(source_url.to_string()));
+        }
+        self.service_links.get(source_url)
+    }
+
+    // MusicBrainz recording id of the track if already found, else it's searched in background
+    pub fn get_recording(&mut self, media_info: &MediaInfo) -> Option<&str> {
+        let key = recording::track_key(media_info);
+        if self.requested_recordings.insert(key.clone()) {
+            let _ = self.jobs.send(Job::Recording {
+                key: key.clone(),
+                media_info: Box::new(MediaInfo {
+                    art_data: ArtData::default(),
+                    ..media_info.clone()
+                }),
+            });
+        }
+        self.recordings
+            .get(&key)
+            .map(|id| id.as_str())
+            .filter(|id| !id.is_empty())
+    }
+}
+
+impl WorkerState {
+    fn find_cover(&mut self, request: &CoverRequest, latest_id: &AtomicU64) -> CoverResult {
+        let is_cancelled = || latest_id.load(Ordering::SeqCst) != request.id;
+
+        if request.refresh {
+            self.forget_cover(&request.album_id);
+        }
+
+        // Panic in cover fetching should not kill the worker
+        let result = utils::catch_panic(|| {
+            let mut lookup = CoverLookup {
+                cache_enabled: self.cache_enabled,
+                album_cache: &mut self.album_cache,
+                artist_variants: &mut self.artist_variants,
+                lastfm_api_key: &self.lastfm_api_key,
+                theaudiodb_api_key: &self.theaudiodb_api_key,
+                artist_info: &mut self.artist_info,
+                cover_overrides: &self.cover_overrides,
+                cover_folder: self.cover_folder.as_mut(),
+                use_musicbrainz: self.use_musicbrainz,
+                use_stores: self.use_stores,
+                parallel: self.parallel_cover_lookup,
+                is_cancelled: &is_cancelled,
+                debug_log: self.debug_log,
+                source: "",
+            };
+            let cover_url = lookup.find_cover(&request.album_id, &request.media_info);
+            (cover_url, lookup.source)
+        });
+
+        let (cover_url, source) = match result {
+            Ok(cover) => cover,
+            Err(err) => {
+                println!(
+                    "\x1b[31mPanic while fetching album cover for \"{}\": {}\x1b[0m",
+                    request.album_id, err
+                );
+                (String::from("missing-cover"), "")
+            }
+        };
+
+        // Update album last use time and remove least recently used albums
+        if self.cache_enabled && self.album_cache.exists(&request.album_id) {
+            album_cache::touch(&mut self.album_access, &request.album_id);
+            album_cache::evict(
+                &mut self.album_cache,
+                &mut self.album_access,
+                self.album_cache_max_entries,
+                self.debug_log,
+            );
+        }
+
+        let mut album_info = AlbumInfo::default();
+        if self.album_info_enabled && !is_cancelled() {
+            album_info = album_info::get_album_info(
+                &mut self.album_info_db,
+                &request.album_id,
+                &request.media_info.album_artist,
+                &request.media_info.album,
+                self.debug_log,
+            );
+        }
+        if self.artist_bio_enabled && !is_cancelled() {
+            let artist = match request.media_info.album_artist.is_empty() {
+                true => &request.media_info.artist,
+                false => &request.media_info.album_artist,
+            };
+            album_info.bio = theaudiodb::get_artist_info_cached(
+                &mut self.artist_info,
+                &self.theaudiodb_api_key,
+                artist,
+                self.debug_log,
+            )
+            .bio;
+        }
+
+        CoverResult {
+            id: request.id,
+            cover_url,
+            source,
+            album_info,
+        }
+    }
+
+    // Cached link and image of the album cover, also the pinned one
+    fn forget_cover(&mut self, album_id: &str) {
+        let _ = self.album_cache.rem(album_id);
+        let _ = self.album_access.rem(album_id);
+        cover_cache::remove_cover(&self.cache_dir, album_id);
+        debug_log!(self.debug_log, "[cache] removed cover of: {}", album_id);
+    }
+
+    fn run(&mut self, job: Job) -> Option<Reply> {
+        match job {
+            Job::Cover(_) => None,
+            Job::StoreCover { album_id, url } => {
+                cover_cache::store_cover(
+                    &self.cache_dir,
+                    &album_id,
+                    &url,
+                    self.cover_cache_max_size,
+                    self.debug_log,
+                );
+                None
+            }
+            Job::Thumbnail(art_url) => {
+                let url = youtube::get_thumbnail_url(
+                    &art_url,
+                    &self.youtube_thumbnail_quality,
+                    self.debug_log,
+                );
+                let url = match &self.square_covers {
+                    Some(square_covers) => {
+                        square_cover::get_square_url(&url, square_covers, self.debug_log)
+                    }
+                    None => url,
+                };
+                Some(Reply::Thumbnail { art_url, url })
+            }
+            Job::UploadArtwork { hash, data } => {
+                let upload_url = self.artwork_upload_url.as_deref().unwrap_or_default();
+                let url = match cover_folder::upload_artwork(upload_url, hash, data) {
+                    Ok(url) => {
+                        debug_log!(self.debug_log, "[artwork] uploaded: {}", url);
+                        url
+                    }
+                    Err(err) => {
+                        debug_log!(self.debug_log, "[artwork] upload failed: {}", err);
+                        String::new()
+                    }
+                };
+                Some(Reply::UploadedArtwork { hash, url })
+            }
+            Job::LastfmAvatar(username) => {
+                let url = utils::get_lastfm_avatar(&username, &self.lastfm_api_key);
+                debug_log!(self.debug_log, "lastfm_avatar: {}", url);
+                Some(Reply::LastfmAvatar(url))
+            }
+            Job::AppleMusic { key, media_info } => {
+                let track = match apple_music::search(
+                    &media_info.artist,
+                    &media_info.title,
+                    &media_info.album,
+                ) {
+                    Ok(track) => track,
+                    Err(err) => {
+                        debug_log!(self.debug_log, "[apple music] search failed: {}", err);
+                        AppleMusicTrack::default()
+                    }
+                };
+                debug_log!(self.debug_log, "[apple music] {}: {:?}", key, track);
+                Some(Reply::AppleMusic { key, track })
+            }
+            Job::LastfmLoved {
+                username,
+                artist,
+                title,
+            } => {
+                let loved =
+                    match utils::get_lastfm_loved(&artist, &title, &username, &self.lastfm_api_key)
+                    {
+                        Ok(loved) => loved,
+                        Err(err) => {
+                            debug_log!(self.debug_log, "[last.fm] track.getInfo failed: {}", err);
+                            return None;
+                        }
+                    };
+                debug_log!(
+                    self.debug_log,
+                    "[last.fm] {} - {} loved: {}",
+                    artist,
+                    title,
+                    loved
+                );
+                Some(Reply::LastfmLoved {
+                    key: format!("{} - {}", artist, title),
+                    loved,
+                })
+            }
+            Job::ServiceLinks(source_url) => {
+                let links = match service_links::get_links(&source_url) {
+                    Ok(links) => links,
+                    Err(err) => {
+                        debug_log!(self.debug_log, "[odesli] request failed: {}", err);
+                        HashMap::new()
+                    }
+                };
+                debug_log!(self.debug_log, "[odesli] {}: {:?}", source_url, links);
+                Some(Reply::ServiceLinks { source_url, links })
+            }
+            Job::Recording { key, media_info } => {
+                let recording_id = match recording::search(&media_info) {
+                    Ok(recording_id) => recording_id,
+                    Err(err) => {
+                        debug_log!(
+                            self.debug_log,
+                            "[musicbrainz] recording search failed: {}",
+                            err
+                        );
+                        String::new()
+                    }
+                };
+                debug_log!(
+                    self.debug_log,
+                    "[musicbrainz] recording of {}: {}",
+                    key,
+                    recording_id
+                );
+                Some(Reply::Recording { key, recording_id })
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use serde_json::Value;
+use std::process::Command;
+
+use crate::debug_log;
+use crate::utils::{ArtData, MediaInfo};
+
+// Reads media properties of PipeWire audio streams using pw-dump, for apps that set stream
+// metadata (media.title, media.artist) but don't have MPRIS support
+#[derive(Debug, Clone)]
+pub struct PipeWireStreams {}
+
+impl PipeWireStreams {
+    pub fn new() -> PipeWireStreams {
+        PipeWireStreams {}
+    }
+
+    // Output audio streams with title, the running ones first
+    fn get_streams(&self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let output = Command::new("pw-dump").output()?;
+        if !output.status.success() {
+            return Err(format!("pw-dump exited with {}", output.status).into());
+        }
+        let objects: Vec<Value> = serde_json::from_slice(&output.stdout)?;
+
+        let mut streams: Vec<Value> = objects
+            .into_iter()
+            .filter(|object| {
+                let props = &object["info"]["props"];
+                object["type"].as_str() == Some("PipeWire:Interface:Node")
+                    && props["media.class"].as_str() == Some("Stream/Output/Audio")
+                    && props["media.title"]
+                        .as_str()
+                        .is_some_and(|title| !title.is_empty())
+            })
+            .collect();
+        streams.sort_by_key(|stream| stream["info"]["state"].as_str() != Some("running"));
+        Ok(streams)
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.get_streams().is_ok_and(|streams| !streams.is_empty())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self.get_streams() {
+            Ok(streams) => streams
+                .first()
+                .is_some_and(|stream| stream["info"]["state"].as_str() == Some("running")),
+            Err(_) => false,
+        }
+    }
+
+    pub fn get_currently_playing(
+        &self,
+        debug_log: bool,
+    ) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+        let streams = match self.get_streams() {
+            Ok(streams) => streams,
+            Err(err) => return Err(format!("Could not read PipeWire streams: {}", err).into()),
+        };
+        let stream = match streams.first() {
+            Some(stream) => stream,
+            None => return Err("No PipeWire stream with metadata.".into()),
+        };
+        let props = &stream["info"]["props"];
+        debug_log!(debug_log, "PipeWire stream properties: {:#?}", props);
+
+        let prop = |key: &str| props[key].as_str().unwrap_or_default().to_string();
+
+        let title = prop("media.title");
+        let mut artist = prop("media.artist");
+        if artist.is_empty() {
+            artist = "Unknown Artist".to_string();
+        }
+        let mut album = prop("media.album");
+        if album.is_empty() {
+            album = "Unknown Album".to_string();
+        }
+
+        Ok(MediaInfo {
+            title,
+            album_artist: artist.clone(),
+            artist,
+            album,
+            is_playing: stream["info"]["state"].as_str() == Some("running"),
+            is_stopped: false,
+            duration: 0,
+            position: 0,
+            is_track_position: false,
+            art_url: String::new(),
+            art_data: ArtData::default(),
+            url: String::new(),
+            track_id: String::new(),
+            rating: None,
+            #[cfg(target_os = "macos")]
+            player_id: "PipeWire".to_string(),
+        })
+    }
+}
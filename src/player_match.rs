@@ -0,0 +1,64 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Compiled "re:" patterns, invalid ones are stored as None so the warning is printed once
+static PATTERNS: Mutex<Vec<(String, Option<Regex>)>> = Mutex::new(Vec::new());
+
+fn regex_matches<S: AsRef<str>>(pattern: &str, names: &[S]) -> bool {
+    let mut patterns = PATTERNS.lock().unwrap_or_else(|err| err.into_inner());
+    let index = match patterns.iter().position(|(p, _)| p == pattern) {
+        Some(index) => index,
+        None => {
+            let regex = Regex::new(pattern)
+                .map_err(|err| {
+                    println!(
+                        "\x1b[33mInvalid regular expression in player list: \"{}\": {}\x1b[0m",
+                        pattern, err
+                    )
+                })
+                .ok();
+            patterns.push((pattern.to_string(), regex));
+            patterns.len() - 1
+        }
+    };
+    match &patterns[index].1 {
+        Some(regex) => names.iter().any(|name| regex.is_match(name.as_ref())),
+        None => false,
+    }
+}
+
+// Player list entry matches any of the player names (identity or D-Bus bus name):
+// "VLC media player" - exact name, "Chrom*" - name starting with "Chrom",
+// "re:^org\.mpris\.MediaPlayer2\.chromium\.instance\d+$" - regular expression
+pub fn matches<S: AsRef<str>>(entry: &str, names: &[S]) -> bool {
+    if let Some(pattern) = entry.strip_prefix("re:") {
+        return regex_matches(pattern, names);
+    }
+    if let Some(prefix) = entry.strip_suffix('*') {
+        return names.iter().any(|name| name.as_ref().starts_with(prefix));
+    }
+    names.iter().any(|name| name.as_ref() == entry)
+}
+
+// Position of the first matching entry, used for allowlist order.
+// Shared by all player lists: allowlist and video_players.
+pub fn position<S: AsRef<str>>(list: &[String], names: &[S]) -> Option<usize> {
+    list.iter().position(|entry| matches(entry, names))
+}
+
+pub fn is_listed<S: AsRef<str>>(list: &[String], names: &[S]) -> bool {
+    position(list, names).is_some()
+}
+
+// Importance of an allowlisted player, lower is better: entry priority (negated, higher priority
+// is more important), then position of the entry
+pub fn rank<S: AsRef<str>>(
+    allowlist: &[String],
+    priorities: &HashMap<String, i64>,
+    names: &[S],
+) -> Option<(i64, usize)> {
+    let position = position(allowlist, names)?;
+    let priority = priorities.get(&allowlist[position]).copied().unwrap_or(0);
+    Some((-priority, position))
+}
@@ -0,0 +1,74 @@
+#![cfg(target_os = "linux")]
+
+use dbus::blocking::Connection;
+use dbus::channel::Channel;
+use dbus::message::MatchRule;
+use std::env;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use crate::debug_log;
+use crate::network_worker::Waker;
+
+// Wait before connecting again after the bus was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+fn open_private(address: &str) -> Result<Connection, dbus::Error> {
+    let mut channel = Channel::open_private(address)?;
+    channel.register()?;
+    Ok(Connection::from(channel))
+}
+
+// The same bus as used to find players, see utils::new_player_finder
+fn connect(dbus_address: Option<&str>) -> Result<Connection, dbus::Error> {
+    match dbus_address.map(str::trim) {
+        None | Some("") | Some("session") => Connection::new_session().or_else(|err| {
+            let path = PathBuf::from(env::var_os("XDG_RUNTIME_DIR").ok_or(err)?).join("bus");
+            open_private(&format!("unix:path={}", path.display()))
+        }),
+        Some("system") => Connection::new_system(),
+        Some(address) => open_private(address),
+    }
+}
+
+fn watch(connection: &Connection, waker: &Waker, debug_log: bool) -> Result<(), dbus::Error> {
+    let rule = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged")
+        .with_sender("org.freedesktop.DBus");
+    let waker = waker.clone();
+    connection.add_match(
+        rule,
+        move |(name, old_owner, new_owner): (String, String, String), _, _| {
+            if name.starts_with("org.mpris.MediaPlayer2.") {
+                debug_log!(
+                    debug_log,
+                    "[dbus] player {}: {}",
+                    match (old_owner.is_empty(), new_owner.is_empty()) {
+                        (true, _) => "appeared",
+                        (_, true) => "disappeared",
+                        _ => "replaced",
+                    },
+                    name
+                );
+                waker.wake();
+            }
+            true
+        },
+    )?;
+    loop {
+        connection.process(Duration::from_secs(60))?;
+    }
+}
+
+// Wakes the main loop as soon as an MPRIS player appears or disappears on D-Bus, so a started
+// player is displayed without waiting for the whole interval
+pub fn start(dbus_address: Option<String>, waker: Waker, debug_log: bool) {
+    thread::spawn(move || loop {
+        let result = connect(dbus_address.as_deref())
+            .and_then(|connection| watch(&connection, &waker, debug_log));
+        if let Err(err) = result {
+            debug_log!(debug_log, "[dbus] player watch error: {}", err);
+        }
+        thread::sleep(RECONNECT_DELAY);
+    });
+}
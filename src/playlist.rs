@@ -0,0 +1,120 @@
+#[cfg(target_os = "linux")]
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+#[cfg(target_os = "linux")]
+use mpris::Player;
+#[cfg(target_os = "linux")]
+use std::collections::HashSet;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use crate::debug_log;
+
+// Position of the track in the player's track list ("3/24") and name of the active playlist,
+// empty when the player doesn't provide them ({queue} and {playlist} in templates)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistContext {
+    pub queue: String,
+    pub playlist: String,
+}
+
+// Reads the MPRIS TrackList and Playlists interfaces. The mpris crate doesn't support playlists,
+// so they are read with a separate connection, kept open between refreshes.
+#[cfg(target_os = "linux")]
+pub struct PlaylistReader {
+    dbus_address: Option<String>,
+    connection: Option<dbus::blocking::Connection>,
+    // Bus names of players without the Playlists interface, so they are not asked again
+    unsupported: HashSet<String>,
+    debug_log: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl PlaylistReader {
+    pub fn new(dbus_address: Option<&str>, debug_log: bool) -> PlaylistReader {
+        PlaylistReader {
+            dbus_address: dbus_address.map(str::to_string),
+            connection: None,
+            unsupported: HashSet::new(),
+            debug_log,
+        }
+    }
+
+    fn connect(&self) -> Result<dbus::blocking::Connection, dbus::Error> {
+        match self.dbus_address.as_deref().map(str::trim) {
+            None | Some("") | Some("session") => dbus::blocking::Connection::new_session(),
+            Some("system") => dbus::blocking::Connection::new_system(),
+            Some(address) => {
+                let mut channel = dbus::channel::Channel::open_private(address)?;
+                channel.register()?;
+                Ok(channel.into())
+            }
+        }
+    }
+
+    // Name of the active playlist, ActivePlaylist is (valid, (id, name, icon))
+    fn active_playlist(&mut self, bus_name: &str) -> String {
+        if self.unsupported.contains(bus_name) {
+            return String::new();
+        }
+        if self.connection.is_none() {
+            match self.connect() {
+                Ok(connection) => self.connection = Some(connection),
+                Err(err) => {
+                    debug_log!(self.debug_log, "[playlist] could not connect: {}", err);
+                    return String::new();
+                }
+            }
+        }
+        let Some(connection) = &self.connection else {
+            return String::new();
+        };
+
+        let proxy = connection.with_proxy(
+            bus_name,
+            "/org/mpris/MediaPlayer2",
+            Duration::from_millis(500),
+        );
+        match proxy.get::<(bool, (dbus::Path, String, String))>(
+            "org.mpris.MediaPlayer2.Playlists",
+            "ActivePlaylist",
+        ) {
+            Ok((true, (_, name, _))) => name,
+            Ok((false, _)) => String::new(),
+            Err(err) => {
+                debug_log!(
+                    self.debug_log,
+                    "[playlist] no active playlist in {}: {}",
+                    bus_name,
+                    err
+                );
+                match err.name() {
+                    Some("org.freedesktop.DBus.Error.UnknownInterface")
+                    | Some("org.freedesktop.DBus.Error.UnknownProperty")
+                    | Some("org.freedesktop.DBus.Error.InvalidArgs") => {
+                        self.unsupported.insert(bus_name.to_string());
+                    }
+                    // Connection could be broken, a new one is opened next time
+                    _ => self.connection = None,
+                }
+                String::new()
+            }
+        }
+    }
+
+    pub fn get(&mut self, player: &Player, track_id: &str) -> PlaylistContext {
+        let queue = match player.checked_get_track_list() {
+            Ok(Some(track_list)) if !track_id.is_empty() => track_list
+                .ids()
+                .iter()
+                .position(|id| id.as_str() == track_id)
+                .map(|index| format!("{}/{}", index + 1, track_list.len()))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        PlaylistContext {
+            queue,
+            playlist: self.active_playlist(player.bus_name()),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::cache_db;
+use crate::debug_log;
+
+// Longer gaps between checks (suspend, no player) are not counted as time with activity
+const MAX_GAP: Duration = Duration::from_secs(60);
+
+// Used time is written to disk at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Date of the budget day in local time, the day starts at reset_hour
+fn current_day(reset_hour: u32) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0) as i64;
+    let time = (now - reset_hour as i64 * 3600) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return String::new();
+    }
+    format!(
+        "{}-{:02}-{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday
+    )
+}
+
+// Daily limit of time with activity visible on Discord. Used time is saved in cache directory,
+// so restarts don't reset it.
+pub struct PresenceBudget {
+    limit: Duration,
+    reset_hour: u32,
+    db: PickleDb,
+    day: String,
+    used: Duration,
+    saved: Duration,
+    shown_since: Option<Instant>,
+    debug_log: bool,
+}
+
+impl PresenceBudget {
+    pub fn new(
+        limit_minutes: u64,
+        reset_hour: u32,
+        cache_dir: &Path,
+        cache_on_disk: bool,
+        debug_log: bool,
+    ) -> PresenceBudget {
+        let path = cache_dir.join("presence_time.db");
+        let policy = || match cache_on_disk {
+            true => PickleDbDumpPolicy::AutoDump,
+            false => PickleDbDumpPolicy::NeverDump,
+        };
+        let db = cache_db::load(&path, policy);
+
+        let day = current_day(reset_hour);
+        let used = match db.get::<String>("day") == Some(day.clone()) {
+            true => Duration::from_secs(db.get::<u64>("seconds").unwrap_or(0)),
+            false => Duration::ZERO,
+        };
+        debug_log!(
+            debug_log,
+            "Presence time used on {}: {}s of {}m",
+            day,
+            used.as_secs(),
+            limit_minutes
+        );
+
+        PresenceBudget {
+            limit: Duration::from_secs(limit_minutes * 60),
+            reset_hour,
+            db,
+            day,
+            used,
+            saved: used,
+            shown_since: None,
+            debug_log,
+        }
+    }
+
+    fn save(&mut self) {
+        if let Err(err) = self
+            .db
+            .set("day", &self.day)
+            .and_then(|_| self.db.set("seconds", &self.used.as_secs()))
+        {
+            debug_log!(self.debug_log, "Could not save presence time: {}", err);
+        }
+        self.saved = self.used;
+    }
+
+    // Count time since the last check if activity was shown then, is_shown is the current state
+    pub fn update(&mut self, is_shown: bool) {
+        let day = current_day(self.reset_hour);
+        if day != self.day {
+            debug_log!(self.debug_log, "New presence day: {}", day);
+            self.day = day;
+            self.used = Duration::ZERO;
+            self.save();
+        }
+
+        if let Some(shown_since) = self.shown_since {
+            let elapsed = shown_since.elapsed();
+            if elapsed <= MAX_GAP {
+                self.used += elapsed;
+            }
+        }
+        self.shown_since = is_shown.then(Instant::now);
+
+        if self.used >= self.saved + SAVE_INTERVAL || self.is_exceeded() && self.saved != self.used
+        {
+            self.save();
+        }
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.used >= self.limit
+    }
+}
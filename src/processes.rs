@@ -0,0 +1,91 @@
+// Running processes, used to detect streaming apps and games
+
+// Directories of running processes in /proc with their names from /proc/<pid>/stat.
+// Exited processes not yet reaped by their parent (zombies) are skipped.
+#[cfg(target_os = "linux")]
+fn get_processes() -> Vec<(std::path::PathBuf, String)> {
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut processes = Vec::new();
+    for entry in entries.flatten() {
+        if !entry
+            .file_name()
+            .to_str()
+            .is_some_and(|pid| pid.bytes().all(|b| b.is_ascii_digit()))
+        {
+            continue;
+        }
+        let path = entry.path();
+        // "<pid> (<name>) <state> ...", the name may contain spaces and parentheses
+        let stat = match std::fs::read_to_string(path.join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        let (name, state) = match (stat.find('('), stat.rfind(')')) {
+            (Some(start), Some(end)) if start < end => {
+                (&stat[start + 1..end], stat[end + 1..].trim_start())
+            }
+            _ => continue,
+        };
+        if state.starts_with('Z') {
+            continue;
+        }
+        processes.push((path.clone(), name.to_string()));
+    }
+    processes
+}
+
+// Names of running processes and their executable file names
+#[cfg(target_os = "linux")]
+pub fn get_process_names() -> Vec<String> {
+    let mut names = Vec::new();
+    for (path, name) in get_processes() {
+        names.push(name);
+        if let Some(name) = std::fs::read_link(path.join("exe")).ok().and_then(|exe| {
+            exe.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        }) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_process_names() -> Vec<String> {
+    let output = match std::process::Command::new("ps")
+        .args(["-axco", "comm="])
+        .output()
+    {
+        Ok(output) => output,
+        Err(_) => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+// Steam starts every game with "reaper SteamLaunch AppId=<id> -- <game>", returns the app ID
+#[cfg(target_os = "linux")]
+pub fn get_steam_game() -> Option<String> {
+    get_processes().into_iter().find_map(|(path, _)| {
+        let cmdline = std::fs::read(path.join("cmdline")).ok()?;
+        let mut args = cmdline.split(|&b| b == 0);
+        if !args.any(|arg| arg == b"SteamLaunch") {
+            return None;
+        }
+        Some(
+            args.find_map(|arg| arg.strip_prefix(b"AppId="))
+                .map(|id| String::from_utf8_lossy(id).to_string())
+                .unwrap_or_default(),
+        )
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_steam_game() -> Option<String> {
+    None
+}
@@ -0,0 +1,199 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+use crate::player_match;
+use crate::settings::PlayerQuirks;
+use crate::utils::MediaInfo;
+
+// Time between the first and the second position read with timestamps: lagging
+#[cfg(target_os = "linux")]
+pub const POSITION_REREAD_DELAY: Duration = Duration::from_millis(200);
+
+// Difference from the expected position (in seconds) that is treated as seeking
+#[cfg(target_os = "linux")]
+const POSITION_JUMP_TOLERANCE: u64 = 2;
+
+// Longer tracks or positions are treated as reported in wrong units or broken
+const MAX_TRACK_LENGTH: u64 = 24 * 60 * 60;
+
+// Position can be a bit past the length at the end of the track
+const POSITION_TOLERANCE: u64 = 5;
+
+// Deck number or letter of DJ software, e.g. Mixxx "[Channel1]" groups, "deck2" or "Deck B"
+static DECK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:deck|channel)[\s_-]?(?P<deck>[1-9]|[a-d]\b)")
+        .expect("deck pattern is valid")
+});
+
+// Workarounds for bugs of specific players, matched like allowlist entries. Values set in
+// player_quirks config replace these.
+fn builtin() -> Vec<(&'static str, PlayerQuirks)> {
+    let apple_music = PlayerQuirks {
+        album_suffixes: Some(vec![String::from(" - EP"), String::from(" - Single")]),
+        ..Default::default()
+    };
+    // Position is updated with a delay after seeking, so the progress bar jumps back
+    let lagging_position = PlayerQuirks {
+        timestamps: Some(String::from("lagging")),
+        ..Default::default()
+    };
+    // DJ software, the track heard on the master output is shown as "Playing Mixxx"
+    // with "Deck A: Artist - Title", the deck is left out when it's unknown
+    let mixxx = PlayerQuirks {
+        activity_type: Some(String::from("playing")),
+        details_format: Some(String::from("[Deck {deck}: ]{artist} - {title}")),
+        ..Default::default()
+    };
+    vec![
+        ("com.apple.Music", apple_music.clone()),
+        ("com.apple.iTunes", apple_music.clone()),
+        ("Cider", apple_music),
+        ("Strawberry", lagging_position.clone()),
+        ("Clementine", lagging_position),
+        ("Mixxx", mixxx),
+    ]
+}
+
+// Deck letter of DJ software taken from the player instance (bus name) or track id and link,
+// e.g. "A" for "[Channel1]". Empty if it's unknown.
+pub fn deck<S: AsRef<str>>(sources: &[S]) -> String {
+    sources
+        .iter()
+        .find_map(|source| DECK.captures(source.as_ref()))
+        .and_then(|captures| captures.name("deck"))
+        .map(|deck| match deck.as_str().parse::<u8>() {
+            Ok(number) => char::from(b'A' + number - 1).to_string(),
+            Err(_) => deck.as_str().to_uppercase(),
+        })
+        .unwrap_or_default()
+}
+
+// Position went back or moved more than the time passed since the last read, e.g. after seeking
+#[cfg(target_os = "linux")]
+pub fn position_jumped(last_position: u64, elapsed: u64, position: u64) -> bool {
+    position < last_position || position.abs_diff(last_position + elapsed) > POSITION_JUMP_TOLERANCE
+}
+
+// Workarounds used for the selected player
+#[derive(Debug, Clone, Default)]
+pub struct Quirks {
+    album_suffixes: Vec<String>,    // Removed from album name, e.g. " - EP"
+    cover: String,                  // "lookup" (default) or "player" (player artwork first)
+    timestamps: String,             // "trusted" (default), "lagging" or "none"
+    time_unit: String,              // "auto" (default), "microseconds" or "nanoseconds"
+    activity_type: Option<String>,  // "listening", "watching", "playing" or "competing"
+    details_format: Option<String>, // Used when details_format is not set
+}
+
+pub fn get<S: AsRef<str>>(player_quirks: &HashMap<String, PlayerQuirks>, names: &[S]) -> Quirks {
+    let mut merged = PlayerQuirks::default();
+    let rules = builtin()
+        .into_iter()
+        .map(|(name, rules)| (name.to_string(), rules))
+        .chain(player_quirks.clone());
+    for (name, rules) in rules {
+        if !player_match::matches(&name, names) {
+            continue;
+        }
+        merged.album_suffixes = rules.album_suffixes.or(merged.album_suffixes);
+        merged.cover = rules.cover.or(merged.cover);
+        merged.timestamps = rules.timestamps.or(merged.timestamps);
+        merged.time_unit = rules.time_unit.or(merged.time_unit);
+        merged.activity_type = rules.activity_type.or(merged.activity_type);
+        merged.details_format = rules.details_format.or(merged.details_format);
+    }
+    Quirks {
+        album_suffixes: merged.album_suffixes.unwrap_or_default(),
+        cover: merged.cover.unwrap_or(String::from("lookup")),
+        timestamps: merged.timestamps.unwrap_or(String::from("trusted")),
+        time_unit: merged.time_unit.unwrap_or(String::from("auto")),
+        activity_type: merged.activity_type,
+        details_format: merged.details_format,
+    }
+}
+
+impl Quirks {
+    // Metadata fixes applied right after reading it from player
+    pub fn apply(&self, media_info: &mut MediaInfo) {
+        for suffix in &self.album_suffixes {
+            if let Some(album) = media_info.album.strip_suffix(suffix.as_str()) {
+                media_info.album = album.trim_end().to_string();
+                break;
+            }
+        }
+        if self.timestamps == "none" {
+            media_info.is_track_position = false;
+        }
+    }
+
+    // MPRIS length and position are in microseconds, but some players send nanoseconds, so
+    // tracks are "47 hours long". With "auto" the unit is guessed from implausible length.
+    // Broken length is dropped (only elapsed time is shown) and broken position disables the
+    // progress bar. Returns what was fixed, for debug log.
+    pub fn fix_times(&self, media_info: &mut MediaInfo) -> Option<String> {
+        let scale = match self.time_unit.as_str() {
+            "nanoseconds" => 1000,
+            "auto"
+                if media_info.duration > MAX_TRACK_LENGTH
+                    && media_info.duration / 1000 <= MAX_TRACK_LENGTH =>
+            {
+                1000
+            }
+            _ => 1,
+        };
+        let (duration, position) = (media_info.duration, media_info.position);
+        media_info.duration /= scale;
+        media_info.position /= scale;
+
+        if media_info.duration > MAX_TRACK_LENGTH {
+            media_info.duration = 0;
+        }
+        let position_limit = match media_info.duration {
+            0 => MAX_TRACK_LENGTH,
+            duration => duration + POSITION_TOLERANCE,
+        };
+        if media_info.is_track_position && media_info.position > position_limit {
+            media_info.is_track_position = false;
+            media_info.position = 0;
+            media_info.duration = 0;
+        }
+
+        match (media_info.duration, media_info.position) == (duration, position) {
+            true => None,
+            false => Some(format!(
+                "length {}s -> {}s, position {}s -> {}s",
+                duration, media_info.duration, position, media_info.position
+            )),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn lagging_position(&self) -> bool {
+        self.timestamps == "lagging"
+    }
+
+    pub fn activity_type(&self) -> Option<&str> {
+        self.activity_type.as_deref()
+    }
+
+    pub fn details_format(&self) -> Option<&str> {
+        self.details_format.as_deref()
+    }
+
+    // Player artwork is used instead of searching for album cover
+    pub fn prefers_player_cover(&self) -> bool {
+        self.cover == "player"
+    }
+}
+
+// Icon and its text shown next to the cover instead of the player icon, when the cover comes
+// from a known site (e.g. YouTube in a browser)
+pub fn cover_icon(image: &str) -> Option<(&'static str, &'static str)> {
+    [("ytimg.com/", "youtube", "YouTube")]
+        .iter()
+        .find(|(host, _, _)| image.contains(host))
+        .map(|(_, icon, text)| (*icon, *text))
+}
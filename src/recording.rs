@@ -0,0 +1,97 @@
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::time::Duration;
+
+use crate::utils::{self, MediaInfo};
+
+// Min MusicBrainz search score to trust that the recording is the same track
+const MIN_SCORE: u64 = 90;
+
+// Found recordings are kept in memory, the list is cleared when full
+pub const MAX_CACHED_RECORDINGS: usize = 1000;
+
+// Recordings with length differing more than this are versions of the track (live, remix, edit)
+const MAX_LENGTH_DIFFERENCE: u64 = 10;
+
+// Key under which the recording is remembered
+pub fn track_key(media_info: &MediaInfo) -> String {
+    format!(
+        "{} - {} - {}",
+        media_info.artist, media_info.title, media_info.album
+    )
+}
+
+// Page of the recording on MusicBrainz, with its releases, credits and listens on ListenBrainz
+pub fn page_url(recording_id: &str) -> String {
+    format!("https://musicbrainz.org/recording/{}", recording_id)
+}
+
+// Search MusicBrainz recording of the track. The result from the same album and with similar
+// length is preferred, empty if the track was not found.
+pub fn search(media_info: &MediaInfo) -> Result<String, Box<dyn std::error::Error>> {
+    // Album is not in the query, so singles and compilations are found too
+    let query = format!(
+        "artist:\"{}\" AND recording:\"{}\"",
+        media_info.artist.replace('"', ""),
+        media_info.title.replace('"', "")
+    );
+    let request_url = format!(
+        "https://musicbrainz.org/ws/2/recording/?query={}&fmt=json&limit=25",
+        url_escape::encode_component(&query),
+    );
+    let data = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(request_url)
+        .header(
+            USER_AGENT,
+            format!(
+                "music-discord-rpc/{} (patryk.kurdziel@protonmail.com)",
+                utils::VERSION
+            ),
+        )
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?;
+
+    let Some(recordings) = data["recordings"].as_array() else {
+        return Ok(String::new());
+    };
+    let recordings: Vec<&serde_json::Value> = recordings
+        .iter()
+        .filter(|recording| recording["score"].as_u64().unwrap_or(0) >= MIN_SCORE)
+        .collect();
+
+    // Length is in milliseconds, unknown lengths are not compared
+    let similar_length = |recording: &&serde_json::Value| match (
+        recording["length"].as_u64(),
+        media_info.duration,
+    ) {
+        (Some(length), duration) if duration > 0 => {
+            (length / 1000).abs_diff(duration) <= MAX_LENGTH_DIFFERENCE
+        }
+        _ => true,
+    };
+    let on_album = |recording: &&serde_json::Value| {
+        recording["releases"].as_array().is_some_and(|releases| {
+            releases.iter().any(|release| {
+                release["title"].as_str().unwrap_or_default().to_lowercase()
+                    == media_info.album.to_lowercase()
+            })
+        })
+    };
+
+    let recording = recordings
+        .iter()
+        .find(|recording| similar_length(recording) && on_album(recording))
+        .or_else(|| {
+            recordings
+                .iter()
+                .find(|recording| similar_length(recording))
+        });
+
+    Ok(recording
+        .and_then(|recording| recording["id"].as_str())
+        .unwrap_or_default()
+        .to_string())
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+// Faster refresh after track change or seek, so the following changes are shown quickly
+const FAST_INTERVAL: Duration = Duration::from_secs(2);
+const FAST_PERIOD: Duration = Duration::from_secs(10);
+
+// Slower refresh when no player is running for some time
+const IDLE_INTERVAL: Duration = Duration::from_secs(30);
+const IDLE_AFTER: Duration = Duration::from_secs(60);
+
+// Interval from interval and interval_ms options, the same rules for global and per-player ones
+pub fn configured_interval(
+    interval: Option<u64>,
+    interval_ms: Option<u64>,
+    min_interval_ms: Option<u64>,
+) -> Duration {
+    match interval_ms {
+        Some(interval_ms) => {
+            let min_interval_ms = min_interval_ms.unwrap_or(1000).max(100);
+            Duration::from_millis(interval_ms.max(min_interval_ms))
+        }
+        None => Duration::from_secs(interval.unwrap_or(10).max(5)),
+    }
+}
+
+// Refresh interval adjusted to what is happening, or the configured one if disabled
+pub struct RefreshInterval {
+    interval: Duration,
+    player_interval: Option<Duration>, // From player_overrides of the selected player
+    adaptive: bool,
+    fast_until: Option<Instant>,
+    idle_since: Option<Instant>,
+}
+
+impl RefreshInterval {
+    pub fn new(interval: Duration, adaptive: bool) -> RefreshInterval {
+        RefreshInterval {
+            interval,
+            player_interval: None,
+            adaptive,
+            fast_until: None,
+            idle_since: None,
+        }
+    }
+
+    // Track, playback status or position changed
+    pub fn changed(&mut self) {
+        self.fast_until = Some(Instant::now() + FAST_PERIOD);
+    }
+
+    pub fn player_found(&mut self) {
+        self.idle_since = None;
+    }
+
+    // Selected player has its own interval, None to use the configured one
+    pub fn set_player_interval(&mut self, player_interval: Option<Duration>) {
+        self.player_interval = player_interval;
+    }
+
+    // Interval without adaptive changes
+    pub fn base(&self) -> Duration {
+        self.player_interval.unwrap_or(self.interval)
+    }
+
+    pub fn no_player(&mut self) {
+        self.idle_since.get_or_insert_with(Instant::now);
+        self.fast_until = None;
+        self.player_interval = None;
+    }
+
+    pub fn get(&self) -> Duration {
+        if !self.adaptive {
+            return self.base();
+        }
+        if self
+            .idle_since
+            .is_some_and(|idle_since| idle_since.elapsed() >= IDLE_AFTER)
+        {
+            return self.base().max(IDLE_INTERVAL);
+        }
+        if self
+            .fast_until
+            .is_some_and(|fast_until| Instant::now() < fast_until)
+        {
+            return self.base().min(FAST_INTERVAL);
+        }
+        self.base()
+    }
+}
@@ -0,0 +1,55 @@
+// Consecutive plays of the same track, {repeat_count} in templates
+
+// Position must be this close to the end of the track before it starts again from the beginning,
+// a bit more than the default refresh interval as the last position is read before the track ends
+const END_MARGIN: u64 = 15;
+const START_MARGIN: u64 = 15;
+
+pub struct RepeatCounter {
+    count: u32,
+}
+
+impl RepeatCounter {
+    pub fn new() -> RepeatCounter {
+        RepeatCounter { count: 1 }
+    }
+
+    // Counts a replay when the same track starts again after being played to the end, seeking
+    // back within the track doesn't count. Returns true if the count changed.
+    pub fn update(
+        &mut self,
+        same_track: bool,
+        last_position: u64,
+        position: u64,
+        duration: u64,
+    ) -> bool {
+        let replayed = duration > END_MARGIN
+            && position < last_position
+            && last_position + END_MARGIN >= duration
+            && position <= START_MARGIN;
+        match (same_track, replayed) {
+            (true, true) => {
+                self.count += 1;
+                true
+            }
+            (true, false) => false,
+            (false, _) => {
+                let changed = self.count != 1;
+                self.count = 1;
+                changed
+            }
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    // Value for templates, empty on the first play so "[x{repeat_count}]" is hidden
+    pub fn text(&self) -> String {
+        match self.count {
+            1 => String::new(),
+            count => count.to_string(),
+        }
+    }
+}
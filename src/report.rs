@@ -0,0 +1,262 @@
+use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
+use regex::Regex;
+use serde_yaml::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::LazyLock;
+
+use crate::cover_cache;
+use crate::diagnostics;
+use crate::discord_socket;
+use crate::settings::{self, Cli};
+use crate::utils;
+
+// Lines of the service log included in the report
+const LOG_LINES: usize = 100;
+
+// Config values containing any of these in the key are replaced with "<redacted>"
+const SECRET_KEYS: [&str; 4] = ["api_key", "password", "token", "secret"];
+
+// Shell commands can contain tokens too (e.g. curl -H "Authorization: ..."), so they're hidden:
+// everything in hooks and "command" anywhere, e.g. in custom_status
+const COMMAND_SECTIONS: [&str; 1] = ["hooks"];
+const COMMAND_KEYS: [&str; 1] = ["command"];
+
+// "user:password@" part of links, e.g. in cover_upload_url
+static LINK_USERINFO: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?P<scheme>[A-Za-z][A-Za-z0-9+.-]*://)[^/?#\s]*@")
+        .expect("userinfo pattern is valid")
+});
+
+// Output of a command, empty if it failed
+fn command_output(program: &str, args: &[&str]) -> String {
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn os_name() -> String {
+    fs::read_to_string("/etc/os-release")
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|name| name.trim_matches('"').to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+#[cfg(target_os = "macos")]
+fn os_name() -> String {
+    format!("macOS {}", command_output("sw_vers", &["-productVersion"]))
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_KEYS.iter().any(|secret| key.contains(secret)) || COMMAND_KEYS.contains(&key.as_str())
+}
+
+fn strip_userinfo(text: &str) -> String {
+    LINK_USERINFO.replace_all(text, "$scheme").to_string()
+}
+
+// Secrets are hidden at any depth, links lose their credentials
+fn redact(value: &mut Value) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                let key = key.as_str().unwrap_or_default();
+                let is_hidden = COMMAND_SECTIONS.contains(&key) || is_secret_key(key);
+                match value {
+                    Value::Null => {}
+                    _ if is_hidden => *value = Value::String(String::from("<redacted>")),
+                    _ => redact(value),
+                }
+            }
+        }
+        Value::Sequence(sequence) => sequence.iter_mut().for_each(redact),
+        Value::String(text) => *text = strip_userinfo(text),
+        Value::Tagged(tagged) => redact(&mut tagged.value),
+        _ => {}
+    }
+}
+
+// Config without comments and with secrets hidden
+fn sanitize_config(config: &str) -> String {
+    match serde_yaml::from_str::<Value>(config) {
+        Ok(Value::Null) => String::new(),
+        Ok(mut value) => {
+            redact(&mut value);
+            serde_yaml::to_string(&value)
+                .unwrap_or_default()
+                .trim_end()
+                .to_string()
+        }
+        // The file can't be shown safely when it can't be parsed, only the error helps
+        Err(err) => format!("# invalid YAML: {}", err),
+    }
+}
+
+fn config_files(settings: &Cli) -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(settings::SYSTEM_CONFIG_FILE)];
+    files.extend(
+        settings
+            .config_file
+            .clone()
+            .or_else(settings::get_user_config_file),
+    );
+    files.into_iter().filter(|path| path.exists()).collect()
+}
+
+fn db_entries(path: &Path) -> String {
+    match PickleDb::load(
+        path,
+        PickleDbDumpPolicy::NeverDump,
+        SerializationMethod::Json,
+    ) {
+        Ok(db) => db.total_keys().to_string(),
+        Err(_) => String::from("none"),
+    }
+}
+
+fn cache_stats(cache_dir: &Path) -> String {
+    let (count, size) = fs::read_dir(cover_cache::covers_dir(cache_dir))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .fold((0, 0), |(count, size), metadata| {
+                    (count + 1, size + metadata.len())
+                })
+        })
+        .unwrap_or((0, 0));
+    format!(
+        "- Directory: `{}`\n- Albums in cache: {}\n- Albums with details: {}\n- Cover images: {} ({:.1} MB)\n",
+        cache_dir.display(),
+        db_entries(&cache_dir.join("album_cache.db")),
+        db_entries(&cache_dir.join("album_info.db")),
+        count,
+        size as f64 / (1024.0 * 1024.0)
+    )
+}
+
+// Recent log of the systemd service, the app itself doesn't write logs to disk
+#[cfg(target_os = "linux")]
+fn recent_log() -> String {
+    command_output(
+        "journalctl",
+        &[
+            "--user",
+            "-u",
+            "music-discord-rpc.service",
+            "-n",
+            &LOG_LINES.to_string(),
+            "--no-pager",
+            "-o",
+            "cat",
+        ],
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn recent_log() -> String {
+    String::new()
+}
+
+fn build_report(settings: &Cli) -> String {
+    let mut report = String::from("## music-discord-rpc report\n\n### Versions\n\n");
+    report.push_str(&format!(
+        "- music-discord-rpc: {}{}\n",
+        utils::VERSION,
+        if utils::is_portable() {
+            " (portable)"
+        } else {
+            ""
+        }
+    ));
+    report.push_str(&format!("- OS: {}\n", os_name()));
+    report.push_str(&format!(
+        "- Kernel: {}\n",
+        command_output("uname", &["-sr"])
+    ));
+    for variable in ["XDG_CURRENT_DESKTOP", "XDG_SESSION_TYPE"] {
+        if let Ok(value) = env::var(variable) {
+            report.push_str(&format!("- {}: {}\n", variable, value));
+        }
+    }
+    let sockets = discord_socket::find_sockets();
+    report.push_str(&format!(
+        "- Discord IPC sockets: {}\n",
+        match sockets.is_empty() {
+            true => String::from("none found"),
+            false => sockets
+                .iter()
+                .map(|socket| format!("`{}`", socket.display()))
+                .collect::<Vec<String>>()
+                .join(", "),
+        }
+    ));
+
+    report.push_str("\n### Config\n\n");
+    let files = config_files(settings);
+    if files.is_empty() {
+        report.push_str("No config file.\n");
+    }
+    for file in files {
+        report.push_str(&format!(
+            "`{}`\n\n```yaml\n{}\n```\n",
+            file.display(),
+            sanitize_config(&fs::read_to_string(&file).unwrap_or_default())
+        ));
+    }
+
+    report.push_str("\n### Players\n\n```json\n");
+    report.push_str(
+        &serde_json::to_string_pretty(&diagnostics::snapshot(settings, true)).unwrap_or_default(),
+    );
+    report.push_str("\n```\n\n### Cache\n\n");
+    match utils::get_app_cache_dir() {
+        Some(cache_dir) => report.push_str(&cache_stats(&cache_dir)),
+        None => report.push_str("Could not determine cache directory.\n"),
+    }
+
+    report.push_str(&format!("\n### Recent log (last {} lines)\n\n", LOG_LINES));
+    let log = recent_log();
+    match log.is_empty() {
+        true => report.push_str(
+            "No log found, the app doesn't run as a systemd service. Paste its output instead.\n",
+        ),
+        false => report.push_str(&format!("```\n{}\n```\n", log)),
+    }
+
+    // Home directory can contain the user name
+    match env::var("HOME") {
+        Ok(home) if home.len() > 1 => report.replace(&home, "~"),
+        _ => report,
+    }
+}
+
+// Used by "report" subcommand
+pub fn run(settings: &Cli, output: Option<&Path>) {
+    let report = build_report(settings);
+    match output {
+        Some(path) => {
+            match fs::write(path, &report) {
+                Ok(_) => {
+                    println!("Report saved to: {}", path.display());
+                    println!("Check it before attaching to an issue, it contains track names from the log.");
+                }
+                Err(err) => {
+                    println!("\x1b[31mCould not save report: {}\x1b[0m", err);
+                    process::exit(1);
+                }
+            }
+        }
+        None => print!("{}", report),
+    }
+    process::exit(0);
+}
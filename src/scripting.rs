@@ -0,0 +1,220 @@
+#[cfg(feature = "scripting")]
+use rhai::module_resolvers::DummyModuleResolver;
+#[cfg(feature = "scripting")]
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+#[cfg(feature = "scripting")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "scripting")]
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "scripting")]
+use std::rc::Rc;
+#[cfg(feature = "scripting")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "scripting")]
+use crate::debug_log;
+use crate::utils::MediaInfo;
+
+// Max time of one script run
+#[cfg(feature = "scripting")]
+const TIME_LIMIT: Duration = Duration::from_millis(100);
+
+// Activity values set by script, None keeps the default value
+#[derive(Debug, Default)]
+pub struct ScriptResult {
+    pub skip: bool, // Don't update activity this time
+    pub name: Option<String>,
+    pub details: Option<String>,
+    pub state: Option<String>,
+    pub large_image: Option<String>,
+    pub large_text: Option<String>,
+    pub small_image: Option<String>,
+    pub small_text: Option<String>,
+    pub buttons: Option<Vec<(String, String)>>, // (label, url)
+}
+
+// User script evaluated on every activity update
+#[cfg(feature = "scripting")]
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    started: Rc<Cell<Instant>>,
+    last_error: RefCell<String>,
+}
+
+#[cfg(feature = "scripting")]
+fn get_string(map: &Map, key: &str) -> Option<String> {
+    map.get(key)
+        .and_then(|value| value.clone().into_string().ok())
+}
+
+// Sandboxed engine: no imports, no eval and limited resources
+#[cfg(feature = "scripting")]
+fn create_engine(started: Rc<Cell<Instant>>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .disable_symbol("eval")
+        .set_max_operations(1_000_000)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(10_000)
+        .set_max_array_size(1_000)
+        .set_max_map_size(1_000)
+        .on_print(|text| println!("[script] {}", text))
+        .on_debug(|text, _, _| println!("[script] {}", text))
+        .on_progress(move |_| match started.get().elapsed() > TIME_LIMIT {
+            true => Some(Dynamic::from("time limit exceeded")),
+            false => None,
+        });
+    engine
+}
+
+#[cfg(feature = "scripting")]
+pub fn load(path: &Path, debug_log: bool) -> Option<Script> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            println!(
+                "\x1b[31mWARNING: Could not read script file {}: {}\x1b[0m",
+                path.display(),
+                err
+            );
+            return None;
+        }
+    };
+
+    let started = Rc::new(Cell::new(Instant::now()));
+    let engine = create_engine(started.clone());
+    match engine.compile(&text) {
+        Ok(ast) => {
+            println!("Script loaded from file: {}", path.display());
+            debug_log!(debug_log, "[script] compiled: {}", path.display());
+            Some(Script {
+                engine,
+                ast,
+                started,
+                last_error: RefCell::new(String::new()),
+            })
+        }
+        Err(err) => {
+            println!(
+                "\x1b[31mWARNING: Script {} has errors and will not be used: {}\x1b[0m",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl Script {
+    // Script can modify "track" map (title, artist, album, album_artist), set "activity" map
+    // fields and set "skip" to true to veto the update. On error nothing is changed.
+    pub fn run(&self, media_info: &mut MediaInfo, player: &str, debug_log: bool) -> ScriptResult {
+        let mut track = Map::new();
+        for (key, value) in [
+            ("title", &media_info.title),
+            ("artist", &media_info.artist),
+            ("album", &media_info.album),
+            ("album_artist", &media_info.album_artist),
+            ("url", &media_info.url),
+            ("art_url", &media_info.art_url),
+        ] {
+            track.insert(key.into(), Dynamic::from(value.clone()));
+        }
+        track.insert("player".into(), Dynamic::from(player.to_string()));
+        track.insert("is_playing".into(), Dynamic::from(media_info.is_playing));
+        track.insert("duration".into(), Dynamic::from(media_info.duration as i64));
+        track.insert("position".into(), Dynamic::from(media_info.position as i64));
+
+        let mut scope = Scope::new();
+        scope.push("track", track);
+        scope.push("activity", Map::new());
+        scope.push("skip", false);
+
+        self.started.set(Instant::now());
+        if let Err(err) = self.engine.run_ast_with_scope(&mut scope, &self.ast) {
+            // Print the same error only once
+            let err = err.to_string();
+            if *self.last_error.borrow() != err {
+                println!("\x1b[31mScript error: {}\x1b[0m", err);
+                *self.last_error.borrow_mut() = err;
+            }
+            return ScriptResult::default();
+        }
+        self.last_error.borrow_mut().clear();
+        debug_log!(
+            debug_log,
+            "[script] finished in {:?}",
+            self.started.get().elapsed()
+        );
+
+        if let Some(track) = scope.get_value::<Map>("track") {
+            for (key, value) in [
+                ("title", &mut media_info.title),
+                ("artist", &mut media_info.artist),
+                ("album", &mut media_info.album),
+                ("album_artist", &mut media_info.album_artist),
+            ] {
+                if let Some(text) = get_string(&track, key) {
+                    *value = text;
+                }
+            }
+        }
+
+        let activity = scope.get_value::<Map>("activity").unwrap_or_default();
+        let buttons = activity
+            .get("buttons")
+            .and_then(|buttons| buttons.clone().into_array().ok())
+            .map(|buttons| {
+                buttons
+                    .into_iter()
+                    .filter_map(|button| button.try_cast::<Map>())
+                    .filter_map(|button| {
+                        Some((get_string(&button, "label")?, get_string(&button, "url")?))
+                    })
+                    .take(2)
+                    .collect()
+            });
+
+        ScriptResult {
+            skip: scope.get_value::<bool>("skip").unwrap_or(false),
+            name: get_string(&activity, "name"),
+            details: get_string(&activity, "details"),
+            state: get_string(&activity, "state"),
+            large_image: get_string(&activity, "large_image"),
+            large_text: get_string(&activity, "large_text"),
+            small_image: get_string(&activity, "small_image"),
+            small_text: get_string(&activity, "small_text"),
+            buttons,
+        }
+    }
+}
+
+// Built without the "scripting" feature, script is never loaded
+#[cfg(not(feature = "scripting"))]
+pub enum Script {}
+
+#[cfg(not(feature = "scripting"))]
+pub fn load(path: &Path, _debug_log: bool) -> Option<Script> {
+    println!(
+        "\x1b[33mWARNING: Script {} is not used, this build doesn't include scripting support (see --version --verbose).\x1b[0m",
+        path.display()
+    );
+    None
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Script {
+    pub fn run(
+        &self,
+        _media_info: &mut MediaInfo,
+        _player: &str,
+        _debug_log: bool,
+    ) -> ScriptResult {
+        match *self {}
+    }
+}
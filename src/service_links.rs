@@ -0,0 +1,77 @@
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::utils;
+
+// Button name, platform name in Odesli API (empty if not supported) and search link used when
+// the track link was not found
+const SERVICES: [(&str, &str, &str); 3] = [
+    ("tidal", "tidal", "https://tidal.com/search?q="),
+    ("deezer", "deezer", "https://www.deezer.com/search/"),
+    ("qobuz", "", "https://play.qobuz.com/search?q="),
+];
+
+// Track links known by Odesli, the player must provide one (or it's found as Spotify or Apple
+// Music track)
+const LINK_SOURCES: [&str; 7] = [
+    "https://open.spotify.com/",
+    "https://music.apple.com/",
+    "https://tidal.com/",
+    "https://listen.tidal.com/",
+    "https://www.deezer.com/",
+    "https://music.youtube.com/",
+    "https://soundcloud.com/",
+];
+
+// Links found for tracks are kept in memory, the list is cleared when full
+pub const MAX_CACHED_TRACKS: usize = 1000;
+
+pub fn is_service_button(button: &str) -> bool {
+    SERVICES.iter().any(|(name, _, _)| *name == button)
+}
+
+pub fn is_link_source(url: &str) -> bool {
+    LINK_SOURCES.iter().any(|source| url.starts_with(source))
+}
+
+// Link to the track on the service, or search of the song if the link is unknown
+pub fn get_url(button: &str, links: Option<&HashMap<String, String>>, song_name: &str) -> String {
+    if let Some(url) = links.and_then(|links| links.get(button)) {
+        return url.clone();
+    }
+    let prefix = SERVICES
+        .iter()
+        .find(|(name, _, _)| *name == button)
+        .map(|(_, _, prefix)| *prefix)
+        .unwrap_or_default();
+    utils::build_trimmed_url(prefix, song_name)
+}
+
+// Links of the same track on other services from Odesli (song.link), by button name
+pub fn get_links(source_url: &str) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let request_url = format!(
+        "https://api.song.link/v1-alpha.1/links?songIfSingle=true&url={}",
+        url_escape::encode_component(source_url)
+    );
+    let data = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(request_url)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?;
+
+    let mut links = HashMap::new();
+    for (name, platform, _) in SERVICES {
+        if platform.is_empty() {
+            continue;
+        }
+        if let Some(url) = data["linksByPlatform"][platform]["url"].as_str() {
+            links.insert(name.to_string(), url.to_string());
+        }
+    }
+    Ok(links)
+}
@@ -1,30 +1,109 @@
 use clap_serde_derive::{
-    clap::{self, Parser, Subcommand},
+    clap::{self, CommandFactory, FromArgMatches, Parser, Subcommand},
     serde::Serialize,
     ClapSerde,
 };
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
+use std::io;
 use std::path::PathBuf;
 use std::process;
 
+use crate::build_info;
+use crate::config_migration;
 use crate::debug_log;
-use crate::utils::get_config_path;
+use crate::locale;
+use crate::utils::{self, get_app_config_dir};
+
+// Settings changed only for one player (config file only)
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlayerOverride {
+    pub activity_name: Option<String>,
+    pub activity_type: Option<String>,
+    pub interval: Option<u64>,
+    pub interval_ms: Option<u64>,
+}
+
+// Workarounds for a specific player, replace the built-in ones (config file only)
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlayerQuirks {
+    pub album_suffixes: Option<Vec<String>>,
+    pub cover: Option<String>,
+    pub timestamps: Option<String>,
+    pub time_unit: Option<String>,
+    pub activity_type: Option<String>,
+    pub details_format: Option<String>,
+}
+
+// User commands run on player events (config file only)
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Hooks {
+    pub on_track_change: Option<String>,
+    pub on_pause: Option<String>,
+    pub on_stop: Option<String>,
+    pub on_player_change: Option<String>,
+}
+
+// Command setting Discord custom status, apps can't change it through RPC (config file only)
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CustomStatus {
+    pub command: Option<String>,
+    pub text_format: Option<String>,
+    pub emoji: Option<String>,
+}
 
 #[derive(Parser, ClapSerde, Serialize, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
 pub struct Cli {
     /// Activity refresh rate (min: 5, default: 10)
     #[arg(short, long, value_name = "seconds", value_parser = clap::value_parser!(u64).range(5..))]
     pub interval: Option<u64>,
 
+    /// Activity refresh rate in milliseconds, overrides interval and allows values below 5 seconds (min: min_interval_ms)
+    #[arg(long, value_name = "milliseconds", value_parser = clap::value_parser!(u64))]
+    pub interval_ms: Option<u64>,
+
+    /// Lowest allowed interval_ms (min: 100, default: 1000)
+    #[arg(long, value_name = "milliseconds", value_parser = clap::value_parser!(u64).range(100..))]
+    pub min_interval_ms: Option<u64>,
+
+    /// Always refresh with the same interval, without faster refreshes after track change and slower ones when no player is running
+    #[arg(long)]
+    pub disable_adaptive_interval: bool,
+
+    /// Sleep until Discord creates its IPC socket instead of checking for Discord every few seconds (Linux only)
+    #[arg(long)]
+    pub wait_for_discord_socket: bool,
+
+    /// Publish activity to all running Discord clients (e.g. stable and Canary), not only the first one found
+    #[arg(long)]
+    pub multiple_discord_clients: bool,
+
+    /// Discord IPC sockets used with multiple_discord_clients, matched like allowlist entries against path and name (config file only)
+    #[arg(skip)]
+    pub discord_sockets: Vec<String>,
+
     /// Select visible buttons
-    #[arg(short, long, value_name = "name", value_parser = ["yt", "lastfm", "listenbrainz", "mprisUrl", "shamelessAd"])]
+    #[arg(short, long, value_name = "name", value_parser = ["yt", "lastfm", "lastfmTrack", "listenbrainz", "musicbrainz", "mprisUrl", "appleMusic", "tidal", "deezer", "qobuz", "shamelessAd"])]
     pub button: Vec<String>,
 
+    /// Custom labels of buttons, "button name": "label" (config file only)
+    #[arg(skip)]
+    pub button_labels: HashMap<String, String>,
+
     /// Your Last.fm nickname
     #[arg(long, value_name = "nickname", value_parser = clap::value_parser!(String))]
     pub lastfm_name: Option<String>,
 
+    /// Show a heart next to the album cover when the current track is loved on Last.fm (requires --lastfm-name)
+    #[arg(long)]
+    pub lastfm_loved_icon: bool,
+
     /// Your Listenbrainz nickname
     #[arg(long, value_name = "nickname", value_parser = clap::value_parser!(String))]
     pub listenbrainz_name: Option<String>,
@@ -33,6 +112,10 @@ pub struct Cli {
     #[arg(short, long, value_name = "value", value_parser = ["artist", "track", "none"])]
     pub rpc_name: Option<String>,
 
+    /// Select which part of activity is displayed after "Listening to" on the user list, independent of rpc_name: state (second line), details (first line) or name (activity name)
+    #[arg(long, value_name = "value", value_parser = ["state", "details", "name"])]
+    pub status_display: Option<String>,
+
     /// Select the icon displayed next to the album cover (default: playPause)
     #[arg(short, long, value_name = "name", value_parser = ["playPause", "player", "lastfmAvatar", "none"])]
     pub small_image: Option<String>,
@@ -49,6 +132,14 @@ pub struct Cli {
     #[arg(long)]
     pub disable_mpris_art_url: bool,
 
+    /// Quality of YouTube thumbnails used as cover: max, high (16:9 without black bars) or original (default: max)
+    #[arg(long, value_name = "quality", value_parser = ["max", "high", "original"])]
+    pub youtube_thumbnail_quality: Option<String>,
+
+    /// Do not use "LIVE" presence for Twitch and YouTube live streams
+    #[arg(long)]
+    pub disable_live_presence: bool,
+
     /// Displays all available music player names and exits. Use to get your player name for -a argument
     #[arg(short, long)]
     #[serde(skip_deserializing)]
@@ -59,34 +150,294 @@ pub struct Cli {
     #[serde(skip_deserializing)]
     pub get_player_id: bool,
 
+    /// Print --list-players and --get-player-id output as JSON with playback status, metadata and stats of the running instance, e.g. for bug reports
+    #[arg(long)]
+    #[serde(skip_deserializing)]
+    pub json: bool,
+
+    /// With --list-players, add given player to the allowlist in the config file (created if it doesn't exist)
+    #[arg(
+        long,
+        value_name = "Player Name",
+        requires = "list_players",
+        conflicts_with = "json"
+    )]
+    #[serde(skip_deserializing)]
+    pub save: Option<String>,
+
     /// Get status only from given player. Use multiple times to add several players.
     #[arg(short = 'a', long = "allowlist-add", value_name = "Player Name", value_parser = clap::value_parser!(String))]
     pub allowlist: Vec<String>,
 
+    /// Priorities of allowlist entries, higher is more important, set in config as allowlist: [{ name: "Spotify", priority: 10 }] (config file only)
+    #[arg(skip)]
+    pub allowlist_priorities: HashMap<String, i64>,
+
+    /// What decides first which allowlisted player is shown when several are active: playback status (default) or priority
+    #[arg(long, value_name = "rule", value_parser = ["status", "priority"])]
+    pub allowlist_sort_by: Option<String>,
+
+    /// Pause allowlisted players with lower priority while the shown player is playing (Linux only)
+    #[arg(long)]
+    pub exclusive_playback: bool,
+
     /// Will use the "watching" activity. Use multiple times to add several players.
     #[arg(short = 'w', long = "video-players", value_name = "Player Name", value_parser = clap::value_parser!(String))]
     pub video_players: Vec<String>,
 
+    /// Show file names of videos without title tag as they are, e.g. "Movie.2019.1080p.x264.mkv" instead of "Movie 2019"
+    #[arg(long)]
+    pub disable_video_title_cleanup: bool,
+
+    /// Show titles of TV show episodes as they are, e.g. "Show.S02E05.Title" instead of "Show — S02E05 • Title"
+    #[arg(long)]
+    pub disable_episode_parsing: bool,
+
     /// Hide album name
     #[arg(long)]
     pub hide_album_name: bool,
 
+    /// Hide the progress bar (timestamps), e.g. when {elapsed} or {remaining} is used in templates instead
+    #[arg(long, alias = "hide-timestamps")]
+    #[serde(alias = "hide_timestamps")]
+    pub hide_progress_bar: bool,
+
+    /// Hide artist name (the second line of activity)
+    #[arg(long)]
+    pub hide_artist: bool,
+
+    /// Hide activity buttons, buttons set by user script are still shown
+    #[arg(long)]
+    pub hide_buttons: bool,
+
+    /// Hide the small icon next to the album cover, also the paused icon
+    #[arg(long)]
+    pub hide_small_image: bool,
+
     /// Only send activity when media is playing
     #[arg(long)]
     pub only_when_playing: bool,
 
+    /// What to do when player reports stopped: show as paused (default), clear activity or keep it for stopped_keep_time
+    #[arg(long, value_name = "behavior", value_parser = ["paused", "clear", "keep"])]
+    pub stopped_behavior: Option<String>,
+
+    /// Seconds to keep activity of a stopped player when stopped_behavior is "keep" (default: 30)
+    #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64))]
+    pub stopped_keep_time: Option<u64>,
+
+    /// On player change clear activity immediately (default) or keep it until the new player's activity is published
+    #[arg(long, value_name = "behavior", value_parser = ["clear", "republish"])]
+    pub player_change_behavior: Option<String>,
+
+    /// What to do while an advertisement plays: show it like a track (default), keep the previous track or clear activity
+    #[arg(long, value_name = "behavior", value_parser = ["show", "keep", "clear"])]
+    pub ad_behavior: Option<String>,
+
+    /// Titles or artists of advertisements, matched like allowlist entries (config file only)
+    #[arg(skip)]
+    pub ad_patterns: Vec<String>,
+
+    /// Hide activity while the screen is shared or streamed (OBS running or screen cast through xdg-desktop-portal)
+    #[arg(long)]
+    pub pause_while_streaming: bool,
+
+    /// Names of processes that mean streaming, matched like allowlist entries (config file only, default: obs)
+    #[arg(skip)]
+    pub streaming_processes: Vec<String>,
+
+    /// Hide activity while a game is running, so Discord shows the game instead (Steam games and game_processes)
+    #[arg(long)]
+    pub yield_to_games: bool,
+
+    /// Names of game processes, matched like allowlist entries (config file only)
+    #[arg(skip)]
+    pub game_processes: Vec<String>,
+
+    /// Hide activity after it was visible for this many minutes in a day
+    #[arg(long, value_name = "minutes", value_parser = clap::value_parser!(u64).range(1..))]
+    pub daily_presence_limit: Option<u64>,
+
+    /// Hour (0-23, local time) at which the daily presence limit resets (default: 0)
+    #[arg(long, value_name = "hour", value_parser = clap::value_parser!(u32).range(0..24))]
+    pub daily_presence_reset_hour: Option<u32>,
+
+    /// Seconds after track change in which position going back is not treated as seek, for crossfading players (default: 5)
+    #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64))]
+    pub transition_window: Option<u64>,
+
+    /// Experimental: for Spotify show "Listening to Spotify" with track links, like Discord's native integration
+    #[arg(long)]
+    pub spotify_mode: bool,
+
+    /// Text shown as the activity name, e.g. "Listening to Vinyl" (default: Music)
+    #[arg(long, value_name = "text", value_parser = clap::value_parser!(String))]
+    pub activity_name: Option<String>,
+
+    /// Template of text shown on album cover hover, e.g. "{album}[ ({year})][ • {tracks} tracks]"
+    #[arg(long, value_name = "template", value_parser = clap::value_parser!(String))]
+    pub large_text_format: Option<String>,
+
+    /// Add the number of plays in a row of the same track to text on album cover hover, e.g. "🔁 x5"
+    #[arg(long)]
+    pub show_repeat_count: bool,
+
+    /// How track rating ({rating} in templates) is displayed (default: stars)
+    #[arg(long, value_name = "style", value_parser = ["stars", "percent"])]
+    pub rating_style: Option<String>,
+
+    /// Template of activity details (first line), e.g. "{player} • {title}" (default: "{title}")
+    #[arg(long, value_name = "template", value_parser = clap::value_parser!(String))]
+    pub details_format: Option<String>,
+
+    /// Template of activity state (second line), e.g. "{artist} on {player}" (default: "{artist}")
+    #[arg(long, value_name = "template", value_parser = clap::value_parser!(String))]
+    pub state_format: Option<String>,
+
+    /// How often the activity is updated while {elapsed} or {remaining} is used in templates (default: 15)
+    #[arg(long, value_name = "seconds", value_parser = clap::value_parser!(u64).range(5..))]
+    pub time_text_interval: Option<u64>,
+
+    /// Settings for specific players, "Player Name": { activity_name: ... } (config file only)
+    #[arg(skip)]
+    pub player_overrides: HashMap<String, PlayerOverride>,
+
+    /// Workarounds for players with broken metadata, "Player Name": { timestamps: lagging, ... } (config file only)
+    #[arg(skip)]
+    pub player_quirks: HashMap<String, PlayerQuirks>,
+
+    /// Commands run on track change, pause, stop and player change (config file only)
+    #[arg(skip)]
+    pub hooks: Hooks,
+
+    /// Command setting Discord custom status to the current track, e.g. through a client mod (config file only)
+    #[arg(skip)]
+    pub custom_status: CustomStatus,
+
+    /// Path to Rhai script customizing the activity (default: script.rhai in config directory, if exists)
+    #[arg(long, value_name = "path", value_parser = clap::value_parser!(String))]
+    pub script_file: Option<String>,
+
+    /// Path to YAML file with translations of texts displayed in the activity
+    #[arg(long, value_name = "path", value_parser = clap::value_parser!(String))]
+    pub locale_file: Option<String>,
+
+    /// Language of displayed texts, e.g. en, pl (default: from LANG)
+    #[arg(long, value_name = "code", value_parser = clap::value_parser!(String))]
+    pub locale: Option<String>,
+
     /// Disable cache (not recommended)
     #[arg(short, long)]
     pub disable_cache: bool,
 
+    /// Also download and store album cover images in cache directory
+    #[arg(long)]
+    pub cache_cover_images: bool,
+
+    /// Max size of stored cover images, least recently used are removed first (default: 50)
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64))]
+    pub cover_cache_max_size: Option<u64>,
+
+    /// Max number of albums stored in cover links cache, least recently used are removed first (default: 10000)
+    #[arg(long, value_name = "number", value_parser = clap::value_parser!(usize))]
+    pub album_cache_max_entries: Option<usize>,
+
+    /// How cache files are written: auto (default) or fsync, flushed to disk after every change, safer on power loss but slower
+    #[arg(long, value_name = "policy", value_parser = ["auto", "fsync"])]
+    pub cache_dump_policy: Option<String>,
+
+    /// Manually set album covers, "Artist - Album": "https://...jpg" (config file only)
+    #[arg(skip)]
+    pub cover_overrides: HashMap<String, String>,
+
+    /// Use images named "Album Artist - Album.jpg" in covers/ of the config directory as album covers
+    #[arg(long)]
+    pub cover_folder: bool,
+
+    /// Server where images from cover folder and player artwork are uploaded with HTTP PUT, it replies with the image link
+    #[arg(long, value_name = "url", value_parser = clap::value_parser!(String))]
+    pub cover_upload_url: Option<String>,
+
+    /// Make non-square player artwork (e.g. video thumbnails) square and upload it to --cover-upload-url
+    #[arg(long)]
+    pub square_covers: bool,
+
+    /// How artwork is made square: pad (default) adds bars in the color of the image edges, crop cuts out the center
+    #[arg(long, value_name = "mode", value_parser = ["pad", "crop"])]
+    pub square_covers_mode: Option<String>,
+
+    /// Round corners of artwork made square with --square-covers
+    #[arg(long)]
+    pub square_covers_rounded: bool,
+
+    /// Serve Prometheus metrics on given address, e.g. 127.0.0.1:9464
+    #[arg(long, value_name = "address", value_parser = clap::value_parser!(String))]
+    pub metrics_address: Option<String>,
+
+    /// Do not listen for playback commands (ctl subcommand) on control socket
+    #[arg(long)]
+    pub disable_control_socket: bool,
+
+    /// Save "now playing" card image (cover, title, artist and progress) as now_playing.png in cache directory
+    #[arg(long)]
+    pub now_playing_card: bool,
+
+    /// Font file (.ttf or .otf) used in now playing card (default: DejaVu Sans or Noto Sans)
+    #[arg(long, value_name = "path", value_parser = clap::value_parser!(String))]
+    pub now_playing_card_font: Option<String>,
+
     /// Your Last.fm API key
     #[arg(long, value_name = "api_key", value_parser = clap::value_parser!(String))]
     pub lastfm_api_key: Option<String>,
 
+    /// Your TheAudioDB API key, enables TheAudioDB as a fallback source of album covers and artist bio ({bio} in templates)
+    #[arg(long, value_name = "api_key", value_parser = clap::value_parser!(String))]
+    pub theaudiodb_api_key: Option<String>,
+
     /// Do not use MusicBrainz as a fallback source of album covers
     #[arg(long)]
     pub disable_musicbrainz_cover: bool,
 
+    /// Do not use iTunes and Deezer as fallback sources of album covers
+    #[arg(long)]
+    pub disable_store_covers: bool,
+
+    /// Ask Last.fm and MusicBrainz for covers one after another (default) or at the same time, the first found cover is used
+    #[arg(long, value_name = "strategy", value_parser = ["serial", "parallel"])]
+    pub cover_lookup_strategy: Option<String>,
+
+    /// Do not look up track links for tidal, deezer and qobuz buttons with Odesli (song.link), search links are used instead
+    #[arg(long)]
+    pub disable_odesli: bool,
+
+    /// Path to mpv JSON IPC socket, used as a fallback when mpv runs without MPRIS support
+    #[arg(long, value_name = "path", value_parser = clap::value_parser!(String))]
+    pub mpv_ipc_socket: Option<String>,
+
+    /// URL of VLC web interface, used as a fallback when VLC MPRIS support is missing or broken
+    #[arg(long, value_name = "url", value_parser = clap::value_parser!(String))]
+    pub vlc_http_url: Option<String>,
+
+    /// Password for VLC web interface
+    #[arg(long, value_name = "password", value_parser = clap::value_parser!(String))]
+    pub vlc_http_password: Option<String>,
+
+    /// Address of Snapcast server JSON-RPC, used to read metadata of streams played through Snapcast
+    #[arg(long, value_name = "host:port", value_parser = clap::value_parser!(String))]
+    pub snapcast_address: Option<String>,
+
+    /// Read metadata of PipeWire audio streams (media.title, media.artist) when the app has no MPRIS support
+    #[arg(long)]
+    pub pipewire_streams: bool,
+
+    /// Address of localhost endpoint for browser extension sending metadata of web players, e.g. 127.0.0.1:9465
+    #[arg(long, value_name = "host:port", value_parser = clap::value_parser!(String))]
+    pub browser_extension_address: Option<String>,
+
+    /// D-Bus used to find MPRIS players: session (default), system or bus address, e.g. unix:path=/run/user/1001/bus
+    #[arg(long, value_name = "address", value_parser = clap::value_parser!(String))]
+    pub dbus_address: Option<String>,
+
     /// Show debug log
     #[arg(long)]
     #[serde(skip_deserializing)]
@@ -97,6 +448,31 @@ pub struct Cli {
     #[serde(skip_deserializing)]
     pub reset_config: bool,
 
+    /// Use given config file instead of the user config file
+    #[arg(long = "config", value_name = "path", value_parser = clap::value_parser!(PathBuf))]
+    #[serde(skip_deserializing)]
+    pub config_file: Option<PathBuf>,
+
+    /// Override a config option for this run, e.g. --set small_image=none. Use multiple times to set several options
+    #[arg(long = "set", value_name = "key=value", value_parser = clap::value_parser!(String))]
+    #[serde(skip_deserializing)]
+    pub set: Vec<String>,
+
+    /// Store config and cache in music-discord-rpc-data next to the executable (also MDRPC_PORTABLE=1)
+    #[arg(long)]
+    #[serde(skip_deserializing)]
+    pub portable: bool,
+
+    /// Print version
+    #[arg(short = 'V', long = "version")]
+    #[serde(skip_deserializing)]
+    pub print_version: bool,
+
+    /// With --version, also list compiled features and what is available on this system
+    #[arg(long, requires = "print_version")]
+    #[serde(skip_deserializing)]
+    pub verbose: bool,
+
     /// Recursive fields
     #[serde(skip_deserializing)]
     #[command(flatten)]
@@ -129,33 +505,108 @@ pub enum Commands {
     Restart {},
     /// Open interactive configuration editor
     Config {},
+    /// Interactive first run setup: Last.fm, allowlist, buttons and autostart
+    Setup {},
+    /// Manage album cover cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommands,
+    },
+    /// Manually set album covers
+    Cover {
+        #[command(subcommand)]
+        action: CoverCommands,
+    },
+    /// Last.fm tools
+    Lastfm {
+        #[command(subcommand)]
+        action: LastfmCommands,
+    },
+    /// Control the player whose status is currently displayed
+    Ctl {
+        /// Command sent to the running instance
+        #[arg(value_parser = ["play", "pause", "play-pause", "next", "previous", "player", "stats", "state", "refresh-cover"])]
+        command: String,
+    },
+    /// Show live events of the running instance: player, metadata, cover and activity changes
+    Tail {},
+    /// Collect config (without secrets), recent logs, players, cache stats and versions into a Markdown bug report
+    Report {
+        /// Save the report to file instead of printing it
+        #[arg(short, long, value_name = "path")]
+        output: Option<PathBuf>,
+    },
+    /// Download the latest release from GitHub and replace this executable
+    Update {
+        /// Only check if a new version is available
+        #[arg(long)]
+        #[serde(skip_deserializing)]
+        check: bool,
+    },
+    /// Print shell completion script for bash, zsh, fish, elvish or powershell
+    Completions {
+        #[arg(value_parser = ["bash", "zsh", "fish", "elvish", "powershell"])]
+        shell: String,
+    },
+    /// Print man page in roff format
+    Manpage {},
 }
 
-// Use to get config path, create new config or reset existing
-pub fn create_config_file(force: bool) -> (bool, PathBuf) {
-    let mut config_file = match get_config_path() {
-        Some(path) => path,
-        None => {
-            println!("\x1b[31mWARNING: Failed to determine user config directory.\x1b[0m");
-            return (false, PathBuf::new());
-        }
-    };
-    config_file.push("music-discord-rpc");
+#[derive(Subcommand, Debug, Serialize, Clone)]
+pub enum CoverCommands {
+    /// Save cover link to cache permanently, it won't be replaced or removed by cache limits
+    Set {
+        /// Album in "Album Artist - Album" format, as shown in the debug log
+        album: String,
+        /// Link to image
+        url: String,
+    },
+    /// Remove album cover from cache, it will be fetched again
+    Unset {
+        /// Album in "Album Artist - Album" format
+        album: String,
+    },
+}
 
-    let config_dir = config_file.clone();
-    config_file.push("config.yaml");
+#[derive(Subcommand, Debug, Serialize, Clone)]
+pub enum LastfmCommands {
+    /// Check if Last.fm API key works and show cover found for sample album
+    Test {
+        /// Artist of the sample album
+        #[arg(long, default_value = "Daft Punk")]
+        artist: String,
+        /// Sample album name
+        #[arg(long, default_value = "Discovery")]
+        album: String,
+    },
+}
 
-    if config_file.exists() && !force {
-        return (true, config_file);
-    }
+#[derive(Subcommand, Debug, Serialize, Clone, Copy)]
+pub enum CacheCommands {
+    /// Remove least recently used cover images exceeding the cover_cache_max_size limit
+    Prune {},
+}
 
-    let config_text = r#"# music-discord-rpc configuration file
+// System-wide config, used as a base that user config overrides
+pub const SYSTEM_CONFIG_FILE: &str = "/etc/music-discord-rpc/config.yaml";
+
+// User config: $XDG_CONFIG_HOME/music-discord-rpc/config.yaml or ~/.config/music-discord-rpc/config.yaml,
+// in portable mode music-discord-rpc-data/config.yaml next to the executable
+pub fn get_user_config_file() -> Option<PathBuf> {
+    Some(get_app_config_dir()?.join("config.yaml"))
+}
+
+// Default config file with comments
+pub const CONFIG_TEMPLATE: &str = r#"# music-discord-rpc configuration file
 
 # You can reset this file using the command:
 # music-discord-rpc --reset-config
 # Or you can manually copy the example config from repo:
 # https://github.com/patryk-ku/music-discord-rpc/blob/main/config.yaml
 
+# Config file format version, used to upgrade old config files. Do not change it.
+version: 1
+
 # If you compiled binary by yourself, you may need to provide your Last.fm API key here.
 # Or if you use precompiled binary, you can override the default Last.fm API key.
 # You can easily get it from: https://www.last.fm/pl/api
@@ -164,21 +615,63 @@ pub fn create_config_file(force: bool) -> (bool, PathBuf) {
 # You can also disable Last.fm as a cover source by providing an empty string as the key.
 # lastfm_api_key: ""
 
+# TheAudioDB is used as the last source of album covers when this key is set, if the album has
+# no cover there the artist photo is shown. It also provides a short artist bio ({bio} in templates).
+# Free key from: https://www.theaudiodb.com/free_key
+# theaudiodb_api_key: key_here
+
 # Activity refresh rate in seconds (min 5)
 interval: 10
 
-# Select visible activity buttons (max 2) [possible values: yt, lastfm, listenbrainz, mprisUrl, shamelessAd]
+# Refresh rate in milliseconds, overrides interval. Allows refreshing more often than every 5 seconds,
+# e.g. to notice seeking faster, at the cost of more D-Bus traffic. Values below min_interval_ms
+# (default: 1000, min: 100) are raised to it.
+# interval_ms: 1000
+# min_interval_ms: 1000
+
+# By default the refresh is faster (2s) for a moment after track change and slower (30s) when
+# no player is running for a minute. Set to true to always use the interval above.
+disable_adaptive_interval: false
+
+# When Discord is not running, sleep until it creates its IPC socket in $XDG_RUNTIME_DIR (watched
+# with inotify) instead of trying to connect every few seconds. Linux only.
+wait_for_discord_socket: false
+
+# Publish activity to every running Discord client, e.g. stable and Canary or two accounts in different
+# sandboxes, instead of only the first one found. Each client has its own IPC socket (discord-ipc-0,
+# discord-ipc-1, ...), use discord_sockets to publish only to some of them, matched like allowlist
+# entries against the socket path and name.
+multiple_discord_clients: false
+# discord_sockets:
+#   - "discord-ipc-0"
+#   - 're:app/com\.discordapp\.Discord/'
+
+# Select visible activity buttons (max 2) [possible values: yt, lastfm, lastfmTrack, listenbrainz, musicbrainz, mprisUrl, appleMusic, tidal, deezer, qobuz, shamelessAd]
 # button:
 #   - yt
 #   - lastfm
 
+# Custom button labels [possible keys: yt, lastfm, lastfmTrack, listenbrainz, musicbrainz, mprisUrl, appleMusic, tidal, deezer, qobuz, spotify, stream, shamelessAd]
+# button_labels:
+#   yt: "Szukaj na YouTube"
+#   mprisUrl: "Odtwórz"
+
 # Uncomment and enter your nicknames for activity buttons
 # lastfm_name: "nickname"
 # listenbrainz_name: "nickname"
 
+# Show a heart icon next to the album cover when the current track is loved on your Last.fm profile
+# (requires lastfm_name). {loved} in templates is a heart too, e.g. details_format: "{title}[ {loved}]"
+lastfm_loved_icon: false
+
 # Select what will be displayed after "Listening to" (default: artist) [possible values: artist, track, none]
 # rpc_name: artist
 
+# Select which part of the activity is displayed after "Listening to" on the user list, replaces the one chosen
+# by rpc_name, e.g. with state_format: "by: {artist}" and status_display: details the second line is "by: Artist"
+# and the user list shows the track title [possible values: state, details, name]
+# status_display: details
+
 # Select the icon displayed next to the album cover (default playPause) [possible values: playPause, player, lastfmAvatar, none]
 small_image: playPause
 
@@ -191,33 +684,327 @@ small_image: playPause
 # Additionally, it also disables icon and player name replacement on YouTube if it detects a YouTube thumbnail link.
 disable_mpris_art_url: false
 
+# YouTube thumbnails provided by players are low quality and have black bars, they are replaced with better ones.
+# "max" - the highest resolution available, "high" - 1280x720, "original" - don't change (default: max)
+youtube_thumbnail_quality: "max"
+
+# Twitch and YouTube live streams are displayed as "Watching" with "LIVE • channel", elapsed time and
+# a "Watch Stream" button. Set to true to display them like other videos.
+disable_live_presence: false
+
 # Only use the status from the following music players
 # Use -l, --list-players to get player exact name to use with this option
 # The order matters and the first is the most important.
+# Entries match the player name, D-Bus bus name, desktop entry or executable name. End an entry with * to match names starting
+# with it, or start it with re: to use a regular expression, e.g. 're:^org\.mpris\.MediaPlayer2\.chromium'
 # allowlist:
 #   - "VLC Media Player"
 #   - "Chrome"
 #   - "Any other player"
+# Entries can have a priority (default: 0), higher is more important and the order is used only
+# for equal priorities. allowlist_sort_by decides what matters more when several players are active:
+# "status" (default) - playing > paused > stopped, then priority
+# "priority" - priority first, e.g. paused Spotify is shown instead of a playing browser
+# allowlist:
+#   - name: "Spotify"
+#     priority: 10
+#   - "Chrome"
+# allowlist_sort_by: "status"
+
+# Pause allowlisted players with lower priority (or lower on the allowlist) while the player shown
+# in the activity is playing, so what you hear matches your status. Linux only.
+exclusive_playback: false
 
 # Will use the "watching" activity
 # Use -l, --list-players to get player exact name to use with this option
+# Entries are matched like in allowlist, so * and re: can be used too.
 # video_players:
 #   - "VLC Media Player"
 #   - "Chrome"
 
+# Video files without title tag are shown with the file name, which is made readable for video players:
+# the extension, [tags] and everything from the quality or codec (1080p, x264, WEB-DL...) are removed and
+# dots and underscores are replaced with spaces, e.g. "Some.Movie.2019.1080p.x264.mkv" => "Some Movie 2019"
+disable_video_title_cleanup: false
+
+# Titles of TV show episodes (S02E05, 2x05, "Season 2 Episode 5", Jellyfin's "Show (2019) - S02E05 - Title")
+# are shown as "Show — S02E05 • Episode Title" for video players. The show name is taken from the album if the
+# title has only the episode. {show}, {episode} (S02E05) and {episode_title} are available in templates.
+disable_episode_parsing: false
+
 # Hide the album name to decrease activity height
 hide_album_name: false
 
+# Don't show the progress bar and elapsed time under the activity, e.g. when {elapsed} or
+# {remaining} is used in templates instead. hide_timestamps works too
+hide_progress_bar: false
+
+# Hide the artist name (the second line of activity)
+hide_artist: false
+
+# Hide activity buttons, including Spotify and stream buttons (buttons set by user script are still shown)
+hide_buttons: false
+
+# Hide the small icon next to the album cover, also the paused icon
+hide_small_image: false
+
 # Only send activity when media is playing
 only_when_playing: false
 
+# Some players report stopped but keep the last track. What to do then:
+# "paused" - show as paused (default), "clear" - clear activity immediately,
+# "keep" - show as paused for stopped_keep_time seconds (default: 30) and then clear
+stopped_behavior: "paused"
+# stopped_keep_time: 30
+
+# When you switch to another player: "clear" - clear activity immediately (default),
+# "republish" - keep the old activity until the new player's activity is published, without a gap
+player_change_behavior: "clear"
+
+# Spotify free and some web radios play advertisements as tracks. What to do while an ad plays:
+# "show" - display it like any other track (default), "keep" - keep the previous track displayed,
+# "clear" - clear activity. Ads are detected by Spotify ad links, titles like "Advertisement" and
+# short tracks without artist and album. Add your own patterns of titles or artists in ad_patterns,
+# matched like allowlist entries.
+ad_behavior: "show"
+# ad_patterns:
+#   - "Werbung"
+#   - "re:(?i)^sponsored by"
+
+# Hide activity while you share your screen or stream, and show it again afterwards. Streaming is
+# detected by running processes from streaming_processes (default: obs), matched like allowlist
+# entries, and on Linux also by screen casts started through xdg-desktop-portal (PipeWire).
+pause_while_streaming: false
+# streaming_processes:
+#   - "obs"
+#   - "simplescreenrecorder"
+
+# Hide activity while you play a game, so your profile shows the game, and show it again when the
+# game is closed. Games started from Steam are detected automatically (Linux only), add other games
+# to game_processes, matched like allowlist entries.
+yield_to_games: false
+# game_processes:
+#   - "factorio"
+#   - "wineserver"
+
+# Show your music only casually: after the activity was visible for daily_presence_limit minutes,
+# it's hidden until daily_presence_reset_hour (0-23, local time, default: 0). Used time is kept in
+# the cache directory, so it's not reset by restarts.
+# daily_presence_limit: 120
+# daily_presence_reset_hour: 4
+
+# With crossfade or gapless playback the next track starts while the previous one is still playing,
+# so its position can go back for a moment. During this many seconds after track change it's not
+# treated as seek or replay (default: 5, 0 to disable).
+# transition_window: 5
+
+# Experimental: when the player is Spotify, show "Listening to Spotify" with links to the track on Spotify,
+# similar to Discord's native Spotify integration. Other players use the normal activity.
+spotify_mode: false
+
+# Text shown as the activity name in the header of activity, e.g. "Listening to Vinyl" (default: Music).
+# It's also displayed on the user list with rpc_name: none or status_display: name
+# activity_name: "Vinyl"
+
+# Text shown when hovering over album cover (default: "album: {album}", with year and label from tags of local files).
+# Available: {album}, {artist}, {album_artist}, {title}, {year}, {tracks}, {label}, {catalog}, {bio}, {rating}, {queue},
+# {playlist}, {elapsed}, {remaining}, {loved}, {repeat_count}. Year, record label and catalog number are read from tags of local files, year and
+# number of tracks are also taken from MusicBrainz, artist bio (first sentence) from TheAudioDB if
+# theaudiodb_api_key is set, rating from the player (e.g. Strawberry, Quod Libet), queue position ("3/24") and playlist name
+# from players with MPRIS TrackList and Playlists (Linux). Played and remaining time of the track looks like "2:31".
+# {loved} is a heart when the track is loved on Last.fm (requires lastfm_name). {repeat_count} is the number of plays
+# in a row of the same track (e.g. with repeat of one track in the player), unknown on the first play.
+# Parts in [square brackets] are hidden if any value inside them is unknown.
+# large_text_format: "{album}[ ({year})][ • {tracks} tracks]"
+
+# Add the number of plays in a row of the same track to the text shown on album cover hover, e.g. "album: Album 🔁 x5".
+# It's reset when the track changes.
+show_repeat_count: false
+
+# How {rating} is displayed (default: stars) [possible values: stars, percent]
+# rating_style: stars
+
+# Layout of the first (details) and second (state) line of activity, the same values as above, {player} and
+# {deck} (deck letter of DJ software, e.g. A in Mixxx).
+# E.g. to show where you're listening without the player icon:
+# details_format: "{player} • {title}"
+# state_format: "{artist}"
+
+# How often the activity is updated (in seconds) while {elapsed} or {remaining} is used in templates
+# (default: 15, min: 5). Discord accepts about 5 updates per 20 seconds, so lower values can make
+# some updates dropped. Combine with hide_progress_bar to show e.g. state_format: "{artist} • {remaining} left"
+# time_text_interval: 15
+
+# Settings for specific players, use -l, --list-players to get player exact name
+# activity_type: listening (default), watching (default for video_players), playing or competing
+# interval, interval_ms: refresh rate used while the player is selected, e.g. lower for browsers to
+#   notice tab changes sooner
+# player_overrides:
+#   "VLC media player":
+#     activity_name: "Vinyl"
+#   "Mixxx":
+#     activity_type: competing
+#   "Mozilla Firefox":
+#     interval: 5
+#   "Spotify":
+#     interval: 15
+
+# Workarounds for players with broken metadata, matched like allowlist entries. Some players have them built in
+# (Apple Music, Strawberry, Clementine), values set here replace the built-in ones.
+# album_suffixes: removed from album name, e.g. Apple Music " - EP"
+# cover: lookup (default, search for album cover first) or player (use player artwork if it has a link)
+# timestamps: trusted (default), lagging (position is read again after seeking, for players reporting old position)
+#   or none (no progress bar, for players reporting wrong position)
+# time_unit: auto (default, length over 24 hours is treated as reported in wrong unit), microseconds (as in MPRIS
+#   specification, implausible values are dropped) or nanoseconds (for players reporting tracks many hours long)
+# activity_type: default activity type of the player, e.g. playing for DJ software (Mixxx has it built in)
+# details_format: default template of the first line, used when details_format is not set
+#   (Mixxx: "[Deck {deck}: ]{artist} - {title}")
+# player_quirks:
+#   "Cider":
+#     album_suffixes: [" - EP", " - Single"]
+#   "Strawberry":
+#     timestamps: lagging
+
+# Run commands on player events (in sh). Metadata is passed as environment variables
+# (MDRPC_EVENT, MDRPC_PLAYER, MDRPC_TITLE, MDRPC_ARTIST, MDRPC_ALBUM, MDRPC_ALBUM_ARTIST, MDRPC_STATUS,
+# MDRPC_DURATION, MDRPC_POSITION, MDRPC_URL, MDRPC_COVER_URL) and as JSON on stdin.
+# hooks:
+#   on_track_change: 'notify-send "$MDRPC_TITLE" "$MDRPC_ARTIST"'
+#   on_pause: ""
+#   on_stop: ""
+#   on_player_change: ""
+
+# Also show the track in your Discord custom status. Discord doesn't let apps change it through RPC and
+# using your account token is against Discord ToS, so it's done by your own command, e.g. talking to
+# a client mod plugin. It's run when the text changes, with MDRPC_STATUS_TEXT and MDRPC_STATUS_EMOJI
+# environment variables (and JSON on stdin). Empty text means the status should be cleared.
+# custom_status:
+#   command: 'echo "$MDRPC_STATUS_EMOJI $MDRPC_STATUS_TEXT" > /tmp/discord-status'
+#   text_format: "{title} - {artist}"
+#   emoji: "🎵"
+
+# Rhai script that can change metadata and activity on every update, see README (default: script.rhai in config directory, if exists)
+# script_file: "/home/user/.config/music-discord-rpc/script.rhai"
+
+# YAML file with translations of texts displayed in the activity (status, prefixes, button labels), see README
+# locale_file: "/home/user/.config/music-discord-rpc/locale.yaml"
+
+# Language of texts displayed in the activity and console (default: from LANG environment variable).
+# Bundled translations: en, pl
+# locale: "en"
+
 # Prevent MusicBrainz to be used as source of album cover if cover is not available on Last.fm
 disable_musicbrainz_cover: false
 
+# Prevent iTunes and Deezer to be used as sources of album cover if cover is not available on
+# Last.fm and MusicBrainz. They don't need API key, so covers are found also without Last.fm key.
+disable_store_covers: false
+
+# How Last.fm and MusicBrainz are asked for album covers, possible values:
+# "serial" (default) - MusicBrainz is asked only after Last.fm found nothing
+# "parallel" - both at once (MusicBrainz slightly later), the first found cover is used. Faster
+# for albums missing on Last.fm, but MusicBrainz covers are used more often.
+# cover_lookup_strategy: "serial"
+
+# Do not look up track links for tidal, deezer and qobuz buttons with Odesli (song.link), the buttons
+# will always search the song on these services
+disable_odesli: false
+
 # Disable cache (not recommended)
 disable_cache: false
+
+# Also download and store album cover images (not only links) in cache directory
+cache_cover_images: false
+
+# Max size of stored cover images in MB, least recently used are removed first (default: 50)
+# Use "music-discord-rpc cache prune" to apply the limit manually.
+# cover_cache_max_size: 50
+
+# Max number of albums stored in cover links cache (album_cache.db), least recently used are removed first (default: 10000)
+# album_cache_max_entries: 10000
+
+# How cache files are written. They are always replaced in one step, so a crash doesn't leave half
+# written file, and a corrupted one is moved aside (e.g. album_cache.db.corrupted-1700000000) and
+# rebuilt. Possible values:
+# "auto" (default) - written after every change, the system decides when data reaches the disk
+# "fsync" - also flushed to disk after every change, safe on power loss but slower on HDD
+# cache_dump_policy: "auto"
+
+# Manually set album covers, checked before any other source. The key is "Album Artist - Album".
+# You can also use "music-discord-rpc cover set" command to save cover permanently in cache.
+# cover_overrides:
+#   "Artist - Album": "https://example.com/cover.jpg"
+
+# Use images dropped into the covers/ folder next to this config file as album covers, named like
+# "Album Artist - Album.jpg" (png, webp and gif work too, "/" in names is replaced with "_"). They
+# override all other sources and are picked up while the app runs. Discord accepts only links, so
+# each image is uploaded once with HTTP PUT to <cover_upload_url>/<file name>, and the server has to
+# reply with the link to it (e.g. self-hosted transfer.sh).
+cover_folder: false
+# Artwork images provided by players instead of links (VLC local files, data: links, macOS) are uploaded
+# there too when no cover is found online.
+# cover_upload_url: "https://transfer.example.com"
+
+# Player artwork that isn't square (e.g. 16:9 video thumbnails) is cropped by Discord. With square_covers it's
+# fitted into a square with bars in the color of its edges ("pad", default) or its center is cut out ("crop"),
+# and uploaded to cover_upload_url. square_covers_rounded also rounds the corners of the processed image.
+square_covers: false
+# square_covers_mode: "pad"
+square_covers_rounded: false
+
+# Serve Prometheus metrics (activity updates, cover provider hits/misses, Discord reconnects, player changes, loop latency) at http://<address>/metrics
+# metrics_address: "127.0.0.1:9464"
+
+# Do not listen for playback commands from "music-discord-rpc ctl" on control socket
+disable_control_socket: false
+
+# Save "now playing" card image with album cover, title, artist and progress bar as now_playing.png in
+# cache directory, e.g. for OBS image source. Album covers from links are used only with cache_cover_images.
+now_playing_card: false
+# now_playing_card_font: "/usr/share/fonts/noto/NotoSans-Regular.ttf"
+
+# Read status directly from mpv JSON IPC socket when mpv runs without the MPRIS script.
+# Start mpv with --input-ipc-server=/tmp/mpvsocket or add "input-ipc-server=/tmp/mpvsocket" to mpv.conf.
+# mpv_ipc_socket: "/tmp/mpvsocket"
+
+# Read status from VLC web interface when VLC MPRIS support is missing or broken.
+# Enable it in VLC: Preferences > All > Interface > Main interfaces > Web, and set a password in Main interfaces > Lua.
+# vlc_http_url: "http://localhost:8080"
+# vlc_http_password: "password"
+
+# Read metadata of streams played through Snapcast server (JSON-RPC port, default 1705).
+# snapcast_address: "127.0.0.1:1705"
+
+# Read metadata of PipeWire audio streams (media.title, media.artist) of apps without MPRIS support (Linux only, requires pw-dump).
+pipewire_streams: false
+
+# Accept metadata of web players (Bandcamp, SoundCloud, Yandex Music...) from a browser extension on this
+# localhost address. It's used instead of the browser MPRIS player while the extension reports playback.
+# browser_extension_address: "127.0.0.1:9465"
+
+# D-Bus used to find MPRIS players (Linux only): "session" (default), "system" or bus address,
+# e.g. session bus of another user: "unix:path=/run/user/1001/bus", remote bus: "tcp:host=192.168.1.10,port=55556"
+# dbus_address: "session"
 "#;
 
+// Use to get config path, create new config or reset existing
+pub fn create_config_file(force: bool) -> (bool, PathBuf) {
+    let config_file = match get_user_config_file() {
+        Some(path) => path,
+        None => {
+            println!("\x1b[31mWARNING: Failed to determine user config directory.\x1b[0m");
+            return (false, PathBuf::new());
+        }
+    };
+    let config_dir = config_file.parent().unwrap_or(&config_file).to_path_buf();
+
+    if config_file.exists() && !force {
+        return (true, config_file);
+    }
+
+    let config_text = CONFIG_TEMPLATE;
+
     match fs::create_dir_all(&config_dir) {
         Err(_) => {
             println!("[config] Failed to create config directory.");
@@ -238,44 +1025,230 @@ disable_cache: false
     return (true, config_file);
 }
 
+// Allowlist entries can be names or { name, priority } maps, priorities are moved to
+// allowlist_priorities, so the allowlist stays a list of names. Returns false if there are none.
+fn split_allowlist_priorities(yaml: &mut serde_yaml::Value) -> bool {
+    let Some(entries) = yaml
+        .get_mut("allowlist")
+        .and_then(|allowlist| allowlist.as_sequence_mut())
+    else {
+        return false;
+    };
+    let mut priorities = serde_yaml::Mapping::new();
+    let mut found = false;
+    for entry in entries.iter_mut() {
+        let Some(name) = entry.get("name").and_then(|name| name.as_str()) else {
+            continue;
+        };
+        let name = serde_yaml::Value::from(name);
+        if let Some(priority) = entry.get("priority") {
+            priorities.insert(name.clone(), priority.clone());
+        }
+        *entry = name;
+        found = true;
+    }
+    if found {
+        yaml["allowlist_priorities"] = serde_yaml::Value::Mapping(priorities);
+    }
+    found
+}
+
+fn parse_config(yaml_str: &str) -> Result<<Cli as ClapSerde>::Opt, serde_yaml::Error> {
+    let mut yaml: serde_yaml::Value = serde_yaml::from_str(yaml_str)?;
+    match split_allowlist_priorities(&mut yaml) {
+        true => serde_yaml::from_value(yaml),
+        // Parsing the text directly gives errors with line numbers
+        false => serde_yaml::from_str(yaml_str),
+    }
+}
+
+// Apply "key=value" overrides from --set like a config file with these options. Values are parsed as
+// YAML, so lists and maps can be passed too, e.g. button=[yt, lastfm].
+fn apply_overrides(config: &mut Cli, overrides: &[String]) {
+    let exit = |message: String| -> ! {
+        println!("\x1b[31mERROR: {}\x1b[0m", message);
+        process::exit(1);
+    };
+
+    for entry in overrides {
+        let Some((key, value)) = entry.split_once('=') else {
+            exit(format!("Invalid --set \"{}\", expected key=value", entry));
+        };
+        let key = key.trim();
+        let before = serde_yaml::to_value(&*config).unwrap_or_default();
+        if before.get(key).is_none() {
+            exit(format!("Unknown config option in --set: \"{}\"", key));
+        }
+        let invalid_value = |err: serde_yaml::Error| -> ! {
+            exit(format!("Invalid value of \"{}\" in --set: {}", key, err))
+        };
+
+        let value = serde_yaml::from_str::<serde_yaml::Value>(value)
+            .unwrap_or_else(|err| invalid_value(err));
+        let mut option = serde_yaml::Mapping::new();
+        option.insert(key.into(), value.clone());
+        let mut option = serde_yaml::Value::Mapping(option);
+        split_allowlist_priorities(&mut option);
+        match serde_yaml::from_value::<<Cli as ClapSerde>::Opt>(option) {
+            Ok(opt) => config.update(opt),
+            Err(err) => invalid_value(err),
+        }
+
+        // Options like debug_log exist only as command line arguments
+        let after = serde_yaml::to_value(&*config).unwrap_or_default();
+        if after.get(key) == before.get(key) && before.get(key) != Some(&value) {
+            println!(
+                "\x1b[33mWARNING: {} can't be changed with --set, use its command line argument.\x1b[0m",
+                key
+            );
+        }
+    }
+}
+
+fn read_config_file(config_file: &PathBuf) -> Option<<Cli as ClapSerde>::Opt> {
+    match fs::read_to_string(config_file) {
+        Ok(yaml_str) => match parse_config(&config_migration::migrate(config_file, yaml_str)) {
+            Ok(yaml_args) => Some(yaml_args),
+            Err(error) => {
+                println!(
+                    "Failed to parse config file {}: {}",
+                    config_file.display(),
+                    error
+                );
+                None
+            }
+        },
+        Err(_) => {
+            println!("Failed to read config file: {}", config_file.display());
+            None
+        }
+    }
+}
+
+// Change description of subcommand on given path, e.g. ["cover", "set"]
+fn set_subcommand_about(command: clap::Command, path: &[&str], about: &str) -> clap::Command {
+    match path.split_first() {
+        Some((name, rest)) => match command.find_subcommand(name).is_some() {
+            true => command.mut_subcommand(*name, |subcommand| {
+                set_subcommand_about(subcommand, rest, about)
+            }),
+            false => command,
+        },
+        None => command.about(about.to_string()),
+    }
+}
+
+// Parse arguments with subcommand help translated to language from LANG (config is not loaded yet)
+fn parse_args() -> Cli {
+    let mut command = Cli::command();
+    for (subcommand, about) in locale::load(None, None, false).help {
+        let path: Vec<&str> = subcommand.split_whitespace().collect();
+        command = set_subcommand_about(command, &path, &about);
+    }
+    Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|err| err.exit())
+}
+
+fn print_completions(shell: &str) -> ! {
+    let shell = shell.parse::<clap_complete::Shell>().unwrap_or_else(|err| {
+        println!("\x1b[31m{}\x1b[0m", err);
+        process::exit(1);
+    });
+    clap_complete::generate(
+        shell,
+        &mut Cli::command(),
+        "music-discord-rpc",
+        &mut io::stdout(),
+    );
+    process::exit(0);
+}
+
+fn print_manpage() -> ! {
+    if let Err(err) = clap_mangen::Man::new(Cli::command()).render(&mut io::stdout()) {
+        println!("\x1b[31mCould not generate man page: {}\x1b[0m", err);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
 // Used to get settings merged from args and config file
 pub fn load_settings() -> Cli {
-    let args = Cli::parse();
+    let args = parse_args();
+    utils::set_portable(args.portable);
     debug_log!(args.debug_log, "Debug logs: enabled.");
     debug_log!(args.debug_log, "args: {:#?}", args);
 
+    if args.print_version {
+        build_info::print_version(args.verbose);
+        process::exit(0);
+    }
+
     // Reset config file is user used --reset-config and exit
     if args.reset_config {
         create_config_file(true);
         process::exit(0);
     }
 
-    let (mut config_exists, config_file) = create_config_file(false);
-    if !config_exists {
-        return args;
+    // Generated from the options definitions, printed before anything else is written to stdout
+    match &args.suboptions.command {
+        Some(Commands::Completions { shell }) => print_completions(shell),
+        Some(Commands::Manpage {}) => print_manpage(),
+        _ => {}
     }
 
-    // Read user config file
-    let mut config = match fs::read_to_string(&config_file) {
-        Ok(yaml_str) => match serde_yaml::from_str::<<Cli as ClapSerde>::Opt>(&yaml_str) {
-            Ok(yaml_args) => Cli::from(yaml_args),
-            Err(error) => {
-                println!("Failed to parse config file: {}", error);
-                config_exists = false;
-                Cli::from_clap()
+    // Config files, values from the later ones override the earlier ones:
+    // system-wide config < user config (or file from --config) < command line arguments
+    let mut config_files: Vec<PathBuf> = Vec::new();
+    let system_config_file = PathBuf::from(SYSTEM_CONFIG_FILE);
+    if system_config_file.exists() && !utils::is_portable() {
+        config_files.push(system_config_file);
+    }
+
+    match &args.config_file {
+        Some(path) => {
+            if !path.exists() {
+                println!(
+                    "\x1b[31mERROR: Config file not found: {}\x1b[0m",
+                    path.display()
+                );
+                process::exit(1);
             }
-        },
-        Err(_) => {
-            println!("Failed to read config file.");
-            config_exists = false;
-            Cli::from_clap()
+            config_files.push(path.clone());
         }
-    };
+        None => {
+            // Generated user config would override every value of the system-wide one,
+            // so create it only if there is no system-wide config
+            let user_config_exists = get_user_config_file().is_some_and(|path| path.exists());
+            if config_files.is_empty() || user_config_exists {
+                let (config_exists, config_file) = create_config_file(false);
+                if config_exists {
+                    config_files.push(config_file);
+                }
+            }
+        }
+    }
+
+    // Read config files
+    let mut config = Cli::default();
+    let mut config_exists = false;
+    for config_file in &config_files {
+        if let Some(yaml_args) = read_config_file(config_file) {
+            config.update(yaml_args);
+            config_exists = true;
+            // Output of --json and report must stay valid
+            if !args.json && !matches!(args.suboptions.command, Some(Commands::Report { .. })) {
+                println!("Configuration loaded from file: {}", config_file.display());
+            }
+        }
+    }
 
+    // Overrides from --set replace values from config files, but not other arguments
     if !config_exists {
+        let mut args = args;
+        let overrides = args.set.clone();
+        apply_overrides(&mut args, &overrides);
         return args;
     }
-    println!("Configuration loaded from file: {}", config_file.display());
+    apply_overrides(&mut config, &args.set);
     debug_log!(args.debug_log, "config: {:#?}", config);
 
     // Logic of merging config with args
@@ -283,14 +1256,46 @@ pub fn load_settings() -> Cli {
         config.interval = args.interval;
     }
 
+    if args.interval_ms != config.interval_ms && args.interval_ms.is_some() {
+        config.interval_ms = args.interval_ms;
+    }
+
+    if args.min_interval_ms != config.min_interval_ms && args.min_interval_ms.is_some() {
+        config.min_interval_ms = args.min_interval_ms;
+    }
+
+    if args.disable_adaptive_interval {
+        config.disable_adaptive_interval = args.disable_adaptive_interval;
+    }
+
+    if args.wait_for_discord_socket {
+        config.wait_for_discord_socket = args.wait_for_discord_socket;
+    }
+
+    if args.multiple_discord_clients {
+        config.multiple_discord_clients = args.multiple_discord_clients;
+    }
+
+    if !args.discord_sockets.is_empty() {
+        config.discord_sockets = args.discord_sockets;
+    }
+
     if args.button != config.button && args.button.len() > 0 {
         config.button = args.button;
     }
 
+    if !args.button_labels.is_empty() {
+        config.button_labels = args.button_labels;
+    }
+
     if args.lastfm_name != config.lastfm_name && args.lastfm_name.is_some() {
         config.lastfm_name = args.lastfm_name;
     }
 
+    if args.lastfm_loved_icon {
+        config.lastfm_loved_icon = args.lastfm_loved_icon;
+    }
+
     if args.listenbrainz_name != config.listenbrainz_name && args.listenbrainz_name.is_some() {
         config.listenbrainz_name = args.listenbrainz_name;
     }
@@ -299,6 +1304,10 @@ pub fn load_settings() -> Cli {
         config.rpc_name = args.rpc_name;
     }
 
+    if args.status_display != config.status_display && args.status_display.is_some() {
+        config.status_display = args.status_display;
+    }
+
     if args.small_image != config.small_image && args.small_image.is_some() {
         config.small_image = args.small_image;
     }
@@ -315,18 +1324,216 @@ pub fn load_settings() -> Cli {
         config.disable_musicbrainz_cover = args.disable_musicbrainz_cover;
     }
 
+    if args.disable_store_covers {
+        config.disable_store_covers = args.disable_store_covers;
+    }
+
+    if args.cover_lookup_strategy != config.cover_lookup_strategy
+        && args.cover_lookup_strategy.is_some()
+    {
+        config.cover_lookup_strategy = args.cover_lookup_strategy;
+    }
+
+    if args.disable_odesli {
+        config.disable_odesli = args.disable_odesli;
+    }
+
     if args.hide_album_name {
         config.hide_album_name = args.hide_album_name;
     }
 
+    if args.hide_progress_bar {
+        config.hide_progress_bar = args.hide_progress_bar;
+    }
+
+    if args.hide_artist {
+        config.hide_artist = args.hide_artist;
+    }
+
+    if args.hide_buttons {
+        config.hide_buttons = args.hide_buttons;
+    }
+
+    if args.hide_small_image {
+        config.hide_small_image = args.hide_small_image;
+    }
+
     if args.only_when_playing {
         config.only_when_playing = args.only_when_playing;
     }
 
+    if args.stopped_behavior != config.stopped_behavior && args.stopped_behavior.is_some() {
+        config.stopped_behavior = args.stopped_behavior;
+    }
+
+    if args.stopped_keep_time != config.stopped_keep_time && args.stopped_keep_time.is_some() {
+        config.stopped_keep_time = args.stopped_keep_time;
+    }
+
+    if args.player_change_behavior != config.player_change_behavior
+        && args.player_change_behavior.is_some()
+    {
+        config.player_change_behavior = args.player_change_behavior;
+    }
+
+    if args.ad_behavior != config.ad_behavior && args.ad_behavior.is_some() {
+        config.ad_behavior = args.ad_behavior;
+    }
+
+    if !args.ad_patterns.is_empty() {
+        config.ad_patterns = args.ad_patterns;
+    }
+
+    if args.pause_while_streaming {
+        config.pause_while_streaming = args.pause_while_streaming;
+    }
+
+    if !args.streaming_processes.is_empty() {
+        config.streaming_processes = args.streaming_processes;
+    }
+
+    if args.yield_to_games {
+        config.yield_to_games = args.yield_to_games;
+    }
+
+    if !args.game_processes.is_empty() {
+        config.game_processes = args.game_processes;
+    }
+
+    if args.daily_presence_limit != config.daily_presence_limit
+        && args.daily_presence_limit.is_some()
+    {
+        config.daily_presence_limit = args.daily_presence_limit;
+    }
+
+    if args.daily_presence_reset_hour != config.daily_presence_reset_hour
+        && args.daily_presence_reset_hour.is_some()
+    {
+        config.daily_presence_reset_hour = args.daily_presence_reset_hour;
+    }
+
+    if args.transition_window != config.transition_window && args.transition_window.is_some() {
+        config.transition_window = args.transition_window;
+    }
+
+    if args.spotify_mode {
+        config.spotify_mode = args.spotify_mode;
+    }
+
+    if args.activity_name != config.activity_name && args.activity_name.is_some() {
+        config.activity_name = args.activity_name;
+    }
+
+    if args.large_text_format != config.large_text_format && args.large_text_format.is_some() {
+        config.large_text_format = args.large_text_format;
+    }
+
+    if args.show_repeat_count {
+        config.show_repeat_count = args.show_repeat_count;
+    }
+
+    if args.rating_style != config.rating_style && args.rating_style.is_some() {
+        config.rating_style = args.rating_style;
+    }
+
+    if args.details_format != config.details_format && args.details_format.is_some() {
+        config.details_format = args.details_format;
+    }
+
+    if args.state_format != config.state_format && args.state_format.is_some() {
+        config.state_format = args.state_format;
+    }
+
+    if args.time_text_interval != config.time_text_interval && args.time_text_interval.is_some() {
+        config.time_text_interval = args.time_text_interval;
+    }
+
+    if !args.player_overrides.is_empty() {
+        config.player_overrides = args.player_overrides;
+    }
+
+    if !args.player_quirks.is_empty() {
+        config.player_quirks = args.player_quirks;
+    }
+
+    if args.script_file != config.script_file && args.script_file.is_some() {
+        config.script_file = args.script_file;
+    }
+
+    if args.locale_file != config.locale_file && args.locale_file.is_some() {
+        config.locale_file = args.locale_file;
+    }
+
+    if args.locale != config.locale && args.locale.is_some() {
+        config.locale = args.locale;
+    }
+
     if args.disable_cache {
         config.disable_cache = args.disable_cache;
     }
 
+    if args.cache_cover_images {
+        config.cache_cover_images = args.cache_cover_images;
+    }
+
+    if args.cover_cache_max_size != config.cover_cache_max_size
+        && args.cover_cache_max_size.is_some()
+    {
+        config.cover_cache_max_size = args.cover_cache_max_size;
+    }
+
+    if args.album_cache_max_entries != config.album_cache_max_entries
+        && args.album_cache_max_entries.is_some()
+    {
+        config.album_cache_max_entries = args.album_cache_max_entries;
+    }
+
+    if args.cache_dump_policy != config.cache_dump_policy && args.cache_dump_policy.is_some() {
+        config.cache_dump_policy = args.cache_dump_policy;
+    }
+
+    if !args.cover_overrides.is_empty() {
+        config.cover_overrides = args.cover_overrides;
+    }
+
+    if args.cover_folder {
+        config.cover_folder = args.cover_folder;
+    }
+
+    if args.cover_upload_url != config.cover_upload_url && args.cover_upload_url.is_some() {
+        config.cover_upload_url = args.cover_upload_url;
+    }
+
+    if args.square_covers {
+        config.square_covers = args.square_covers;
+    }
+
+    if args.square_covers_mode != config.square_covers_mode && args.square_covers_mode.is_some() {
+        config.square_covers_mode = args.square_covers_mode;
+    }
+
+    if args.square_covers_rounded {
+        config.square_covers_rounded = args.square_covers_rounded;
+    }
+
+    if args.metrics_address != config.metrics_address && args.metrics_address.is_some() {
+        config.metrics_address = args.metrics_address;
+    }
+
+    if args.disable_control_socket {
+        config.disable_control_socket = args.disable_control_socket;
+    }
+
+    if args.now_playing_card {
+        config.now_playing_card = args.now_playing_card;
+    }
+
+    if args.now_playing_card_font != config.now_playing_card_font
+        && args.now_playing_card_font.is_some()
+    {
+        config.now_playing_card_font = args.now_playing_card_font;
+    }
+
     if args.list_players {
         config.list_players = args.list_players;
     }
@@ -335,22 +1542,94 @@ pub fn load_settings() -> Cli {
         config.get_player_id = args.get_player_id;
     }
 
+    if args.json {
+        config.json = args.json;
+    }
+
+    if args.save.is_some() {
+        config.save = args.save;
+    }
+
     if args.allowlist != config.allowlist && args.allowlist.len() > 0 {
         config.allowlist = args.allowlist;
     }
 
+    if !args.allowlist_priorities.is_empty() {
+        config.allowlist_priorities = args.allowlist_priorities;
+    }
+
+    if args.allowlist_sort_by != config.allowlist_sort_by && args.allowlist_sort_by.is_some() {
+        config.allowlist_sort_by = args.allowlist_sort_by;
+    }
+
+    if args.exclusive_playback {
+        config.exclusive_playback = args.exclusive_playback;
+    }
+
     if args.video_players != config.video_players && args.video_players.len() > 0 {
         config.video_players = args.video_players;
     }
 
+    if args.disable_video_title_cleanup {
+        config.disable_video_title_cleanup = args.disable_video_title_cleanup;
+    }
+
+    if args.disable_episode_parsing {
+        config.disable_episode_parsing = args.disable_episode_parsing;
+    }
+
     if args.lastfm_api_key != config.lastfm_api_key && args.lastfm_api_key.is_some() {
         config.lastfm_api_key = args.lastfm_api_key;
     }
 
+    if args.theaudiodb_api_key != config.theaudiodb_api_key && args.theaudiodb_api_key.is_some() {
+        config.theaudiodb_api_key = args.theaudiodb_api_key;
+    }
+
+    if args.mpv_ipc_socket != config.mpv_ipc_socket && args.mpv_ipc_socket.is_some() {
+        config.mpv_ipc_socket = args.mpv_ipc_socket;
+    }
+
+    if args.vlc_http_url != config.vlc_http_url && args.vlc_http_url.is_some() {
+        config.vlc_http_url = args.vlc_http_url;
+    }
+
+    if args.vlc_http_password != config.vlc_http_password && args.vlc_http_password.is_some() {
+        config.vlc_http_password = args.vlc_http_password;
+    }
+
+    if args.snapcast_address != config.snapcast_address && args.snapcast_address.is_some() {
+        config.snapcast_address = args.snapcast_address;
+    }
+
+    if args.pipewire_streams {
+        config.pipewire_streams = args.pipewire_streams;
+    }
+
+    if args.browser_extension_address != config.browser_extension_address
+        && args.browser_extension_address.is_some()
+    {
+        config.browser_extension_address = args.browser_extension_address;
+    }
+
+    if args.dbus_address != config.dbus_address && args.dbus_address.is_some() {
+        config.dbus_address = args.dbus_address;
+    }
+
     if args.disable_mpris_art_url {
         config.disable_mpris_art_url = args.disable_mpris_art_url;
     }
 
+    if args.youtube_thumbnail_quality != config.youtube_thumbnail_quality
+        && args.youtube_thumbnail_quality.is_some()
+    {
+        config.youtube_thumbnail_quality = args.youtube_thumbnail_quality;
+    }
+
+    if args.disable_live_presence {
+        config.disable_live_presence = args.disable_live_presence;
+    }
+
     if args.debug_log {
         config.debug_log = args.debug_log;
     }
@@ -359,6 +1638,14 @@ pub fn load_settings() -> Cli {
         config.reset_config = args.reset_config;
     }
 
+    if args.config_file.is_some() {
+        config.config_file = args.config_file;
+    }
+
+    if args.portable {
+        config.portable = args.portable;
+    }
+
     config.suboptions = args.suboptions;
 
     return config;
@@ -0,0 +1,181 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::control::PlayerCommand;
+use crate::debug_log;
+use crate::utils::{ArtData, MediaInfo};
+
+// Client for Snapcast server JSON-RPC (TCP port 1705), reads metadata of the streamed sources
+#[derive(Debug, Clone)]
+pub struct SnapcastRpc {
+    pub address: String,
+}
+
+// Snapcast metadata has artists as arrays
+fn join_names(value: &Value) -> String {
+    match value {
+        Value::Array(names) => names
+            .iter()
+            .filter_map(|name| name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", "),
+        Value::String(name) => name.clone(),
+        _ => String::new(),
+    }
+}
+
+impl SnapcastRpc {
+    pub fn new(address: &str) -> SnapcastRpc {
+        SnapcastRpc {
+            address: address.to_string(),
+        }
+    }
+
+    // Send request and wait for the reply with the same id (notifications are skipped)
+    fn request(&self, method: &str, params: Value) -> Result<Value, Box<dyn std::error::Error>> {
+        let stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+
+        let request = json!({ "id": 1, "jsonrpc": "2.0", "method": method, "params": params });
+        (&stream).write_all(format!("{}\r\n", request).as_bytes())?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err("Snapcast server closed the connection".into());
+            }
+            let reply: Value = match serde_json::from_str(&line) {
+                Ok(reply) => reply,
+                Err(_) => continue,
+            };
+            if reply["id"].as_u64() != Some(1) {
+                continue;
+            }
+            if !reply["error"].is_null() {
+                return Err(format!("Snapcast error: {}", reply["error"]["message"]).into());
+            }
+            return Ok(reply["result"].clone());
+        }
+    }
+
+    fn get_streams(&self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let status = self.request("Server.GetStatus", json!({}))?;
+        Ok(status["server"]["streams"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    // Playing stream with metadata, or any stream with metadata
+    fn get_stream(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        let streams: Vec<Value> = self
+            .get_streams()?
+            .into_iter()
+            .filter(|stream| stream["properties"]["metadata"]["title"].is_string())
+            .collect();
+        streams
+            .iter()
+            .find(|stream| stream["status"].as_str() == Some("playing"))
+            .or(streams.first())
+            .cloned()
+            .ok_or_else(|| "No Snapcast stream with metadata.".into())
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.get_stream().is_ok()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self.get_stream() {
+            Ok(stream) => stream["status"].as_str() == Some("playing"),
+            Err(_) => false,
+        }
+    }
+
+    pub fn send_command(&self, command: PlayerCommand) -> Result<(), Box<dyn std::error::Error>> {
+        let stream = self.get_stream()?;
+        if stream["properties"]["canControl"].as_bool() == Some(false) {
+            return Err("this Snapcast stream can't be controlled".into());
+        }
+        let command = match command {
+            PlayerCommand::Play => "play",
+            PlayerCommand::Pause => "pause",
+            PlayerCommand::PlayPause => "playPause",
+            PlayerCommand::Next => "next",
+            PlayerCommand::Previous => "previous",
+        };
+        self.request(
+            "Stream.Control",
+            json!({ "id": stream["id"], "command": command }),
+        )?;
+        Ok(())
+    }
+
+    pub fn get_currently_playing(
+        &self,
+        debug_log: bool,
+    ) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+        let stream = match self.get_stream() {
+            Ok(stream) => stream,
+            Err(err) => {
+                return Err(format!(
+                    "Could not get stream from Snapcast server {}: {}",
+                    self.address, err
+                )
+                .into())
+            }
+        };
+        debug_log!(debug_log, "Snapcast stream: {:#?}", stream);
+
+        let properties = &stream["properties"];
+        let metadata = &properties["metadata"];
+
+        let mut title = metadata["title"].as_str().unwrap_or_default().to_string();
+        if title.is_empty() {
+            title = "Unknown Title".to_string();
+        }
+        let mut artist = join_names(&metadata["artist"]);
+        if artist.is_empty() {
+            artist = "Unknown Artist".to_string();
+        }
+        let mut album_artist = join_names(&metadata["albumArtist"]);
+        if album_artist.is_empty() {
+            album_artist = artist.clone();
+        }
+        let mut album = metadata["album"].as_str().unwrap_or_default().to_string();
+        if album.is_empty() {
+            album = "Unknown Album".to_string();
+        }
+
+        let duration = metadata["duration"].as_f64();
+        let position = properties["position"].as_f64();
+        let is_playing = match properties["playbackStatus"].as_str() {
+            Some(status) => status == "playing",
+            None => stream["status"].as_str() == Some("playing"),
+        };
+
+        Ok(MediaInfo {
+            title,
+            artist,
+            album_artist,
+            album,
+            is_playing,
+            is_stopped: properties["playbackStatus"].as_str() == Some("stopped"),
+            duration: duration.unwrap_or(0.0) as u64,
+            position: position.unwrap_or(0.0) as u64,
+            is_track_position: position.is_some(),
+            art_url: metadata["artUrl"].as_str().unwrap_or_default().to_string(),
+            art_data: ArtData::default(),
+            url: metadata["url"].as_str().unwrap_or_default().to_string(),
+            track_id: String::new(),
+            rating: None,
+            #[cfg(target_os = "macos")]
+            player_id: "Snapcast".to_string(),
+        })
+    }
+}
@@ -0,0 +1,320 @@
+#[cfg(target_os = "linux")]
+use mpris::{FindingError, Player};
+
+use crate::browser_extension::BrowserExtension;
+use crate::control::{ControlTarget, PlayerCommand};
+use crate::debug_log;
+use crate::mpv::MpvIpc;
+use crate::pipewire::PipeWireStreams;
+use crate::player_match;
+use crate::settings::Cli;
+use crate::snapcast::SnapcastRpc;
+use crate::utils::{self, NowPlayingResult};
+use crate::vlc::VlcHttp;
+
+// Optional backends used when a player has no (working) MPRIS support
+#[derive(Debug, Clone)]
+pub enum Fallback {
+    Mpv(MpvIpc),
+    Vlc(VlcHttp),
+    Snapcast(SnapcastRpc),
+    PipeWire(PipeWireStreams),
+    BrowserExtension(BrowserExtension),
+}
+
+impl Fallback {
+    // Name used in allowlist and video_players, same as MPRIS identity of these players
+    pub fn identity(&self) -> &'static str {
+        match self {
+            Fallback::Mpv(_) => "mpv",
+            Fallback::Vlc(_) => "VLC media player",
+            Fallback::Snapcast(_) => "Snapcast",
+            Fallback::PipeWire(_) => "PipeWire",
+            Fallback::BrowserExtension(_) => "Browser extension",
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Fallback::Mpv(mpv) => format!("JSON IPC socket: {}", mpv.socket_path.display()),
+            Fallback::Vlc(vlc) => format!("HTTP interface: {}", vlc.url),
+            Fallback::Snapcast(snapcast) => format!("JSON-RPC: {}", snapcast.address),
+            Fallback::PipeWire(_) => String::from("audio streams from pw-dump"),
+            Fallback::BrowserExtension(extension) => {
+                format!("HTTP endpoint: http://{}/now-playing", extension.address)
+            }
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        match self {
+            Fallback::Mpv(mpv) => mpv.is_available(),
+            Fallback::Vlc(vlc) => vlc.is_available(),
+            Fallback::Snapcast(snapcast) => snapcast.is_available(),
+            Fallback::PipeWire(pipewire) => pipewire.is_available(),
+            Fallback::BrowserExtension(extension) => extension.is_available(),
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self {
+            Fallback::Mpv(mpv) => mpv.is_playing(),
+            Fallback::Vlc(vlc) => vlc.is_playing(),
+            Fallback::Snapcast(snapcast) => snapcast.is_playing(),
+            Fallback::PipeWire(pipewire) => pipewire.is_playing(),
+            Fallback::BrowserExtension(extension) => extension.is_playing(),
+        }
+    }
+
+    pub fn get_currently_playing(&self, debug_log: bool) -> NowPlayingResult {
+        match self {
+            Fallback::Mpv(mpv) => mpv.get_currently_playing(debug_log),
+            Fallback::Vlc(vlc) => vlc.get_currently_playing(debug_log),
+            Fallback::Snapcast(snapcast) => snapcast.get_currently_playing(debug_log),
+            Fallback::PipeWire(pipewire) => pipewire.get_currently_playing(debug_log),
+            Fallback::BrowserExtension(extension) => extension.get_currently_playing(debug_log),
+        }
+    }
+
+    pub fn send_command(&self, command: PlayerCommand) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Fallback::Mpv(mpv) => mpv.send_command(command),
+            Fallback::Vlc(vlc) => vlc.send_command(command),
+            Fallback::Snapcast(snapcast) => snapcast.send_command(command),
+            Fallback::PipeWire(_) => Err("PipeWire streams can't be controlled".into()),
+            Fallback::BrowserExtension(_) => {
+                Err("Browser extension players can't be controlled".into())
+            }
+        }
+    }
+
+    // Metadata sent by the browser extension is better than the one of the browser MPRIS
+    // player, so it's used even when an MPRIS player is playing
+    fn is_preferred(&self) -> bool {
+        matches!(self, Fallback::BrowserExtension(_))
+    }
+}
+
+// Create fallback backends enabled in settings
+pub fn get_fallbacks(settings: &Cli) -> Vec<Fallback> {
+    let mut fallbacks = Vec::new();
+
+    if let Some(socket_path) = &settings.mpv_ipc_socket {
+        fallbacks.push(Fallback::Mpv(MpvIpc::new(socket_path)));
+    }
+
+    if let Some(url) = &settings.vlc_http_url {
+        let password = settings.vlc_http_password.clone().unwrap_or_default();
+        // Output of --json must stay valid JSON
+        if password.is_empty() && !settings.json {
+            println!("\x1b[33;1mINFO: VLC HTTP interface requires a password, set vlc_http_password in config.\x1b[0m");
+        }
+        fallbacks.push(Fallback::Vlc(VlcHttp::new(url, &password)));
+    }
+
+    if let Some(address) = &settings.snapcast_address {
+        fallbacks.push(Fallback::Snapcast(SnapcastRpc::new(address)));
+    }
+
+    if settings.pipewire_streams {
+        fallbacks.push(Fallback::PipeWire(PipeWireStreams::new()));
+    }
+
+    // Commands that exit right away would only block the port of the running instance
+    let is_quick_exit =
+        settings.list_players || settings.get_player_id || settings.suboptions.command.is_some();
+    if let (Some(address), false) = (&settings.browser_extension_address, is_quick_exit) {
+        fallbacks.push(Fallback::BrowserExtension(BrowserExtension::new(
+            address,
+            settings.debug_log,
+        )));
+    }
+
+    fallbacks
+}
+
+fn is_allowed(name: &str, allowlist: &[String]) -> bool {
+    allowlist.is_empty() || player_match::is_listed(allowlist, &[name])
+}
+
+// Playing fallback that takes priority over the primary source
+fn select_preferred(
+    fallbacks: &[Fallback],
+    allowlist: &[String],
+    debug_log: bool,
+) -> Option<Fallback> {
+    let selected = fallbacks.iter().find(|fallback| {
+        fallback.is_preferred()
+            && is_allowed(fallback.identity(), allowlist)
+            && fallback.is_playing()
+    })?;
+    debug_log!(
+        debug_log,
+        "Selected preferred source: {} ({})",
+        selected.identity(),
+        selected.description()
+    );
+    Some(selected.clone())
+}
+
+// First allowed fallback that is playing, or if nothing else is available, any allowed fallback
+fn select_fallback(
+    fallbacks: &[Fallback],
+    allowlist: &[String],
+    primary_available: bool,
+    debug_log: bool,
+) -> Option<Fallback> {
+    let available: Vec<&Fallback> = fallbacks
+        .iter()
+        .filter(|fallback| is_allowed(fallback.identity(), allowlist) && fallback.is_available())
+        .collect();
+
+    let selected = match available.iter().find(|fallback| fallback.is_playing()) {
+        Some(fallback) => Some(*fallback),
+        None if !primary_available => available.first().copied(),
+        None => None,
+    };
+
+    if let Some(fallback) = selected {
+        debug_log!(
+            debug_log,
+            "Selected fallback source: {} ({})",
+            fallback.identity(),
+            fallback.description()
+        );
+    }
+
+    selected.cloned()
+}
+
+// Player from which the status is taken, MPRIS or one of the fallback backends
+#[cfg(target_os = "linux")]
+pub enum Source {
+    Mpris(Player),
+    Fallback(Fallback),
+}
+
+#[cfg(target_os = "linux")]
+impl Source {
+    pub fn identity(&self) -> String {
+        match self {
+            Source::Mpris(player) => player.identity().to_string(),
+            Source::Fallback(fallback) => fallback.identity().to_string(),
+        }
+    }
+
+    // Source of metadata shown in the log of published activity
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Source::Mpris(_) => "mpris",
+            Source::Fallback(fallback) => fallback.identity(),
+        }
+    }
+
+    // Names matched with player lists, see utils::get_player_names
+    pub fn names(&self, dbus_address: Option<&str>) -> Vec<String> {
+        match self {
+            Source::Mpris(player) => utils::get_player_names(player, dbus_address),
+            Source::Fallback(fallback) => vec![fallback.identity().to_string()],
+        }
+    }
+
+    // D-Bus name of MPRIS player, different for every instance of the same player
+    pub fn bus_name(&self) -> Option<&str> {
+        match self {
+            Source::Mpris(player) => Some(player.bus_name()),
+            Source::Fallback(_) => None,
+        }
+    }
+
+    // Used to detect player change between loop iterations. Bus name is compared to also detect
+    // switching between instances of the same player (e.g. two browser windows), identity because
+    // it can change after the player was selected (e.g. playerctld proxying another player).
+    pub fn is_same(&self, other: &Source) -> bool {
+        match (self, other) {
+            (Source::Mpris(a), Source::Mpris(b)) => {
+                a.bus_name() == b.bus_name() && a.identity() == b.identity()
+            }
+            (Source::Fallback(a), Source::Fallback(b)) => a.identity() == b.identity(),
+            _ => false,
+        }
+    }
+
+    pub fn get_currently_playing(&self, debug_log: bool) -> NowPlayingResult {
+        match self {
+            Source::Mpris(player) => utils::get_currently_playing(player, debug_log),
+            Source::Fallback(fallback) => fallback.get_currently_playing(debug_log),
+        }
+    }
+
+    // Where playback commands from the control socket are sent
+    pub fn control_target(&self) -> ControlTarget {
+        match self {
+            Source::Mpris(player) => ControlTarget::Mpris(player.bus_name().to_string()),
+            Source::Fallback(fallback) => ControlTarget::Fallback(fallback.clone()),
+        }
+    }
+}
+
+// Choose between the MPRIS player and fallback backends.
+// Priority: playing browser extension > playing MPRIS player > playing fallback > any MPRIS player
+// > any fallback
+#[cfg(target_os = "linux")]
+pub fn select_source(
+    mpris_player: Result<Player, FindingError>,
+    fallbacks: &[Fallback],
+    allowlist: &[String],
+    debug_log: bool,
+) -> Option<Source> {
+    if let Some(fallback) = select_preferred(fallbacks, allowlist, debug_log) {
+        return Some(Source::Fallback(fallback));
+    }
+
+    let mpris_player = mpris_player.ok();
+    let mpris_playing = match &mpris_player {
+        Some(player) => {
+            player
+                .get_playback_status()
+                .unwrap_or(mpris::PlaybackStatus::Stopped)
+                == mpris::PlaybackStatus::Playing
+        }
+        None => false,
+    };
+
+    if mpris_playing || fallbacks.is_empty() {
+        return mpris_player.map(Source::Mpris);
+    }
+
+    match select_fallback(fallbacks, allowlist, mpris_player.is_some(), debug_log) {
+        Some(fallback) => Some(Source::Fallback(fallback)),
+        None => mpris_player.map(Source::Mpris),
+    }
+}
+
+// On macOS media-control is the primary source, fallback backends are used when it has nothing playing
+// (except the browser extension, which is used whenever it's playing)
+#[cfg(target_os = "macos")]
+pub fn get_currently_playing(
+    fallbacks: &[Fallback],
+    allowlist: &[String],
+    debug_log: bool,
+) -> NowPlayingResult {
+    if let Some(fallback) = select_preferred(fallbacks, allowlist, debug_log) {
+        return fallback.get_currently_playing(debug_log);
+    }
+
+    let media_control = utils::get_currently_playing();
+    let media_control_playing = match &media_control {
+        Ok(info) => info.is_playing,
+        Err(_) => false,
+    };
+
+    if media_control_playing || fallbacks.is_empty() {
+        return media_control;
+    }
+
+    match select_fallback(fallbacks, allowlist, media_control.is_ok(), debug_log) {
+        Some(fallback) => fallback.get_currently_playing(debug_log),
+        None => media_control,
+    }
+}
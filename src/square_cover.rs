@@ -0,0 +1,146 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::time::Duration;
+
+use crate::cover_folder;
+use crate::debug_log;
+use crate::utils;
+
+// Side of the processed image, Discord doesn't show the large image bigger
+const SIZE: u32 = 512;
+
+// Images this close to square are used unchanged
+const SQUARE_TOLERANCE: f32 = 0.02;
+
+// Radius of rounded corners, relative to the side of the image
+const CORNER_RADIUS: f32 = 0.08;
+
+// Player artwork that isn't square (e.g. 16:9 video thumbnail) is cropped by Discord, with
+// square_covers it's made square and uploaded to cover_upload_url instead
+pub struct SquareCovers {
+    pub crop: bool,    // Crop the center instead of adding bars
+    pub rounded: bool, // Rounded corners with transparent background
+    pub upload_url: String,
+}
+
+fn average_color<'a>(pixels: impl Iterator<Item = &'a Rgba<u8>>) -> Rgba<u8> {
+    let mut sum = [0u64; 3];
+    let mut count = 0;
+    for pixel in pixels {
+        for (total, value) in sum.iter_mut().zip(pixel.0) {
+            *total += value as u64;
+        }
+        count += 1;
+    }
+    let channel = |total: u64| (total / count.max(1)) as u8;
+    Rgba([channel(sum[0]), channel(sum[1]), channel(sum[2]), 255])
+}
+
+// Image fitted into a square, bars have the average color of the image edge next to them, so
+// they blend with it instead of the black ones
+fn pad(image: DynamicImage) -> RgbaImage {
+    let image = image.resize(SIZE, SIZE, FilterType::Triangle).to_rgba8();
+    let (width, height) = image.dimensions();
+    let is_wide = width >= height;
+    let (first, second) = match is_wide {
+        true => (
+            average_color((0..width).map(|x| image.get_pixel(x, 0))),
+            average_color((0..width).map(|x| image.get_pixel(x, height - 1))),
+        ),
+        false => (
+            average_color((0..height).map(|y| image.get_pixel(0, y))),
+            average_color((0..height).map(|y| image.get_pixel(width - 1, y))),
+        ),
+    };
+    let mut square = RgbaImage::from_fn(SIZE, SIZE, |x, y| match is_wide {
+        true if y < SIZE / 2 => first,
+        false if x < SIZE / 2 => first,
+        _ => second,
+    });
+    imageops::overlay(
+        &mut square,
+        &image,
+        ((SIZE - width) / 2) as i64,
+        ((SIZE - height) / 2) as i64,
+    );
+    square
+}
+
+// Corners outside of the circles are made transparent, edges are smoothed
+fn round_corners(image: &mut RgbaImage) {
+    let size = image.width() as f32;
+    let radius = size * CORNER_RADIUS;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        // Distance from the center of the nearest corner circle, 0 outside of corners
+        let distance = |position: u32| {
+            let position = position as f32 + 0.5;
+            (radius - position).max(position - (size - radius)).max(0.0)
+        };
+        let (dx, dy) = (distance(x), distance(y));
+        let coverage = (radius - (dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+        pixel.0[3] = (pixel.0[3] as f32 * coverage) as u8;
+    }
+}
+
+// Link of the square image, None if the image is already square
+fn process(
+    url: &str,
+    settings: &SquareCovers,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let data = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(url)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+    let image = image::load_from_memory(&data)?;
+    let (width, height) = image.dimensions();
+    if (width as f32 / height as f32 - 1.0).abs() <= SQUARE_TOLERANCE {
+        return Ok(None);
+    }
+
+    let mut square = match settings.crop {
+        true => image
+            .resize_to_fill(SIZE, SIZE, FilterType::Triangle)
+            .to_rgba8(),
+        false => pad(image),
+    };
+    if settings.rounded {
+        round_corners(&mut square);
+    }
+    let mut png = Vec::new();
+    square.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    // The same artwork gets the same file name
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let file_name = format!("cover-{:016x}.png", hasher.finish());
+    Ok(Some(cover_folder::upload(
+        &settings.upload_url,
+        &file_name,
+        "image/png",
+        png,
+    )?))
+}
+
+// Square version of the artwork, or the original link if it's square or can't be processed
+pub fn get_square_url(url: &str, settings: &SquareCovers, debug_log: bool) -> String {
+    match process(url, settings) {
+        Ok(Some(square_url)) => {
+            debug_log!(debug_log, "[square cover] {} => {}", url, square_url);
+            square_url
+        }
+        Ok(None) => url.to_string(),
+        Err(err) => {
+            debug_log!(debug_log, "[square cover] {} failed: {}", url, err);
+            url.to_string()
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::time::Duration;
+
+use crate::album_cache::normalize_key;
+use crate::apple_music::ARTWORK_SIZE;
+use crate::utils;
+
+// Album covers from iTunes Search API and Deezer, neither needs an API key, so covers work
+// also in builds without Last.fm API key
+
+fn get_json(request_url: &str) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    Ok(Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(request_url)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?)
+}
+
+// Stores add e.g. " - EP" or " (Deluxe Edition)" to names, so only exact matches are trusted
+fn is_same(value: &serde_json::Value, text: &str) -> bool {
+    normalize_key(value.as_str().unwrap_or_default()) == normalize_key(text)
+}
+
+pub fn search_itunes(artist: &str, album: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let data = get_json(&format!(
+        "https://itunes.apple.com/search?media=music&entity=album&limit=10&term={}",
+        url_escape::encode_component(&format!("{} {}", artist, album))
+    ))?;
+    let cover_url = data["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|result| {
+            is_same(&result["artistName"], artist) && is_same(&result["collectionName"], album)
+        })
+        .and_then(|result| result["artworkUrl100"].as_str())
+        .map(|url| url.replace("100x100bb", ARTWORK_SIZE))
+        .unwrap_or_default();
+    Ok(cover_url)
+}
+
+pub fn search_deezer(artist: &str, album: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let data = get_json(&format!(
+        "https://api.deezer.com/search/album?limit=10&q={}",
+        url_escape::encode_component(&format!(
+            "artist:\"{}\" album:\"{}\"",
+            artist.replace('"', ""),
+            album.replace('"', "")
+        ))
+    ))?;
+    let cover_url = data["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|result| {
+            is_same(&result["artist"]["name"], artist) && is_same(&result["title"], album)
+        })
+        .and_then(|result| result["cover_xl"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    Ok(cover_url)
+}
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use crate::debug_log;
+use crate::player_match;
+use crate::processes;
+
+// Used when streaming_processes is empty
+const DEFAULT_PROCESSES: [&str; 1] = ["obs"];
+
+// Running processes and PipeWire are not checked on every refresh, as it's slower than metadata
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Screen casts started with xdg-desktop-portal are PipeWire video sources created by the
+// compositor, unlike cameras they don't belong to any device
+#[cfg(target_os = "linux")]
+fn is_screencast_running() -> bool {
+    let output = match std::process::Command::new("pw-dump").output() {
+        Ok(output) if output.status.success() => output,
+        _ => return false,
+    };
+    let objects: Vec<serde_json::Value> = match serde_json::from_slice(&output.stdout) {
+        Ok(objects) => objects,
+        Err(_) => return false,
+    };
+    objects.iter().any(|object| {
+        let props = &object["info"]["props"];
+        object["type"].as_str() == Some("PipeWire:Interface:Node")
+            && props["media.class"].as_str() == Some("Video/Source")
+            && props["device.id"].is_null()
+            && object["info"]["state"].as_str() == Some("running")
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn is_screencast_running() -> bool {
+    false
+}
+
+// Detects screen sharing or streaming, so activity can be hidden during it
+pub struct StreamingDetector {
+    processes: Vec<String>,
+    last_check: Option<Instant>,
+    active: bool,
+}
+
+impl StreamingDetector {
+    pub fn new(processes: &[String]) -> StreamingDetector {
+        let processes = if processes.is_empty() {
+            DEFAULT_PROCESSES
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        } else {
+            processes.to_vec()
+        };
+        StreamingDetector {
+            processes,
+            last_check: None,
+            active: false,
+        }
+    }
+
+    pub fn is_active(&mut self, debug_log: bool) -> bool {
+        if self
+            .last_check
+            .is_some_and(|last_check| last_check.elapsed() < CHECK_INTERVAL)
+        {
+            return self.active;
+        }
+        self.last_check = Some(Instant::now());
+
+        let names = processes::get_process_names();
+        self.active = if let Some(name) = names
+            .iter()
+            .find(|name| player_match::is_listed(&self.processes, &[name]))
+        {
+            debug_log!(debug_log, "Streaming process running: {}", name);
+            true
+        } else if is_screencast_running() {
+            debug_log!(debug_log, "Screen cast running.");
+            true
+        } else {
+            false
+        };
+        self.active
+    }
+}
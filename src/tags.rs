@@ -0,0 +1,81 @@
+#[cfg(feature = "local-tags")]
+use lofty::file::TaggedFileExt;
+#[cfg(feature = "local-tags")]
+use lofty::tag::ItemKey;
+
+#[cfg(feature = "local-tags")]
+use crate::debug_log;
+
+// Release details read from tags of local files, empty when unknown
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagInfo {
+    pub year: String,
+    pub label: String,
+    pub catalog: String,
+}
+
+impl TagInfo {
+    // "2003, Warp Records" added to the default album hover text
+    pub fn summary(&self) -> String {
+        [self.year.as_str(), self.label.as_str()]
+            .iter()
+            .filter(|value| !value.is_empty())
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(", ")
+    }
+}
+
+// Tags store full dates ("2003-10-13") or only years
+#[cfg(feature = "local-tags")]
+fn parse_year(date: &str) -> String {
+    let year: String = date.trim().chars().take(4).collect();
+    match year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) {
+        true => year,
+        false => String::new(),
+    }
+}
+
+// Tags of the track if the player plays a local file (xesam:url is a file:// link)
+#[cfg(feature = "local-tags")]
+pub fn read(url: &str, debug_log: bool) -> TagInfo {
+    let Some(path) = url.strip_prefix("file://") else {
+        return TagInfo::default();
+    };
+    let path = url_escape::decode(path).to_string();
+
+    let tagged_file = match lofty::read_from_path(&path) {
+        Ok(tagged_file) => tagged_file,
+        Err(err) => {
+            debug_log!(debug_log, "[tags] could not read {}: {}", path, err);
+            return TagInfo::default();
+        }
+    };
+    let Some(tag) = tagged_file.primary_tag().or(tagged_file.first_tag()) else {
+        return TagInfo::default();
+    };
+
+    let get = |keys: &[ItemKey]| {
+        keys.iter()
+            .find_map(|key| tag.get_string(key))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let info = TagInfo {
+        year: parse_year(&get(&[
+            ItemKey::RecordingDate,
+            ItemKey::OriginalReleaseDate,
+        ])),
+        label: get(&[ItemKey::Label, ItemKey::Publisher]),
+        catalog: get(&[ItemKey::CatalogNumber]),
+    };
+    debug_log!(debug_log, "[tags] {}: {:?}", path, info);
+    info
+}
+
+// Built without the "local-tags" feature, tags are never read
+#[cfg(not(feature = "local-tags"))]
+pub fn read(_url: &str, _debug_log: bool) -> TagInfo {
+    TagInfo::default()
+}
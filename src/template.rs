@@ -0,0 +1,58 @@
+// Replace {name} placeholders with values. Parts in [square brackets] are removed when any
+// placeholder inside them is empty, e.g. "{album}[ ({year})]" => "Album" if year is unknown.
+// Unknown placeholders are left unchanged.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut result = String::new();
+    let mut optional = String::new();
+    let mut in_optional = false;
+    let mut optional_empty = false;
+
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if !in_optional => {
+                in_optional = true;
+                optional_empty = false;
+                optional.clear();
+            }
+            ']' if in_optional => {
+                in_optional = false;
+                if !optional_empty {
+                    result.push_str(&optional);
+                }
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let target = if in_optional {
+                    &mut optional
+                } else {
+                    &mut result
+                };
+                match values.iter().find(|(key, _)| *key == name) {
+                    Some((_, value)) => {
+                        if value.is_empty() {
+                            optional_empty = true;
+                        }
+                        target.push_str(value);
+                    }
+                    None => target.push_str(&format!("{{{}}}", name)),
+                }
+            }
+            _ => {
+                if in_optional {
+                    optional.push(c);
+                } else {
+                    result.push(c);
+                }
+            }
+        }
+    }
+
+    // Not closed bracket is a normal text
+    if in_optional {
+        result.push('[');
+        result.push_str(&optional);
+    }
+
+    result.trim().to_string()
+}
@@ -0,0 +1,102 @@
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::debug_log;
+use crate::utils;
+
+// Artist photo and short biography
+#[derive(Debug, Clone, Default)]
+pub struct ArtistInfo {
+    pub thumb: String,
+    pub bio: String,
+}
+
+fn request(
+    api_key: &str,
+    endpoint: &str,
+    query: &[(&str, &str)],
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let query: Vec<String> = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, url_escape::encode_component(value)))
+        .collect();
+    let request_url = format!(
+        "https://www.theaudiodb.com/api/v1/json/{}/{}?{}",
+        url_escape::encode_component(api_key),
+        endpoint,
+        query.join("&")
+    );
+    Ok(Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?
+        .get(request_url)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?)
+}
+
+// Album cover link, empty if album was not found or has no cover
+pub fn get_album_cover(
+    api_key: &str,
+    artist: &str,
+    album: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let data = request(api_key, "searchalbum.php", &[("s", artist), ("a", album)])?;
+    Ok(data["album"][0]["strAlbumThumb"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+// First sentence of the English biography, the full one is too long for activity
+fn short_bio(bio: &str) -> String {
+    let bio = bio.trim();
+    let end = [bio.find(". ").map(|index| index + 1), bio.find('\n')]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(bio.len());
+    bio[..end].trim().to_string()
+}
+
+pub fn get_artist_info(
+    api_key: &str,
+    artist: &str,
+) -> Result<ArtistInfo, Box<dyn std::error::Error>> {
+    let data = request(api_key, "search.php", &[("s", artist)])?;
+    let artist = &data["artists"][0];
+    Ok(ArtistInfo {
+        thumb: artist["strArtistThumb"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        bio: short_bio(artist["strBiographyEN"].as_str().unwrap_or_default()),
+    })
+}
+
+// Artists are looked up once per run, not found ones are remembered too
+pub fn get_artist_info_cached(
+    artist_info: &mut HashMap<String, ArtistInfo>,
+    api_key: &str,
+    artist: &str,
+    debug_log: bool,
+) -> ArtistInfo {
+    if let Some(info) = artist_info.get(artist) {
+        return info.clone();
+    }
+    match get_artist_info(api_key, artist) {
+        Ok(info) => {
+            debug_log!(debug_log, "[theaudiodb] artist info: {:?}", info);
+            artist_info.insert(artist.to_string(), info.clone());
+            info
+        }
+        // Network errors are not remembered, so it will be checked again next time
+        Err(err) => {
+            debug_log!(debug_log, "[theaudiodb] could not get artist info: {}", err);
+            ArtistInfo::default()
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use aws_lc_rs::digest;
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use crate::utils;
+
+const RELEASES_URL: &str =
+    "https://api.github.com/repos/patryk-ku/music-discord-rpc/releases/latest";
+
+// File with SHA-256 checksums of all release assets, in "sha256sum" format
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+struct Release {
+    version: String,
+    assets: Vec<(String, String)>, // (name, download url)
+}
+
+impl Release {
+    fn asset_url(&self, name: &str) -> Option<&str> {
+        self.assets
+            .iter()
+            .find(|(asset_name, _)| asset_name == name)
+            .map(|(_, url)| url.as_str())
+    }
+}
+
+fn client() -> Result<Client, Box<dyn std::error::Error>> {
+    Ok(Client::builder().timeout(Duration::from_secs(60)).build()?)
+}
+
+fn get_latest_release() -> Result<Release, Box<dyn std::error::Error>> {
+    let data = client()?
+        .get(RELEASES_URL)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .json::<serde_json::Value>()?;
+
+    let version = data["tag_name"]
+        .as_str()
+        .ok_or("release has no tag")?
+        .trim_start_matches('v')
+        .to_string();
+    let assets = data["assets"]
+        .as_array()
+        .map(|assets| {
+            assets
+                .iter()
+                .filter_map(|asset| {
+                    Some((
+                        asset["name"].as_str()?.to_string(),
+                        asset["browser_download_url"].as_str()?.to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Release { version, assets })
+}
+
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let data = client()?
+        .get(url)
+        .header(USER_AGENT, format!("music-discord-rpc/{}", utils::VERSION))
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+    Ok(data.to_vec())
+}
+
+// "0.10.1" > "0.9.3", missing parts are 0
+fn is_newer(version: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    let (version, current) = (parse(version), parse(current));
+    for i in 0..version.len().max(current.len()) {
+        let (a, b) = (
+            version.get(i).copied().unwrap_or(0),
+            current.get(i).copied().unwrap_or(0),
+        );
+        if a != b {
+            return a > b;
+        }
+    }
+    false
+}
+
+// Name of prebuilt binary for this platform, macOS builds are installed with Homebrew
+fn get_asset_name() -> Option<&'static str> {
+    match (env::consts::OS, env::consts::ARCH) {
+        ("linux", "x86_64") => Some("music-discord-rpc"),
+        _ => None,
+    }
+}
+
+// Binaries installed by package managers must be updated by them
+fn check_install_location(exe: &Path) -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        return Err(String::from(
+            "On macOS update with Homebrew: brew upgrade music-discord-rpc",
+        ));
+    }
+    if env::var_os("APPIMAGE").is_some() {
+        return Err(String::from(
+            "Running as AppImage, download the new AppImage from the releases page.",
+        ));
+    }
+    if exe.starts_with("/usr") {
+        return Err(format!(
+            "{} is managed by the package manager, use it to update.",
+            exe.display()
+        ));
+    }
+    Ok(())
+}
+
+fn verify_checksum(
+    release: &Release,
+    asset_name: &str,
+    data: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let checksums_url = release
+        .asset_url(CHECKSUMS_ASSET)
+        .ok_or("release has no checksums file, can't verify the download")?;
+    let checksums = String::from_utf8(download(checksums_url)?)?;
+    let expected = checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim().trim_start_matches('*') == asset_name)
+        .map(|(checksum, _)| checksum.to_lowercase())
+        .ok_or("no checksum for the binary in checksums file")?;
+
+    let actual: String = digest::digest(&digest::SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if actual != expected {
+        return Err(format!("checksum mismatch, expected {}, got {}", expected, actual).into());
+    }
+    Ok(())
+}
+
+// New binary is written next to the old one and renamed over it, so it's never half written
+fn replace_executable(exe: &Path, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let new_exe = exe.with_extension("new");
+    fs::write(&new_exe, data)?;
+    fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))?;
+    if let Err(err) = fs::rename(&new_exe, exe) {
+        let _ = fs::remove_file(&new_exe);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+fn exit_with_error(message: &str) -> ! {
+    println!("\x1b[31m{}\x1b[0m", message);
+    process::exit(1);
+}
+
+// Used by "update" subcommand
+pub fn run(check_only: bool) {
+    println!("Current version: {}", utils::VERSION);
+    let release = get_latest_release()
+        .unwrap_or_else(|err| exit_with_error(&format!("Could not check for updates: {}", err)));
+
+    if !is_newer(&release.version, utils::VERSION) {
+        println!("You are using the latest version.");
+        process::exit(0);
+    }
+    println!("New version available: {}", release.version);
+    if check_only {
+        process::exit(0);
+    }
+
+    let exe = env::current_exe()
+        .and_then(fs::canonicalize)
+        .unwrap_or_else(|err| exit_with_error(&format!("Could not find executable: {}", err)));
+    if let Err(message) = check_install_location(&exe) {
+        exit_with_error(&message);
+    }
+
+    let asset_name = get_asset_name().unwrap_or_else(|| {
+        exit_with_error(&format!(
+            "There is no prebuilt binary for {} {}, compile it from source.",
+            env::consts::OS,
+            env::consts::ARCH
+        ))
+    });
+    let asset_url = release
+        .asset_url(asset_name)
+        .unwrap_or_else(|| exit_with_error(&format!("Release has no {} file.", asset_name)));
+
+    println!("Downloading: {}", asset_url);
+    let data = download(asset_url)
+        .unwrap_or_else(|err| exit_with_error(&format!("Download failed: {}", err)));
+    if let Err(err) = verify_checksum(&release, asset_name, &data) {
+        exit_with_error(&format!("Verification failed: {}", err));
+    }
+    println!("Checksum verified.");
+
+    if let Err(err) = replace_executable(&exe, &data) {
+        exit_with_error(&format!("Could not replace {}: {}", exe.display(), err));
+    }
+    println!("Updated {} to version {}.", exe.display(), release.version);
+    println!("Restart the running instance to use it: music-discord-rpc restart");
+    process::exit(0);
+}
@@ -1,14 +1,31 @@
-use discord_rich_presence::{DiscordIpc, DiscordIpcClient};
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
 use pickledb::PickleDb;
 use reqwest;
 use reqwest::blocking::Client;
 use reqwest::header::USER_AGENT;
 use serde_json;
 use std::env;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
 use url_escape;
 
+use crate::control;
+use crate::discord_clients::DiscordClients;
+use crate::media_state;
+use crate::metrics;
+
+#[cfg(target_os = "linux")]
+use crate::player_match;
+#[cfg(target_os = "linux")]
+use mpris::{FindingError, Player, PlayerFinder, TrackID};
 #[cfg(target_os = "linux")]
-use mpris::{FindingError, Player, PlayerFinder};
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
 #[cfg(target_os = "linux")]
 use std::time::Duration;
 #[cfg(target_os = "linux")]
@@ -16,26 +33,73 @@ use std::{fs, process};
 
 // A common struct to hold song information, ensuring a consistent
 // return type regardless of the platform.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MediaInfo {
     pub title: String,
     pub artist: String,
     pub album_artist: String,
     pub album: String,
     pub is_playing: bool,
+    pub is_stopped: bool, // Player reports stopped (not only paused) but may keep the last track
     pub duration: u64,
     pub position: u64,
     pub is_track_position: bool,
-    pub art_url: String, // Link to cover art on the internet
-    pub url: String,     // Link to the currently playing media on the internet
+    pub art_url: String,     // Link to cover art on the internet
+    pub art_data: ArtData,   // Raw cover image provided directly by the player
+    pub url: String,         // Link to the currently playing media on the internet
+    pub track_id: String,    // MPRIS track id, changes with every track even with the same metadata
+    pub rating: Option<f64>, // Track rating from 0.0 to 1.0, if provided by the player
     #[cfg(target_os = "macos")]
     pub player_id: String,
 }
 
+// Raw image bytes, wrapped to keep debug log readable
+#[derive(Default, Clone)]
+pub struct ArtData(pub Vec<u8>);
+
+impl std::fmt::Debug for ArtData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ArtData({} bytes)", self.0.len())
+    }
+}
+
+// Image embedded in the link: "data:image/jpeg;base64,<data>", used by some browsers and streaming apps
+pub fn decode_data_url(url: &str) -> Option<Vec<u8>> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    if !header.ends_with(";base64") {
+        return None;
+    }
+    // Some players wrap long data or encode it for URL
+    decode_base64(&url_escape::decode(data))
+}
+
+// Base64 image data, with or without padding and line breaks
+pub fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    let engine = GeneralPurpose::new(
+        &alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+    engine.decode(data).ok()
+}
+
 // Use a Result to handle potential errors, like no media playing.
-type NowPlayingResult = Result<MediaInfo, Box<dyn std::error::Error>>;
+pub type NowPlayingResult = Result<MediaInfo, Box<dyn std::error::Error>>;
+
+// Run function and return panic message as error instead of crashing the whole daemon
+pub fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(panic::AssertUnwindSafe(f)).map_err(|payload| {
+        if let Some(msg) = payload.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            String::from("unknown panic")
+        }
+    })
+}
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 // Use to print debug log if enabled with argument
 #[macro_export]
@@ -157,6 +221,51 @@ pub fn get_config_path() -> Option<std::path::PathBuf> {
     }
 }
 
+pub fn get_cache_path() -> Option<std::path::PathBuf> {
+    if let Some(cache_home) = env::var_os("XDG_CACHE_HOME") {
+        Some(std::path::PathBuf::from(cache_home))
+    } else if let Some(home_dir) = env::var_os("HOME") {
+        let mut path = std::path::PathBuf::from(home_dir);
+        path.push(".cache");
+        Some(path)
+    } else {
+        None
+    }
+}
+
+// Portable mode, set from --portable or MDRPC_PORTABLE=1 before anything is read from disk
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_portable(portable: bool) {
+    let portable = portable || env::var("MDRPC_PORTABLE").is_ok_and(|value| value == "1");
+    PORTABLE.store(portable, Ordering::Relaxed);
+}
+
+pub fn is_portable() -> bool {
+    PORTABLE.load(Ordering::Relaxed)
+}
+
+// In portable mode config and cache are stored next to the executable, e.g. on a USB stick
+fn get_portable_path() -> Option<std::path::PathBuf> {
+    let exe = env::current_exe().ok()?;
+    Some(exe.parent()?.join("music-discord-rpc-data"))
+}
+
+// Directory with config.yaml and script.rhai
+pub fn get_app_config_dir() -> Option<std::path::PathBuf> {
+    if is_portable() {
+        return get_portable_path();
+    }
+    Some(get_config_path()?.join("music-discord-rpc"))
+}
+
+pub fn get_app_cache_dir() -> Option<std::path::PathBuf> {
+    if is_portable() {
+        return Some(get_portable_path()?.join("cache"));
+    }
+    Some(get_cache_path()?.join("music-discord-rpc"))
+}
+
 #[cfg(target_os = "linux")]
 pub fn add_xdg_autostart() {
     let mut desktopt_file_path = match get_config_path() {
@@ -222,12 +331,14 @@ pub fn remove_xdg_autostart() {
     process::exit(0);
 }
 
-pub fn clear_activity(is_activity_set: &mut bool, client: &mut DiscordIpcClient) {
+pub fn clear_activity(is_activity_set: &mut bool, client: &mut DiscordClients) {
     if *is_activity_set {
         let is_activity_cleared = client.clear_activity().is_ok();
 
         if is_activity_cleared {
             *is_activity_set = false;
+            media_state::clear_presence();
+            control::publish_event("activity", "Cleared activity");
             return;
         }
 
@@ -238,6 +349,8 @@ pub fn clear_activity(is_activity_set: &mut bool, client: &mut DiscordIpcClient)
 
         if client.clear_activity().is_ok() {
             *is_activity_set = false;
+            media_state::clear_presence();
+            control::publish_event("activity", "Cleared activity");
         }
     }
 }
@@ -270,6 +383,7 @@ pub fn get_cover_url(
         };
 
         if (!cache_url.is_empty()) && (cache_url.len() > 5) {
+            metrics::inc(&metrics::CACHE_HITS);
             return String::from(cache_url);
         }
     }
@@ -293,6 +407,7 @@ pub fn get_cover_url(
         url.pop();
         url.remove(0);
         println!("[last.fm] fetched image link: {}", url);
+        metrics::inc(&metrics::LASTFM_HITS);
 
         // Save cover url to cache
         if cache_enabled {
@@ -309,9 +424,11 @@ pub fn get_cover_url(
         return url;
     }
 
+    metrics::inc(&metrics::LASTFM_MISSES);
     return String::from("missing-cover");
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn get_cover_url_musicbrainz(
     album_id: &str,
     album: &str,
@@ -319,6 +436,8 @@ pub fn get_cover_url_musicbrainz(
     cache_enabled: bool,
     album_cache: &mut PickleDb,
     artist: &str,
+    verify_track: Option<&str>,
+    is_cancelled: &dyn Fn() -> bool,
 ) -> String {
     // If no album or Unknown Album
     if album.eq("Unknown Album") {
@@ -339,6 +458,7 @@ pub fn get_cover_url_musicbrainz(
         };
 
         if (!cache_url.is_empty()) && (cache_url.len() > 5) {
+            metrics::inc(&metrics::CACHE_HITS);
             return String::from(cache_url);
         }
     }
@@ -348,28 +468,45 @@ pub fn get_cover_url_musicbrainz(
         VERSION
     );
 
+    // Check more releases if the one containing current track has to be found
+    let limit = if verify_track.is_some() { 5 } else { 1 };
     let request_url = format!(
-    	"https://musicbrainz.org/ws/2/release/?query=artist:\"{}\"ANDrelease:\"{}\"&fmt=json&limit=1",
+    	"https://musicbrainz.org/ws/2/release/?query=artist:\"{}\"%20AND%20release:\"{}\"&fmt=json&limit={}",
     	url_escape::encode_component(artist),
-     	url_escape::encode_component(album)
+     	url_escape::encode_component(album),
+        limit
     );
 
     let client = Client::new();
-    let mut mbid: String = match client
+    let release_ids: Vec<String> = match client
         .get(request_url)
         .header(USER_AGENT, &user_agent)
         .send()
     {
         Ok(res) => match res.json::<serde_json::Value>() {
-            Ok(data) => data["releases"][0]["id"].to_string(),
-            Err(_) => String::new(),
+            Ok(data) => data["releases"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|release| release["id"].as_str().map(String::from))
+                .collect(),
+            Err(_) => Vec::new(),
         },
-        Err(_) => String::new(),
+        Err(_) => Vec::new(),
     };
 
-    if !mbid.is_empty() && (mbid.len() > 5) {
-        mbid.pop();
-        mbid.remove(0);
+    // Track lists are fetched one by one, stop when the track changed and the result won't be used
+    let mbid = match verify_track {
+        Some(track) => release_ids
+            .into_iter()
+            .take_while(|_| !is_cancelled())
+            .find(|id| musicbrainz_release_contains_track(&client, &user_agent, id, track))
+            .unwrap_or_default(),
+        None => release_ids.into_iter().next().unwrap_or_default(),
+    };
+    if mbid.is_empty() {
+        metrics::inc(&metrics::MUSICBRAINZ_MISSES);
+        return String::from("missing-cover");
     }
 
     let mut url: String = match client
@@ -388,6 +525,7 @@ pub fn get_cover_url_musicbrainz(
         url.pop();
         url.remove(0);
         println!("[musicbrainz] fetched image link: {}", url);
+        metrics::inc(&metrics::MUSICBRAINZ_HITS);
 
         // Save cover url to cache
         if cache_enabled {
@@ -404,9 +542,301 @@ pub fn get_cover_url_musicbrainz(
         return url;
     }
 
+    metrics::inc(&metrics::MUSICBRAINZ_MISSES);
     return String::from("missing-cover");
 }
 
+// Fetch release track list and check if it contains given track
+fn musicbrainz_release_contains_track(
+    client: &Client,
+    user_agent: &str,
+    release_id: &str,
+    track: &str,
+) -> bool {
+    // MusicBrainz allows 1 request per second
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    let data = match client
+        .get(format!(
+            "https://musicbrainz.org/ws/2/release/{}?inc=recordings&fmt=json",
+            release_id
+        ))
+        .header(USER_AGENT, user_agent)
+        .send()
+    {
+        Ok(res) => res.json::<serde_json::Value>().unwrap_or_default(),
+        Err(_) => return false,
+    };
+
+    let track = track.trim().to_lowercase();
+    data["media"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|media| media["tracks"].as_array().into_iter().flatten())
+        .any(|t| {
+            [&t["title"], &t["recording"]["title"]]
+                .iter()
+                .filter_map(|title| title.as_str())
+                .any(|title| title.trim().to_lowercase() == track)
+        })
+}
+
+// Save cover url to cache
+pub fn save_cover_url(cache_enabled: bool, album_cache: &mut PickleDb, album_id: &str, url: &str) {
+    if cache_enabled {
+        match album_cache.set(album_id, &url) {
+            Ok(_) => println!("[cache] saved image url for: {}.", album_id),
+            Err(_) => println!("[cache] error, unable to write to cache file."),
+        }
+    }
+}
+
+// Search Last.fm albums by title only, used for compilations where artist name is useless
+pub fn get_cover_url_lastfm_album_search(
+    album_id: &str,
+    album: &str,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+    lastfm_api_key: &str,
+) -> String {
+    let request_url = format!(
+        "https://ws.audioscrobbler.com/2.0/?method=album.search&api_key={}&album={}&limit=10&format=json",
+        lastfm_api_key,
+        url_escape::encode_component(album)
+    );
+
+    let data = match reqwest::blocking::get(request_url) {
+        Ok(res) => res.json::<serde_json::Value>().unwrap_or_default(),
+        Err(_) => serde_json::Value::Null,
+    };
+
+    let empty = Vec::new();
+    let url = data["results"]["albummatches"]["album"]
+        .as_array()
+        .unwrap_or(&empty)
+        .iter()
+        .filter(|result| {
+            result["name"]
+                .as_str()
+                .is_some_and(|name| name.to_lowercase() == album.to_lowercase())
+        })
+        .filter_map(|result| result["image"][3]["#text"].as_str())
+        .find(|url| url.len() > 5);
+
+    match url {
+        Some(url) => {
+            println!("[last.fm] fetched image link (album search): {}", url);
+            metrics::inc(&metrics::LASTFM_HITS);
+            save_cover_url(cache_enabled, album_cache, album_id, url);
+            url.to_string()
+        }
+        None => {
+            metrics::inc(&metrics::LASTFM_MISSES);
+            String::from("missing-cover")
+        }
+    }
+}
+
+// Cover of the album containing given track, used for singles
+pub fn get_cover_url_lastfm_track(
+    album_id: &str,
+    artist: &str,
+    title: &str,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+    lastfm_api_key: &str,
+) -> String {
+    let request_url = format!(
+        "https://ws.audioscrobbler.com/2.0/?method=track.getinfo&api_key={}&artist={}&track={}&autocorrect=0&format=json",
+        lastfm_api_key,
+        url_escape::encode_component(artist),
+        url_escape::encode_component(title)
+    );
+
+    let data = match reqwest::blocking::get(request_url) {
+        Ok(res) => res.json::<serde_json::Value>().unwrap_or_default(),
+        Err(_) => serde_json::Value::Null,
+    };
+
+    match data["track"]["album"]["image"][3]["#text"].as_str() {
+        Some(url) if url.len() > 5 => {
+            println!("[last.fm] fetched image link (track): {}", url);
+            metrics::inc(&metrics::LASTFM_HITS);
+            save_cover_url(cache_enabled, album_cache, album_id, url);
+            url.to_string()
+        }
+        _ => {
+            metrics::inc(&metrics::LASTFM_MISSES);
+            String::from("missing-cover")
+        }
+    }
+}
+
+// Cover of release group containing given recording, singles first
+pub fn get_cover_url_musicbrainz_recording(
+    album_id: &str,
+    artist: &str,
+    title: &str,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+) -> String {
+    let user_agent = format!(
+        "music-discord-rpc/{} (patryk.kurdziel@protonmail.com)",
+        VERSION
+    );
+
+    let request_url = format!(
+        "https://musicbrainz.org/ws/2/recording/?query=recording:\"{}\"%20AND%20artist:\"{}\"&fmt=json&limit=5",
+        url_escape::encode_component(title),
+        url_escape::encode_component(artist)
+    );
+
+    let client = Client::new();
+    let data = match client
+        .get(request_url)
+        .header(USER_AGENT, &user_agent)
+        .send()
+    {
+        Ok(res) => res.json::<serde_json::Value>().unwrap_or_default(),
+        Err(_) => serde_json::Value::Null,
+    };
+
+    let mut release_groups: Vec<(bool, String)> = Vec::new();
+    for recording in data["recordings"].as_array().into_iter().flatten() {
+        let title_matches = recording["title"]
+            .as_str()
+            .is_some_and(|name| name.to_lowercase() == title.to_lowercase());
+        if !title_matches {
+            continue;
+        }
+        for release in recording["releases"].as_array().into_iter().flatten() {
+            let release_group = &release["release-group"];
+            let id = match release_group["id"].as_str() {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+            let is_single = release_group["primary-type"].as_str() == Some("Single");
+            if !release_groups.iter().any(|(_, group_id)| *group_id == id) {
+                release_groups.push((is_single, id));
+            }
+        }
+    }
+    release_groups.sort_by_key(|(is_single, _)| !is_single);
+
+    for (_, release_group_id) in release_groups.iter().take(3) {
+        let url = match client
+            .get(format!(
+                "https://coverartarchive.org/release-group/{}/",
+                release_group_id
+            ))
+            .header(USER_AGENT, &user_agent)
+            .send()
+        {
+            Ok(res) => match res.json::<serde_json::Value>() {
+                Ok(data) => data["images"][0]["thumbnails"]["small"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                Err(_) => String::new(),
+            },
+            Err(_) => String::new(),
+        };
+
+        if url.len() > 5 {
+            println!("[musicbrainz] fetched image link (recording): {}", url);
+            metrics::inc(&metrics::MUSICBRAINZ_HITS);
+            save_cover_url(cache_enabled, album_cache, album_id, &url);
+            return url;
+        }
+    }
+
+    metrics::inc(&metrics::MUSICBRAINZ_MISSES);
+    String::from("missing-cover")
+}
+
+// Find release group containing both album title and track title, used for compilations
+pub fn get_cover_url_musicbrainz_release_group(
+    album_id: &str,
+    album: &str,
+    title: &str,
+    cache_enabled: bool,
+    album_cache: &mut PickleDb,
+) -> String {
+    let user_agent = format!(
+        "music-discord-rpc/{} (patryk.kurdziel@protonmail.com)",
+        VERSION
+    );
+
+    let request_url = format!(
+        "https://musicbrainz.org/ws/2/recording/?query=recording:\"{}\"%20AND%20release:\"{}\"&fmt=json&limit=10",
+        url_escape::encode_component(title),
+        url_escape::encode_component(album)
+    );
+
+    let client = Client::new();
+    let data = match client
+        .get(request_url)
+        .header(USER_AGENT, &user_agent)
+        .send()
+    {
+        Ok(res) => res.json::<serde_json::Value>().unwrap_or_default(),
+        Err(_) => serde_json::Value::Null,
+    };
+
+    // Matching release groups, compilations first
+    let mut release_groups: Vec<(bool, String)> = Vec::new();
+    for recording in data["recordings"].as_array().into_iter().flatten() {
+        for release in recording["releases"].as_array().into_iter().flatten() {
+            let release_group = &release["release-group"];
+            let title_matches = release_group["title"]
+                .as_str()
+                .is_some_and(|name| name.to_lowercase() == album.to_lowercase());
+            let id = match release_group["id"].as_str() {
+                Some(id) if title_matches => id.to_string(),
+                _ => continue,
+            };
+            let is_compilation = release_group["secondary-types"]
+                .as_array()
+                .is_some_and(|types| types.iter().any(|t| t == "Compilation"));
+            if !release_groups.iter().any(|(_, group_id)| *group_id == id) {
+                release_groups.push((is_compilation, id));
+            }
+        }
+    }
+    release_groups.sort_by_key(|(is_compilation, _)| !is_compilation);
+
+    for (_, release_group_id) in release_groups.iter().take(3) {
+        let url = match client
+            .get(format!(
+                "https://coverartarchive.org/release-group/{}/",
+                release_group_id
+            ))
+            .header(USER_AGENT, &user_agent)
+            .send()
+        {
+            Ok(res) => match res.json::<serde_json::Value>() {
+                Ok(data) => data["images"][0]["thumbnails"]["small"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                Err(_) => String::new(),
+            },
+            Err(_) => String::new(),
+        };
+
+        if url.len() > 5 {
+            println!("[musicbrainz] fetched image link (release group): {}", url);
+            metrics::inc(&metrics::MUSICBRAINZ_HITS);
+            save_cover_url(cache_enabled, album_cache, album_id, &url);
+            return url;
+        }
+    }
+
+    metrics::inc(&metrics::MUSICBRAINZ_MISSES);
+    String::from("missing-cover")
+}
+
 pub fn get_lastfm_avatar(username: &str, lastfm_api_key: &str) -> String {
     let request_url = format!(
         "https://ws.audioscrobbler.com/2.0/?method=user.getinfo&api_key={}&user={}&format=json",
@@ -432,6 +862,125 @@ pub fn get_lastfm_avatar(username: &str, lastfm_api_key: &str) -> String {
     return String::new();
 }
 
+// Whether the user loved the track on Last.fm
+pub fn get_lastfm_loved(
+    artist: &str,
+    title: &str,
+    username: &str,
+    lastfm_api_key: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let request_url = format!(
+        "https://ws.audioscrobbler.com/2.0/?method=track.getInfo&api_key={}&artist={}&track={}&username={}&autocorrect=1&format=json",
+        lastfm_api_key,
+        url_escape::encode_component(artist),
+        url_escape::encode_component(title),
+        url_escape::encode_component(username)
+    );
+    let data = reqwest::blocking::get(request_url)?
+        .error_for_status()?
+        .json::<serde_json::Value>()?;
+    Ok(data["track"]["userloved"].as_str() == Some("1"))
+}
+
+// Used by "lastfm test" subcommand
+pub fn test_lastfm_api_key(lastfm_api_key: &str, is_compiled_in: bool, artist: &str, album: &str) {
+    if lastfm_api_key.is_empty() {
+        println!("\x1b[31mLast.fm API key is not set.\x1b[0m");
+        println!(
+            "Provide it with lastfm_api_key option in config file or --lastfm-api-key argument."
+        );
+        std::process::exit(1);
+    }
+
+    println!(
+        "Testing Last.fm API key ({}): {}...",
+        if is_compiled_in {
+            "compiled in"
+        } else {
+            "from config/arguments"
+        },
+        lastfm_api_key.chars().take(6).collect::<String>()
+    );
+
+    let request_url = format!(
+        "https://ws.audioscrobbler.com/2.0/?method=album.getinfo&api_key={}&artist={}&album={}&autocorrect=0&format=json",
+        lastfm_api_key,
+        url_escape::encode_component(artist),
+        url_escape::encode_component(album)
+    );
+
+    let start = std::time::Instant::now();
+    let res = match reqwest::blocking::get(request_url) {
+        Ok(res) => res,
+        Err(err) => {
+            println!("\x1b[31mCould not connect to Last.fm: {}\x1b[0m", err);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "HTTP status: {} ({} ms)",
+        res.status(),
+        start.elapsed().as_millis()
+    );
+
+    // Last.fm doesn't document rate limit headers, show them only if present
+    let rate_limit_headers: Vec<String> = res
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.as_str().contains("ratelimit"))
+        .map(|(name, value)| format!("{}: {}", name, value.to_str().unwrap_or_default()))
+        .collect();
+
+    let data = match res.json::<serde_json::Value>() {
+        Ok(data) => data,
+        Err(err) => {
+            println!("\x1b[31mInvalid response from Last.fm: {}\x1b[0m", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut key_valid = true;
+    if let Some(code) = data["error"].as_u64() {
+        let message = data["message"].as_str().unwrap_or_default();
+        match code {
+            10 | 26 => {
+                key_valid = false;
+                println!("\x1b[31mAPI key is not valid: {}\x1b[0m", message);
+            }
+            29 => println!("\x1b[31mRate limit exceeded: {}\x1b[0m", message),
+            _ => println!("\x1b[33mLast.fm error {}: {}\x1b[0m", code, message),
+        }
+    }
+
+    if key_valid {
+        println!("\x1b[32mAPI key is valid.\x1b[0m");
+    }
+
+    if rate_limit_headers.is_empty() {
+        println!(
+            "Rate limit: not reported by Last.fm (it's exceeded only when error 29 is returned)."
+        );
+    } else {
+        println!("Rate limit:");
+        for header in rate_limit_headers {
+            println!("  {}", header);
+        }
+    }
+
+    if key_valid {
+        let url = data["album"]["image"][3]["#text"]
+            .as_str()
+            .unwrap_or_default();
+        if url.len() > 5 {
+            println!("Cover for \"{} - {}\": {}", artist, album, url);
+        } else {
+            println!("Last.fm has no cover for \"{} - {}\".", artist, album);
+        }
+    }
+
+    std::process::exit(if key_valid { 0 } else { 1 });
+}
+
 pub fn sanitize_name(input: &str) -> String {
     input
         .to_lowercase()
@@ -464,6 +1013,7 @@ pub fn get_currently_playing(player: &Player, debug_log: bool) -> NowPlayingResu
         mpris::PlaybackStatus::Paused => false,
         mpris::PlaybackStatus::Stopped => false,
     };
+    let is_stopped = playback_status == mpris::PlaybackStatus::Stopped;
     debug_log!(debug_log, "playback_status: {:#?}", playback_status);
 
     // Parse metadata
@@ -509,34 +1059,58 @@ pub fn get_currently_playing(player: &Player, debug_log: bool) -> NowPlayingResu
         Err(_) => Duration::new(0, 0).as_secs(),
     };
 
-    let art_url = match metadata.art_url() {
+    let mut art_url = match metadata.art_url() {
         Some(url) => url.to_string(),
         _ => String::new(),
     };
-
-    let url = match metadata.url() {
-        Some(url) => {
-            let url_string = url.to_string();
-            if url_string.starts_with("http://") || url_string.starts_with("https://") {
-                url_string
-            } else {
-                String::new()
+    let mut art_data = ArtData::default();
+    if art_url.starts_with("data:") {
+        match decode_data_url(&art_url) {
+            Some(bytes) => {
+                debug_log!(
+                    debug_log,
+                    "Artwork embedded in artUrl: {} bytes",
+                    bytes.len()
+                );
+                art_data = ArtData(bytes);
             }
+            None => debug_log!(debug_log, "Could not decode artwork embedded in artUrl."),
         }
+        art_url = String::new();
+    }
+
+    let url = match metadata.url() {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => url.to_string(),
         _ => String::new(),
     };
 
+    let track_id = match metadata.track_id() {
+        Some(track_id) if track_id != TrackID::no_track() => track_id.as_str().to_string(),
+        _ => String::new(),
+    };
+
+    // Rating set by user, or calculated by player (e.g. from play count)
+    let rating = ["xesam:userRating", "xesam:autoRating"]
+        .iter()
+        .find_map(|key| metadata.get(key).and_then(|value| value.as_f64()))
+        .filter(|rating| *rating >= 0.0)
+        .map(|rating| rating.min(1.0));
+
     Ok(MediaInfo {
         title,
         artist,
         album_artist,
         album,
         is_playing,
+        is_stopped,
         duration,
         position,
         is_track_position,
         art_url,
+        art_data,
         url,
+        track_id,
+        rating,
     })
 }
 
@@ -577,6 +1151,13 @@ pub fn get_currently_playing() -> NowPlayingResult {
                 .unwrap_or("Unknown Player")
                 .to_string();
             let art_url = String::new(); // For now cant get artwork remote url like with mpris
+
+            // Artwork image is provided directly as base64, it's uploaded to cover_upload_url
+            let art_data = json_result["artworkData"]
+                .as_str()
+                .and_then(decode_base64)
+                .map(ArtData)
+                .unwrap_or_default();
             let is_track_position = true;
             let url = String::new();
 
@@ -586,11 +1167,15 @@ pub fn get_currently_playing() -> NowPlayingResult {
                 album_artist,
                 album,
                 is_playing,
+                is_stopped: false,
                 duration,
                 position,
                 is_track_position,
                 art_url,
+                art_data,
                 url,
+                track_id: String::new(),
+                rating: None,
                 player_id,
             })
         }
@@ -640,6 +1225,25 @@ pub fn app_name_from_bundle_id(bundle_id: &str) -> String {
     name.to_string()
 }
 
+// Track time as "2:31", used in "now playing" card and {elapsed}/{remaining} template values
+pub fn format_time(seconds: u64) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// Track rating as "★★★★☆" (stars) or "80%" (percent), empty if not rated
+pub fn format_rating(rating: Option<f64>, style: &str) -> String {
+    let Some(rating) = rating else {
+        return String::new();
+    };
+    match style {
+        "percent" => format!("{}%", (rating * 100.0).round()),
+        _ => {
+            let stars = (rating * 5.0).round() as usize;
+            format!("{}{}", "★".repeat(stars), "☆".repeat(5 - stars))
+        }
+    }
+}
+
 // Functions used to trim values to 256 bytes (Discord RPC limitation)
 pub fn trim_to_max_bytes(mut input: String, max: usize) -> String {
     if input.len() <= max {
@@ -665,6 +1269,119 @@ fn encoded_len_of_byte(b: u8) -> usize {
     }
 }
 
+// Query parameters used only for tracking, they make image links longer without changing the image
+const TRACKING_PARAMS: [&str; 7] = [
+    "fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "si", "ref_src",
+];
+
+// Percent-encode characters not allowed in URL, already encoded "%xx" sequences are kept
+fn encode_url_part(part: &str) -> String {
+    let bytes = part.as_bytes();
+    let mut result = String::new();
+    for (i, c) in part.char_indices() {
+        let is_encoded = c == '%'
+            && bytes.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+            && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit);
+        if is_encoded || (c.is_ascii_alphanumeric() || "-._~:/?[]@!$&'()*+,;=".contains(c)) {
+            result.push(c);
+        } else {
+            let mut buffer = [0u8; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                result.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    result
+}
+
+// Discord silently ignores images with links that are not https, contain spaces or are too long.
+// Returns link upgraded to https, encoded and without tracking parameters, or None if unusable.
+pub fn sanitize_image_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    if path.is_empty() {
+        return None;
+    }
+
+    let query: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or_default();
+            !param.is_empty() && !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&name)
+        })
+        .collect();
+
+    let mut sanitized = format!("https://{}", encode_url_part(path));
+    if !query.is_empty() {
+        sanitized.push('?');
+        sanitized.push_str(&encode_url_part(&query.join("&")));
+    }
+    if sanitized.len() > MAX_URL_LEN {
+        return None;
+    }
+    Some(sanitized)
+}
+
+// Activity image is a link or name of an app asset, only links are changed
+pub fn sanitize_activity_image(image: String, debug_log: bool) -> String {
+    if !image.starts_with("http://") && !image.starts_with("https://") {
+        return image;
+    }
+    match sanitize_image_url(&image) {
+        Some(url) => {
+            if url != image {
+                debug_log!(debug_log, "Image link sanitized: {}", url);
+            }
+            url
+        }
+        None => {
+            debug_log!(debug_log, "Image link can't be used by Discord: {}", image);
+            String::from("missing-cover")
+        }
+    }
+}
+
+// Spotify MPRIS url "https://open.spotify.com/track/<id>" or "spotify:track:<id>" => track link
+pub fn get_spotify_track_url(url: &str) -> Option<String> {
+    let id = url
+        .strip_prefix("https://open.spotify.com/track/")
+        .or_else(|| url.strip_prefix("spotify:track:"))?
+        .split(['?', '/'])
+        .next()?;
+
+    // Ads and podcast episodes don't have track id
+    if id.len() != 22 || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(format!("https://open.spotify.com/track/{}", id))
+}
+
+// Channel name if url is a Twitch or YouTube live stream (no duration), empty if unknown
+pub fn get_live_stream_channel(media_info: &MediaInfo) -> Option<String> {
+    if media_info.duration > 0 {
+        return None;
+    }
+
+    let url = media_info.url.as_str();
+    let twitch_channel = url
+        .split_once("twitch.tv/")
+        .and_then(|(_, path)| path.split(['/', '?', '#']).next())
+        .filter(|channel| !channel.is_empty() && *channel != "videos");
+    let is_youtube = url.contains("youtube.com/") || url.contains("youtu.be/");
+    if twitch_channel.is_none() && !is_youtube {
+        return None;
+    }
+
+    if !media_info.artist.is_empty() && media_info.artist != "Unknown Artist" {
+        return Some(media_info.artist.clone());
+    }
+    Some(twitch_channel.unwrap_or_default().to_string())
+}
+
 pub fn build_trimmed_url(prefix: &str, component: &str) -> String {
     let encoded = url_escape::encode_component(component);
     let full_url = format!("{prefix}{encoded}");
@@ -705,77 +1422,305 @@ pub fn build_trimmed_url(prefix: &str, component: &str) -> String {
     format!("{prefix}{encoded}")
 }
 
+// Timeout of player calls on buses given by address, they may be on another machine
+#[cfg(target_os = "linux")]
+const REMOTE_PLAYER_TIMEOUT_MS: i32 = 2000;
+
+// Session bus started by systemd
+#[cfg(target_os = "linux")]
+fn runtime_dir_player_finder() -> Option<PlayerFinder> {
+    let path = PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?).join("bus");
+    let connection =
+        dbus::ffidisp::Connection::open_private(&format!("unix:path={}", path.display())).ok()?;
+    connection.register().ok()?;
+    Some(PlayerFinder::for_connection(connection))
+}
+
+// Session bus by default, "system" for system bus or any D-Bus address, e.g. "unix:path=/run/user/1001/bus"
+// or "tcp:host=192.168.1.10,port=55556"
+#[cfg(target_os = "linux")]
+pub fn new_player_finder(dbus_address: Option<&str>) -> Result<PlayerFinder, mpris::DBusError> {
+    match dbus_address.map(str::trim) {
+        // DBUS_SESSION_BUS_ADDRESS is not updated when the session bus is restarted on another
+        // address, so the bus started by systemd in the runtime directory is tried too
+        None | Some("") | Some("session") => {
+            PlayerFinder::new().or_else(|err| runtime_dir_player_finder().ok_or(err))
+        }
+        Some("system") => Ok(PlayerFinder::for_connection(
+            dbus::ffidisp::Connection::new_system()?,
+        )),
+        Some(address) => {
+            let connection = dbus::ffidisp::Connection::open_private(address)?;
+            connection.register()?;
+            let mut finder = PlayerFinder::for_connection(connection);
+            finder.set_player_timeout_ms(REMOTE_PLAYER_TIMEOUT_MS);
+            Ok(finder)
+        }
+    }
+}
+
+// Desktop entry and executable names of players, by D-Bus unique name of the player connection
 #[cfg(target_os = "linux")]
+static PLAYER_NAMES: Mutex<Vec<(String, Vec<String>)>> = Mutex::new(Vec::new());
+#[cfg(target_os = "linux")]
+const MAX_CACHED_PLAYER_NAMES: usize = 50;
+
+// Executable name of the process owning the bus connection, only on local buses
+#[cfg(target_os = "linux")]
+fn get_bus_owner_executable(unique_name: &str, dbus_address: Option<&str>) -> Option<String> {
+    let connection = match dbus_address.map(str::trim) {
+        None | Some("") | Some("session") => dbus::blocking::Connection::new_session().ok()?,
+        Some("system") => dbus::blocking::Connection::new_system().ok()?,
+        Some(_) => return None,
+    };
+    let proxy = connection.with_proxy(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        Duration::from_millis(500),
+    );
+    let (pid,): (u32,) = proxy
+        .method_call(
+            "org.freedesktop.DBus",
+            "GetConnectionUnixProcessID",
+            (unique_name,),
+        )
+        .ok()?;
+
+    // Executable of processes of other users can't be read, but their name can
+    match fs::read_link(format!("/proc/{}/exe", pid)) {
+        Ok(exe) => Some(exe.file_name()?.to_string_lossy().into_owned()),
+        Err(_) => Some(
+            fs::read_to_string(format!("/proc/{}/comm", pid))
+                .ok()?
+                .trim()
+                .to_string(),
+        ),
+    }
+}
+
+// Forget cached names, e.g. when D-Bus was restarted and unique names belong to other players
+#[cfg(target_os = "linux")]
+pub fn clear_player_names() {
+    PLAYER_NAMES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clear();
+}
+
+// Names used to match the player with allowlist and video_players entries: identity, bus name, MPRIS desktop entry
+// and executable name, e.g. "Музыка Яндекс", "org.mpris.MediaPlayer2.yandexmusic", "yandex-music".
+// Identity can change between versions and locales, desktop entry and executable are more stable.
+#[cfg(target_os = "linux")]
+pub fn get_player_names(player: &Player, dbus_address: Option<&str>) -> Vec<String> {
+    let mut names = vec![player.identity().to_string(), player.bus_name().to_string()];
+
+    // They don't change while the player is connected, so D-Bus is asked only once
+    let mut cache = PLAYER_NAMES.lock().unwrap_or_else(|err| err.into_inner());
+    let extra_names = match cache.iter().find(|(name, _)| name == player.unique_name()) {
+        Some((_, extra_names)) => extra_names.clone(),
+        None => {
+            let extra_names: Vec<String> = [
+                player.get_desktop_entry().ok().flatten(),
+                get_bus_owner_executable(player.unique_name(), dbus_address),
+            ]
+            .into_iter()
+            .flatten()
+            .filter(|name| !name.is_empty())
+            .collect();
+            if cache.len() >= MAX_CACHED_PLAYER_NAMES {
+                cache.clear();
+            }
+            cache.push((player.unique_name().to_string(), extra_names.clone()));
+            extra_names
+        }
+    };
+
+    for name in extra_names {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Lower is better: playing > paused > stopped, stopped players are equal to paused ones if they
+// are displayed as paused
+#[cfg(target_os = "linux")]
+pub fn get_playback_priority(status: mpris::PlaybackStatus, stopped_as_paused: bool) -> u8 {
+    match status {
+        mpris::PlaybackStatus::Playing => 0,
+        mpris::PlaybackStatus::Paused => 1,
+        mpris::PlaybackStatus::Stopped if stopped_as_paused => 1,
+        mpris::PlaybackStatus::Stopped => 2,
+    }
+}
+
+// Like PlayerFinder::find_active (playing > paused > has metadata), but the selected player is kept
+// when another one has the same status. Instances of the same player (e.g. two mpv windows) are
+// listed by D-Bus in varying order, so the first one would change between refreshes.
+#[cfg(target_os = "linux")]
+pub fn active_player_finder(
+    player: &PlayerFinder,
+    selected: Option<&str>,
+) -> Result<Player, FindingError> {
+    player
+        .find_all()?
+        .into_iter()
+        .min_by_key(|p| {
+            let status_priority = match p.get_playback_status() {
+                Ok(mpris::PlaybackStatus::Playing) => 0,
+                Ok(mpris::PlaybackStatus::Paused) => 1,
+                _ => match p.get_metadata() {
+                    Ok(metadata) if !metadata.is_empty() => 2,
+                    _ => 3,
+                },
+            };
+            (status_priority, Some(p.bus_name()) != selected)
+        })
+        .ok_or(FindingError::NoPlayerFound)
+}
+
+#[cfg(target_os = "linux")]
+#[allow(clippy::too_many_arguments)]
 pub fn allowlist_player_finder(
     player: &PlayerFinder,
     allowlist: &Vec<String>,
+    priorities: &HashMap<String, i64>,
+    sort_by_priority: bool,
+    dbus_address: Option<&str>,
+    stopped_as_paused: bool,
+    selected: Option<&str>,
     debug_log: bool,
 ) -> Result<Player, FindingError> {
     let mut allowlist_finder = Err(mpris::FindingError::NoPlayerFound);
 
     // Find all players and sort them by allow list order then return the first one
-    if let Ok(all_players) = player.find_all() {
-        let mut found_players: Vec<_> = all_players
-            .into_iter()
-            .filter(|p| {
-                !p.bus_name().eq("org.mpris.MediaPlayer2.playerctld")
-                    && allowlist.contains(&p.identity().to_string())
-            })
-            .collect();
-
-        if !found_players.is_empty() {
-            debug_log!(debug_log, "Allowlist sorting:");
-            // Allowlist sorting priority: playing > has metadata > allowlist order
-            found_players.sort_by_key(|p| {
-                // Check if player is currently playing
-                let is_playing = p
-                    .get_playback_status()
-                    .unwrap_or(mpris::PlaybackStatus::Stopped)
-                    == mpris::PlaybackStatus::Playing;
-
-                // Check if metadata is complete (artist, title, and album)
-                let mut is_metadata_complete = false;
-                if let Ok(m) = p.get_metadata() {
-                    let has_artist = match m.artists() {
-                        Some(a) => !a.is_empty(),
-                        None => false,
-                    };
-                    let has_title = match m.title() {
-                        Some(t) => !t.is_empty(),
-                        None => false,
-                    };
-                    let has_album = match m.album_name() {
-                        Some(a) => !a.is_empty(),
-                        None => false,
-                    };
-                    is_metadata_complete = has_artist && has_title && has_album;
-                }
+    // D-Bus errors are returned, so the caller can open a new connection
+    let all_players = player.find_all()?;
+    let mut found_players: Vec<_> = all_players
+        .into_iter()
+        .filter(|p| !p.bus_name().eq("org.mpris.MediaPlayer2.playerctld"))
+        .filter_map(|p| {
+            let names = get_player_names(&p, dbus_address);
+            let rank = player_match::rank(allowlist, priorities, &names)?;
+            Some((p, rank))
+        })
+        .collect();
+
+    if !found_players.is_empty() {
+        debug_log!(debug_log, "Allowlist sorting:");
+        // Allowlist sorting priority: playback status > has metadata > entry priority > allowlist
+        // order > already selected, or entry priority first with sort_by_priority. The selected
+        // player wins only ties, e.g. with another instance of the same player.
+        // Keys are cached, so every player is asked over D-Bus only once.
+        found_players.sort_by_cached_key(|(p, (entry_priority, allowlist_position))| {
+            let playback_priority = get_playback_priority(
+                p.get_playback_status()
+                    .unwrap_or(mpris::PlaybackStatus::Stopped),
+                stopped_as_paused,
+            );
 
-                debug_log!(
-                    debug_log,
-                    " - {}, playing: {}, metadata: {}",
-                    p.identity(),
-                    is_playing,
-                    is_metadata_complete
-                );
+            // Check if metadata is complete (artist, title, and album)
+            let mut is_metadata_complete = false;
+            if let Ok(m) = p.get_metadata() {
+                let has_artist = match m.artists() {
+                    Some(a) => !a.is_empty(),
+                    None => false,
+                };
+                let has_title = match m.title() {
+                    Some(t) => !t.is_empty(),
+                    None => false,
+                };
+                let has_album = match m.album_name() {
+                    Some(a) => !a.is_empty(),
+                    None => false,
+                };
+                is_metadata_complete = has_artist && has_title && has_album;
+            }
 
-                (
-                    !is_playing,
-                    !is_metadata_complete,
-                    allowlist
-                        .iter()
-                        .position(|allowlisted_name| allowlisted_name == p.identity())
-                        .unwrap_or(usize::MAX),
-                )
-            });
+            let is_selected = Some(p.bus_name()) == selected;
 
             debug_log!(
                 debug_log,
-                "Selected player: {}",
-                found_players[0].identity()
+                " - {} ({}), playback priority: {}, metadata: {}, entry priority: {}",
+                p.identity(),
+                p.bus_name(),
+                playback_priority,
+                is_metadata_complete,
+                -entry_priority
             );
-            allowlist_finder = Ok(found_players.remove(0));
-        }
+
+            match sort_by_priority {
+                true => (
+                    *entry_priority,
+                    playback_priority,
+                    !is_metadata_complete,
+                    0,
+                    *allowlist_position,
+                    !is_selected,
+                ),
+                false => (
+                    0,
+                    playback_priority,
+                    !is_metadata_complete,
+                    *entry_priority,
+                    *allowlist_position,
+                    !is_selected,
+                ),
+            }
+        });
+
+        debug_log!(
+            debug_log,
+            "Selected player: {} ({})",
+            found_players[0].0.identity(),
+            found_players[0].0.bus_name()
+        );
+        allowlist_finder = Ok(found_players.remove(0).0);
     }
     allowlist_finder
 }
+
+// Pause playing allowlisted players ranked lower than the selected one (exclusive_playback), so
+// only the player shown in the activity is heard
+#[cfg(target_os = "linux")]
+pub fn pause_lower_players(
+    finder: &PlayerFinder,
+    selected_names: &[String],
+    allowlist: &[String],
+    priorities: &HashMap<String, i64>,
+    dbus_address: Option<&str>,
+    debug_log: bool,
+) -> Result<(), FindingError> {
+    let Some(selected_rank) = player_match::rank(allowlist, priorities, selected_names) else {
+        return Ok(());
+    };
+    for player in finder.find_all()? {
+        // Selected player and playerctld, which proxies one of the players
+        if selected_names.iter().any(|name| name == player.bus_name())
+            || player.bus_name() == "org.mpris.MediaPlayer2.playerctld"
+        {
+            continue;
+        }
+        let names = get_player_names(&player, dbus_address);
+        let is_lower = player_match::rank(allowlist, priorities, &names)
+            .is_some_and(|rank| rank > selected_rank);
+        if !is_lower || player.get_playback_status().ok() != Some(mpris::PlaybackStatus::Playing) {
+            continue;
+        }
+        match player.pause() {
+            Ok(_) => debug_log!(
+                debug_log,
+                "[exclusive playback] paused: {}",
+                player.identity()
+            ),
+            Err(err) => debug_log!(
+                debug_log,
+                "[exclusive playback] could not pause {}: {}",
+                player.identity(),
+                err
+            ),
+        }
+    }
+    Ok(())
+}
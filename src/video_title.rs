@@ -0,0 +1,91 @@
+// Video files without title tag are shown with the file name as the title, e.g.
+// "Some.Movie.2019.1080p.WEB-DL.x264-GROUP.mkv" is shown as "Some Movie 2019"
+
+const EXTENSIONS: [&str; 14] = [
+    "mkv", "mp4", "avi", "webm", "mov", "m4v", "wmv", "flv", "ts", "m2ts", "mpg", "mpeg", "ogv",
+    "3gp",
+];
+
+// Quality, source and codec tags of release names, they come after the name, so everything from
+// the first one is removed. Words that can also be a part of the title (e.g. "web") are not here.
+const RELEASE_TAGS: [&str; 31] = [
+    "4k", "uhd", "hdr", "hdr10", "10bit", "8bit", "webdl", "web-dl", "webrip", "bluray", "blu-ray",
+    "bdrip", "brrip", "dvdrip", "hdtv", "hdrip", "remux", "x264", "x265", "h264", "h265", "hevc",
+    "xvid", "divx", "aac", "ac3", "eac3", "dts", "ddp5", "atmos", "repack",
+];
+
+// Resolution like 1080p, 720p or 1080i
+fn is_resolution(word: &str) -> bool {
+    match word.strip_suffix('p').or(word.strip_suffix('i')) {
+        Some(number) => {
+            (3..=4).contains(&number.len()) && number.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+fn is_release_tag(word: &str, next: Option<&str>) -> bool {
+    let word = word.trim_matches(|c| c == '(' || c == ')').to_lowercase();
+    // "x264-GROUP", the release group is after the dash
+    let tag = word.split('-').next().unwrap_or_default();
+    // "H.264" is split into "H" and "264"
+    let is_split_codec = matches!(word.as_str(), "h" | "x")
+        && next.is_some_and(|next| next == "264" || next == "265");
+    RELEASE_TAGS.contains(&word.as_str())
+        || RELEASE_TAGS.contains(&tag)
+        || is_resolution(tag)
+        || is_split_codec
+}
+
+// Parts in [square brackets] are release groups and checksums, e.g. "[Group] Show - 05 [1A2B3C4D]"
+fn remove_brackets(text: &str) -> String {
+    let mut result = String::new();
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+    result
+}
+
+// Readable title if it looks like a file name: has a video file extension or words are separated
+// with dots or underscores instead of spaces. None if the title should be used as it is.
+pub fn clean_file_name(title: &str) -> Option<String> {
+    let (name, has_extension) = match title.rsplit_once('.') {
+        Some((name, extension))
+            if EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension)) =>
+        {
+            (name, true)
+        }
+        _ => (title, false),
+    };
+    let has_separators = !name.contains(' ') && name.contains(['.', '_']);
+    if !has_extension && !has_separators {
+        return None;
+    }
+
+    let name = remove_brackets(name);
+    let words: Vec<&str> = name
+        .split(|c: char| c.is_whitespace() || c == '.' || c == '_')
+        .filter(|word| !word.is_empty())
+        .collect();
+    // The first word is kept, so the title is never empty
+    let end = (1..words.len())
+        .find(|&index| is_release_tag(words[index], words.get(index + 1).copied()))
+        .unwrap_or(words.len());
+    let cleaned = words[..end]
+        .join(" ")
+        .trim_matches(|c: char| c == '-' || c.is_whitespace())
+        .to_string();
+
+    match cleaned.is_empty() || cleaned == title {
+        true => None,
+        false => Some(cleaned),
+    }
+}
@@ -0,0 +1,219 @@
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::control::PlayerCommand;
+use crate::debug_log;
+use crate::utils::{ArtData, MediaInfo};
+
+// Client for VLC web interface (Lua HTTP), used when VLC MPRIS support is missing or broken
+#[derive(Debug, Clone)]
+pub struct VlcHttp {
+    pub url: String,
+    password: String,
+    client: Client,
+    // Last artwork_url and its image, so it's downloaded only when the item changes
+    artwork: Arc<Mutex<Option<(String, ArtData)>>>,
+}
+
+impl VlcHttp {
+    pub fn new(url: &str, password: &str) -> VlcHttp {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(2))
+            .build()
+            .unwrap_or_default();
+
+        VlcHttp {
+            url: url.trim_end_matches('/').to_string(),
+            password: password.to_string(),
+            client,
+            artwork: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn get_status(&self) -> Result<Value, Box<dyn std::error::Error>> {
+        let res = self
+            .client
+            .get(format!("{}/requests/status.json", self.url))
+            .basic_auth("", Some(&self.password))
+            .send()?;
+
+        if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err("VLC HTTP interface rejected the password.".into());
+        }
+
+        Ok(res.error_for_status()?.json::<Value>()?)
+    }
+
+    // Artwork of the current item, served by VLC from local files or embedded tags
+    fn download_artwork(&self) -> Option<Vec<u8>> {
+        let res = self
+            .client
+            .get(format!("{}/art", self.url))
+            .basic_auth("", Some(&self.password))
+            .send()
+            .ok()?
+            .error_for_status()
+            .ok()?;
+
+        match res.bytes() {
+            Ok(bytes) if !bytes.is_empty() => Some(bytes.to_vec()),
+            _ => None,
+        }
+    }
+
+    // Image is downloaded again only when artwork_url changes, not on every refresh
+    fn get_artwork(&self, artwork_url: &str, debug_log: bool) -> ArtData {
+        let mut artwork = self.artwork.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some((url, art_data)) = artwork.as_ref() {
+            if url == artwork_url {
+                return art_data.clone();
+            }
+        }
+        let art_data = match self.download_artwork() {
+            Some(bytes) => {
+                debug_log!(debug_log, "VLC artwork: {} bytes", bytes.len());
+                ArtData(bytes)
+            }
+            None => ArtData::default(),
+        };
+        *artwork = Some((artwork_url.to_string(), art_data.clone()));
+        art_data
+    }
+
+    pub fn is_available(&self) -> bool {
+        match self.get_status() {
+            Ok(status) => status["state"].as_str().unwrap_or("stopped") != "stopped",
+            Err(_) => false,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self.get_status() {
+            Ok(status) => status["state"].as_str() == Some("playing"),
+            Err(_) => false,
+        }
+    }
+
+    pub fn send_command(&self, command: PlayerCommand) -> Result<(), Box<dyn std::error::Error>> {
+        let command = match command {
+            PlayerCommand::Play => "pl_forceresume",
+            PlayerCommand::Pause => "pl_forcepause",
+            PlayerCommand::PlayPause => "pl_pause",
+            PlayerCommand::Next => "pl_next",
+            PlayerCommand::Previous => "pl_previous",
+        };
+        let res = self
+            .client
+            .get(format!(
+                "{}/requests/status.json?command={}",
+                self.url, command
+            ))
+            .basic_auth("", Some(&self.password))
+            .send()?;
+
+        if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err("VLC HTTP interface rejected the password.".into());
+        }
+        res.error_for_status()?;
+        Ok(())
+    }
+
+    pub fn get_currently_playing(
+        &self,
+        debug_log: bool,
+    ) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+        let status = match self.get_status() {
+            Ok(status) => status,
+            Err(err) => {
+                return Err(
+                    format!("Could not get status from VLC at {}: {}", self.url, err).into(),
+                )
+            }
+        };
+
+        let state = status["state"].as_str().unwrap_or("stopped");
+        if state == "stopped" {
+            return Err("VLC is stopped, nothing is playing.".into());
+        }
+
+        let meta = &status["information"]["category"]["meta"];
+        debug_log!(debug_log, "VLC meta: {:#?}", meta);
+
+        // VLC meta keys differ between versions and file formats
+        let tag = |keys: &[&str]| -> String {
+            match meta.as_object() {
+                Some(tags) => tags
+                    .iter()
+                    .find(|(name, _)| keys.iter().any(|key| name.eq_ignore_ascii_case(key)))
+                    .and_then(|(_, value)| value.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                None => String::new(),
+            }
+        };
+
+        let mut title = tag(&["title"]);
+        if title.is_empty() {
+            title = tag(&["now_playing"]);
+        }
+        if title.is_empty() {
+            title = tag(&["filename"]);
+        }
+        if title.is_empty() {
+            title = "Unknown Title".to_string();
+        }
+        let mut artist = tag(&["artist"]);
+        if artist.is_empty() {
+            artist = "Unknown Artist".to_string();
+        }
+        let mut album = tag(&["album"]);
+        if album.is_empty() {
+            album = "Unknown Album".to_string();
+        }
+        let mut album_artist = tag(&["album_artist", "albumartist", "album artist"]);
+        if album_artist.is_empty() {
+            album_artist = artist.clone();
+        }
+
+        let duration = status["length"].as_u64().unwrap_or(0);
+        let position = status["time"].as_u64();
+
+        let mut art_url = String::new();
+        let mut art_data = ArtData::default();
+        let artwork_url = tag(&["artwork_url"]);
+        if artwork_url.starts_with("http://") || artwork_url.starts_with("https://") {
+            art_url = artwork_url;
+        } else if !artwork_url.is_empty() {
+            // Local artwork is only reachable through the VLC art endpoint
+            art_data = self.get_artwork(&artwork_url, debug_log);
+        }
+
+        let stream_url = tag(&["url"]);
+        let url = if stream_url.starts_with("http://") || stream_url.starts_with("https://") {
+            stream_url
+        } else {
+            String::new()
+        };
+
+        Ok(MediaInfo {
+            title,
+            artist,
+            album_artist,
+            album,
+            is_playing: state == "playing",
+            is_stopped: state == "stopped",
+            duration,
+            position: position.unwrap_or(0),
+            is_track_position: position.is_some(),
+            art_url,
+            art_data,
+            url,
+            track_id: String::new(),
+            rating: None,
+            #[cfg(target_os = "macos")]
+            player_id: "VLC media player".to_string(),
+        })
+    }
+}
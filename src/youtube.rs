@@ -0,0 +1,59 @@
+use reqwest::blocking::Client;
+use std::time::Duration;
+
+use crate::debug_log;
+
+// Max number of remembered thumbnail links
+pub const MAX_CACHED_THUMBNAILS: usize = 100;
+
+// Video id from thumbnail link, e.g. https://i.ytimg.com/vi/<id>/hqdefault.jpg
+fn get_video_id(url: &str) -> Option<&str> {
+    let (_, path) = url.split_once("ytimg.com/")?;
+    let mut segments = path.split('/');
+    match segments.next()? {
+        "vi" | "vi_webp" => segments.next().filter(|id| !id.is_empty()),
+        _ => None,
+    }
+}
+
+// YouTube returns 404 for thumbnails not generated for the video
+fn thumbnail_exists(client: &Client, url: &str) -> bool {
+    client
+        .head(url)
+        .send()
+        .is_ok_and(|res| res.status().is_success())
+}
+
+// Only thumbnails of YouTube videos are replaced, quality "original" keeps them unchanged
+pub fn can_replace_thumbnail(art_url: &str, quality: &str) -> bool {
+    quality != "original" && get_video_id(art_url).is_some()
+}
+
+// Replace low quality YouTube thumbnail (4:3 with black bars) with a 16:9 one without bars.
+// Quality: "max" (maxresdefault, then hq720), "high" (hq720) or "original" (no change).
+pub fn get_thumbnail_url(art_url: &str, quality: &str, debug_log: bool) -> String {
+    let video_id = match get_video_id(art_url) {
+        Some(video_id) if quality != "original" => video_id,
+        _ => return art_url.to_string(),
+    };
+
+    let mut candidates = Vec::new();
+    if quality == "max" {
+        candidates.push(format!(
+            "https://i.ytimg.com/vi/{}/maxresdefault.jpg",
+            video_id
+        ));
+    }
+    candidates.push(format!("https://i.ytimg.com/vi/{}/hq720.jpg", video_id));
+
+    let client = match Client::builder().timeout(Duration::from_secs(5)).build() {
+        Ok(client) => client,
+        Err(_) => return art_url.to_string(),
+    };
+    let url = candidates
+        .into_iter()
+        .find(|url| thumbnail_exists(&client, url))
+        .unwrap_or_else(|| art_url.to_string());
+    debug_log!(debug_log, "YouTube thumbnail: {} => {}", art_url, url);
+    url
+}